@@ -1,3 +1,3 @@
 fn main() {
     println!(r"cargo:rustc-link-search=/opt/homebrew/Cellar/duckdb/0.8.1/lib/");
-}
\ No newline at end of file
+}
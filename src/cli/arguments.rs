@@ -1,38 +1,269 @@
 use anyhow::anyhow;
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
 use clap::{Parser, Subcommand, ValueEnum};
 
+use crate::output::{CsvOptions, JsonOptions, ParquetCompression};
+use common::cache::Cache;
+use common::http::{configure_http, FilterOp, Http, PriceSetting, SortDir};
+use common::http_config::HttpConfig;
 use std::fmt::{Debug, Formatter};
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
+use tracing::level_filters::LevelFilter;
 
-#[derive(ValueEnum, Clone)]
+#[derive(ValueEnum, Clone, Default)]
 pub enum OutputFormat {
-    JSON,
+    /// One JSON object per line. Nulls are written out unless --skip-nulls and
+    /// timestamps look like `2020-09-13 12:26:40`, with or without --arrow2
+    #[default]
+    Ndjson,
+    /// A single pretty printed JSON array of objects, shaped like ndjson
+    Json,
     Parquet,
-}
-impl Default for OutputFormat {
-    fn default() -> Self {
-        OutputFormat::JSON
-    }
+    Csv,
+    /// Arrow IPC file, also known as Feather v2
+    Arrow,
+    /// Avro object container file, requires --arrow2
+    Avro,
 }
 #[derive(Parser)]
 pub struct OutputFormatArgs {
     #[arg(long, required = false)]
     pub output_format: Option<OutputFormat>,
+
+    /// Single byte CSV field delimiter, e.g. ';'. Use '\t' for tabs
+    #[arg(long, required = false, default_value = ",", value_parser = parse_single_byte)]
+    pub csv_delimiter: u8,
+
+    /// Single byte CSV quote character
+    #[arg(long, required = false, default_value = "\"", value_parser = parse_single_byte)]
+    pub csv_quote: u8,
+
+    /// Do not write the CSV header row
+    #[arg(long, required = false, default_value_t = false)]
+    pub csv_no_header: bool,
+
+    /// Leave out null fields of JSON rows instead of writing them as null
+    #[arg(long, required = false, default_value_t = false)]
+    pub skip_nulls: bool,
+
+    /// Compression codec for Parquet output
+    #[arg(long, required = false, value_enum, default_value_t)]
+    pub compression: ParquetCompression,
+
+    /// Rows per Parquet row group, the last one may be smaller. Defaults to the
+    /// parquet writer's 1048576
+    #[arg(long, required = false, value_name = "rows")]
+    pub row_group_size: Option<usize>,
+
+    /// Also write the arrow schema of the output as JSON to this path, showing
+    /// the types chosen for each field
+    #[arg(long, required = false, value_name = "path")]
+    pub schema_out: Option<PathBuf>,
+}
+
+impl OutputFormatArgs {
+    pub fn json_options(&self) -> JsonOptions {
+        JsonOptions {
+            skip_nulls: self.skip_nulls,
+        }
+    }
+
+    pub fn csv_options(&self) -> CsvOptions {
+        CsvOptions {
+            delimiter: self.csv_delimiter,
+            quote: self.csv_quote,
+            header: !self.csv_no_header,
+        }
+    }
+}
+
+fn parse_single_byte(s: &str) -> anyhow::Result<u8> {
+    match s.as_bytes() {
+        [b] => Ok(*b),
+        b"\\t" => Ok(b'\t'),
+        _ => Err(anyhow!("expected a single byte character, got '{}'", s)),
+    }
 }
 #[derive(Parser)]
 pub struct Common {
-    #[arg(long, required = true)]
-    pub conf: String,
+    /// Shop configuration. Defaults to $XDG_CONFIG_HOME/ps17-cli/config.toml
+    /// or ~/.config/ps17-cli/config.toml
+    #[arg(long, required = false)]
+    pub conf: Option<PathBuf>,
+
+    /// Shop to use when the config has several [profiles.<name>] tables
+    #[arg(long, required = false)]
+    pub profile: Option<String>,
 
+    /// Write to this file instead of stdout. '{resource}' and '{ext}' are replaced
+    /// by the resource and the output format's extension, parent directories are created.
+    /// The file is written as '<path>.partial' and only renamed once complete
     #[arg(long, required = false)]
     pub output_path: Option<PathBuf>,
+
+    /// Write to stdout as well as to --output-path, e.g. to save the output of a pipeline
+    #[arg(
+        long,
+        required = false,
+        default_value_t = false,
+        requires = "output_path"
+    )]
+    pub tee: bool,
+
+    /// Indent JSON documents, e.g. the output of get-schema. Has no effect on
+    /// the records written by get
+    #[arg(long, required = false, default_value_t = false)]
+    pub pretty: bool,
+
+    /// Append the method, URL, status, timing and size of every request to this
+    /// file as JSON lines
+    #[arg(long, required = false, value_name = "path")]
+    pub http_log: Option<PathBuf>,
+
+    /// Seconds to wait for a whole request, overrides request_timeout_secs of the config
+    #[arg(long, required = false, value_name = "n")]
+    pub timeout_secs: Option<u64>,
+
+    /// Retries of a GET on 429 and 5xx gateway errors, overrides max_retries of the config
+    #[arg(long, required = false, value_name = "n")]
+    pub retries: Option<u32>,
+
+    /// Requests per second at most, overrides max_rps of the config
+    #[arg(long, required = false, value_name = "n")]
+    pub max_rps: Option<f64>,
 }
+impl Common {
+    pub fn configure_http(&self) -> anyhow::Result<Http> {
+        let http = configure_http(self.conf_path()?, self.profile.as_deref(), |config| {
+            self.override_config(config)
+        })?;
+        match &self.http_log {
+            Some(path) => http.with_http_log(path),
+            None => Ok(http),
+        }
+    }
+    /// Replaces the config values given on the command line
+    fn override_config(&self, config: &mut HttpConfig) {
+        if let Some(secs) = self.timeout_secs {
+            config.request_timeout_secs = Some(secs);
+        }
+        if let Some(retries) = self.retries {
+            config.max_retries = Some(retries);
+        }
+        if let Some(max_rps) = self.max_rps {
+            config.max_rps = Some(max_rps);
+        }
+    }
+    pub fn conf_path(&self) -> anyhow::Result<PathBuf> {
+        if let Some(conf) = &self.conf {
+            return Ok(conf.clone());
+        }
+        let path = default_conf_path(
+            std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+            std::env::var_os("HOME").map(PathBuf::from),
+        )
+        .ok_or_else(|| anyhow!("--conf is required, neither XDG_CONFIG_HOME nor HOME is set"))?;
+        if !path.exists() {
+            return Err(anyhow!(
+                "--conf is required, the default {} does not exist",
+                path.display()
+            ));
+        }
+        Ok(path)
+    }
+}
+
+fn default_conf_path(xdg_config_home: Option<PathBuf>, home: Option<PathBuf>) -> Option<PathBuf> {
+    let config_dir = xdg_config_home
+        .filter(|p| p.is_absolute())
+        .or_else(|| home.map(|h| h.join(".config")))?;
+    Some(config_dir.join("ps17-cli").join("config.toml"))
+}
+
+#[derive(Parser)]
+pub struct CacheArgs {
+    /// Directory where fetched metadata (e.g. the language mapping) is cached between runs
+    #[arg(long, required = false)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// How long cached entries stay valid, in seconds
+    #[arg(long, required = false, default_value_t = 24 * 60 * 60)]
+    pub cache_ttl_secs: u64,
+}
+
+impl CacheArgs {
+    pub fn cache(&self) -> Option<Cache> {
+        self.cache_dir
+            .as_ref()
+            .map(|dir| Cache::new(dir.clone(), Duration::from_secs(self.cache_ttl_secs)))
+    }
+}
+
+#[derive(Parser)]
+pub struct GetAvailableResources {
+    /// Request a single record of every resource and report whether the key
+    /// can read it, with the HTTP status of the request
+    #[arg(long, required = false, default_value_t = false)]
+    pub available_only: bool,
+
+    /// Print a table of every resource's number of records instead, with '-'
+    /// for resources that cannot be counted
+    #[arg(
+        long,
+        required = false,
+        default_value_t = false,
+        conflicts_with = "available_only"
+    )]
+    pub count_only: bool,
+
+    #[command(flatten)]
+    pub common: Common,
+}
+
+#[derive(Parser)]
+pub struct Languages {
+    #[command(flatten)]
+    pub cache: CacheArgs,
+
+    #[command(flatten)]
+    pub common: Common,
+}
+
+#[derive(ValueEnum, Clone, Default)]
+pub enum SchemaFormat {
+    #[default]
+    Json,
+    /// Indented field tree
+    Text,
+}
+
 #[derive(Parser)]
 pub struct GetSchema {
-    #[arg(required = true)]
-    pub resource: String,
+    #[arg(required_unless_present = "all", conflicts_with = "all")]
+    pub resource: Option<String>,
+
+    /// Fetch the schema of every available resource, keyed by resource name.
+    /// Resources that fail are reported at the end
+    #[arg(long, required = false, default_value_t = false)]
+    pub all: bool,
+
+    #[arg(long, required = false, default_value = "json")]
+    pub format: SchemaFormat,
+
+    /// Nesting depth after which the text format prints '...'
+    #[arg(long, required = false, value_name = "n")]
+    pub max_depth: Option<usize>,
+
+    /// Write the XML returned by the server as is, without parsing it
+    #[arg(
+        long,
+        required = false,
+        default_value_t = false,
+        conflicts_with = "all"
+    )]
+    pub raw: bool,
 
     #[command(flatten)]
     pub common: Common,
@@ -40,100 +271,489 @@ pub struct GetSchema {
 
 #[derive(Clone, Debug)]
 pub struct DateRange {
-    pub from: NaiveDate,
-    pub to: NaiveDate,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
 }
+
+/// Parses a date, optionally with a time, e.g. 2020-10-10, 2020-10-10T12:00,
+/// 2020-10-10 12:00:00 or 2020-10-10T12:00:00+02:00. Dates without a time are
+/// at midnight. The offset of an RFC3339 time is dropped as the shop compares
+/// dates in its own local time
+fn parse_date_time(s: &str) -> anyhow::Result<NaiveDateTime> {
+    let s = s.trim();
+    if let Ok(date) = NaiveDate::from_str(s) {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap());
+    }
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date_time.naive_local());
+    }
+    [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+    .ok_or_else(|| anyhow!("invalid date or date and time '{}'", s))
+}
+
 impl FromStr for DateRange {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if let Some((from, to)) = s.split_once("..") {
-            let from = NaiveDate::from_str(from)?;
-            let to = NaiveDate::from_str(to)?;
+            let from = parse_date_time(from)?;
+            let to = parse_date_time(to)?;
             Ok(DateRange { from, to })
         } else {
             Err(anyhow!(
-                "expected date range in format: 2020-10-10..2021-10-10"
+                "expected date range in format: 2020-10-10..2021-10-10 or 2020-10-10T00:00:00..2020-10-11T12:00:00"
             ))
         }
     }
 }
 
+/// Type overrides like 'reference=utf8,price=decimal(10,2)'
+#[derive(Clone, Debug)]
+pub struct Cast {
+    pub fields: Vec<(String, String)>,
+}
+
+impl FromStr for Cast {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // commas within a type, as in decimal(10,2), continue the previous item
+        let mut items: Vec<String> = vec![];
+        for part in s.split(',') {
+            match items.last_mut() {
+                Some(last) if !part.contains('=') => {
+                    last.push(',');
+                    last.push_str(part);
+                }
+                _ => items.push(part.to_string()),
+            }
+        }
+        let fields = items
+            .iter()
+            .map(|item| match item.split_once('=') {
+                Some((field, ty)) if !field.trim().is_empty() && !ty.trim().is_empty() => {
+                    Ok((field.trim().to_string(), ty.trim().to_string()))
+                }
+                _ => Err(anyhow!("expected format is 'field=type', got '{}'", item)),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Cast { fields })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct FieldValueIn {
     pub field_name: String,
     pub values: Vec<String>,
 }
 
-// use JSON here?
+/// Reads `field_name=value1|value2|..`. PrestaShop splits the values on `|`
+/// without any escaping, so a value can't contain it
 impl FromStr for FieldValueIn {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let error = Err(anyhow!("expected format is 'field_name=value1|value2|..'"));
-        //let mut s = s.chars().collect::<Vec<_>>();
-        let (field_name, s) = match s.split_once('=') {
-            Some(a) => a,
-            None => return error,
-        };
-        if field_name.is_empty() {
-            return error;
-        }
-        let field_name = field_name.to_string();
-        let mut values = vec![];
-        let mut input = s.chars();
-        let mut tmp = vec![];
-        while let Some(c) = input.next() {
-            match c {
-                '\\' => {
-                    tmp.push(
-                        input
-                            .next()
-                            .ok_or(anyhow!("unexpected end of input after '\\'"))?,
-                    );
-                }
-                '|' => {
-                    values.push(String::from_iter(&tmp));
-                    tmp.clear();
-                }
-                _ => {
-                    tmp.push(c);
-                }
-            }
-        }
-        if !tmp.is_empty() {
-            values.push(String::from_iter(&tmp));
-        }
-        if values.is_empty() {
-            return error;
+        let (field_name, s) = s
+            .split_once('=')
+            .filter(|(field_name, s)| !field_name.is_empty() && !s.is_empty())
+            .ok_or_else(|| anyhow!("expected format is 'field_name=value1|value2|..'"))?;
+        if s.contains("\\|") {
+            return Err(anyhow!(
+                "values can't contain '|', the shop always reads it as a separator"
+            ));
         }
         Ok(FieldValueIn {
             field_name: field_name.to_string(),
+            values: s.split('|').map(String::from).collect(),
+        })
+    }
+}
+
+/// A `filter[field]` query parameter, see [Filter::from_str] for the syntax
+#[derive(Clone, Debug)]
+pub struct Filter {
+    pub field: String,
+    pub op: FilterOp,
+    pub values: Vec<String>,
+}
+
+/// Accepts PrestaShop's own filter syntax after `field=`:
+/// `[1,10]` range, `[a|b]` any of, `[a]%` begins, `%[a]%` contains, `%[a]` ends,
+/// and a bare `value` for equality. `[]` and `![]` match empty and non-empty values,
+/// NULL is matched by neither. A bare `NULL` is rejected as the webservice can't
+/// filter on it, `[NULL]` matches the literal string
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, value) = s
+            .split_once('=')
+            .filter(|(field, _)| !field.is_empty())
+            .ok_or_else(|| anyhow!("expected format is 'field=value' or 'field=[..]'"))?;
+        let (prefix, value) = match value.strip_prefix('%') {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        let (suffix, value) = match value.strip_suffix('%') {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        match value {
+            "[]" if !prefix && !suffix => return Ok(Filter::new(field, FilterOp::IsEmpty, vec![])),
+            "![]" if !prefix && !suffix => {
+                return Ok(Filter::new(field, FilterOp::IsNotEmpty, vec![]))
+            }
+            "NULL" | "!NULL" => {
+                return Err(anyhow!(
+                    "the webservice can't filter on NULL, use '[]' or '![]' for empty \
+                     values or '[NULL]' for the literal string"
+                ))
+            }
+            _ => (),
+        }
+        let bracketed = value.strip_prefix('[').and_then(|v| v.strip_suffix(']'));
+        let (op, values) = match (prefix, suffix, bracketed) {
+            (false, false, None) => (FilterOp::Equals, vec![value.to_string()]),
+            (false, false, Some(v)) if v.contains(',') => {
+                let (from, to) = v.split_once(',').unwrap();
+                if to.contains(',') {
+                    return Err(anyhow!("a range takes exactly two values: '[from,to]'"));
+                }
+                (FilterOp::Range, vec![from.to_string(), to.to_string()])
+            }
+            (false, false, Some(v)) => (FilterOp::In, v.split('|').map(String::from).collect()),
+            (false, true, Some(v)) => (FilterOp::Begins, vec![v.to_string()]),
+            (true, true, Some(v)) => (FilterOp::Contains, vec![v.to_string()]),
+            (true, false, Some(v)) => (FilterOp::Ends, vec![v.to_string()]),
+            _ => {
+                return Err(anyhow!(
+                    "'%' must be combined with a bracketed value, e.g. '[abc]%'"
+                ))
+            }
+        };
+        Ok(Filter::new(field, op, values))
+    }
+}
+
+impl Filter {
+    fn new(field: &str, op: FilterOp, values: Vec<String>) -> Self {
+        Filter {
+            field: field.to_string(),
+            op,
             values,
+        }
+    }
+}
+
+/// A `price[field][setting]=value` query parameter, given as `field[setting]=value`
+#[derive(Clone, Debug)]
+pub struct Price {
+    pub field: String,
+    pub setting: PriceSetting,
+}
+
+impl FromStr for Price {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected format is 'field[setting]=value'"))?;
+        let (field, setting) = key
+            .strip_suffix(']')
+            .and_then(|key| key.split_once('['))
+            .filter(|(field, setting)| !field.is_empty() && !setting.is_empty())
+            .ok_or_else(|| anyhow!("expected format is 'field[setting]=value'"))?;
+        Ok(Price {
+            field: field.to_string(),
+            setting: PriceSetting::new(setting, value)?,
         })
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Sort {
+    pub fields: Vec<(String, SortDir)>,
+}
+
+impl FromStr for Sort {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = vec![];
+        for part in s.split(',') {
+            let (name, dir) = match part.split_once(':') {
+                Some((name, dir)) => (name, dir.parse::<SortDir>()?),
+                None => (part, SortDir::Asc),
+            };
+            if name.is_empty() {
+                return Err(anyhow!("expected format is 'field1:asc,field2:desc'"));
+            }
+            fields.push((name.to_string(), dir));
+        }
+        Ok(Sort { fields })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn test() {
-        let x = <FieldValueIn as FromStr>::from_str("field=ab\\|c|de|f||g").unwrap();
+        let x = <FieldValueIn as FromStr>::from_str("field=ab|de|f||g").unwrap();
         assert_eq!(x.field_name.as_str(), "field");
-        assert_eq!(x.values, vec!["ab|c", "de", "f", "", "g"]);
+        assert_eq!(x.values, vec!["ab", "de", "f", "", "g"]);
+        let x = <FieldValueIn as FromStr>::from_str("reference=a\\b|c").unwrap();
+        assert_eq!(x.values, vec!["a\\b", "c"]);
+        assert!(<FieldValueIn as FromStr>::from_str("field=ab\\|c").is_err());
         let x = <FieldValueIn as FromStr>::from_str("field=a").unwrap();
         assert_eq!(x.field_name.as_str(), "field");
         assert_eq!(x.values, vec!["a"]);
         assert!(<FieldValueIn as FromStr>::from_str("=a").is_err());
         assert!(<FieldValueIn as FromStr>::from_str("a=").is_err());
     }
+
+    #[test]
+    fn test_price() {
+        let price = Price::from_str("my_price[use_tax]=1").unwrap();
+        assert_eq!(price.field, "my_price");
+        assert_eq!(price.setting, PriceSetting::UseTax(true));
+        let price = Price::from_str("my_price[decimals]=2").unwrap();
+        assert_eq!(price.setting, PriceSetting::Decimals(2));
+        assert!(Price::from_str("my_price=1").is_err());
+        assert!(Price::from_str("[use_tax]=1").is_err());
+        assert!(Price::from_str("my_price[currency]=eur").is_err());
+    }
+
+    #[test]
+    fn test_cast() {
+        let cast = Cast::from_str("reference=utf8,price=decimal(10,2), id = int64").unwrap();
+        assert_eq!(
+            cast.fields,
+            vec![
+                ("reference".to_string(), "utf8".to_string()),
+                ("price".to_string(), "decimal(10,2)".to_string()),
+                ("id".to_string(), "int64".to_string()),
+            ]
+        );
+        assert!(Cast::from_str("reference").is_err());
+        assert!(Cast::from_str("=utf8").is_err());
+    }
+
+    #[test]
+    fn test_date_range() {
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        let range = DateRange::from_str("2020-01-01..2020-01-02").unwrap();
+        assert_eq!(range.from, at("2020-01-01 00:00:00"));
+        assert_eq!(range.to, at("2020-01-02 00:00:00"));
+        let range = DateRange::from_str("2020-01-01T00:00:00..2020-01-02T12:00:00").unwrap();
+        assert_eq!(range.to, at("2020-01-02 12:00:00"));
+        let range = DateRange::from_str("2020-01-01 08:30..2020-01-02T12:00:00+02:00").unwrap();
+        assert_eq!(range.from, at("2020-01-01 08:30:00"));
+        assert_eq!(range.to, at("2020-01-02 12:00:00"));
+        assert!(DateRange::from_str("2020-01-01").is_err());
+        assert!(DateRange::from_str("2020-01-01..yesterday").is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let parse = |s: &str| {
+            let f = Filter::from_str(s).unwrap();
+            (f.field, f.op, f.values)
+        };
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            parse("price=[10,100]"),
+            (
+                "price".to_string(),
+                FilterOp::Range,
+                strings(&["10", "100"])
+            )
+        );
+        assert_eq!(parse("id=[1|2]").2, strings(&["1", "2"]));
+        assert_eq!(parse("name=[foo]%").1, FilterOp::Begins);
+        assert_eq!(parse("name=%[foo]%").1, FilterOp::Contains);
+        assert_eq!(parse("name=%[foo]").1, FilterOp::Ends);
+        assert_eq!(parse("active=1").1, FilterOp::Equals);
+        assert_eq!(parse("note=[]").1, FilterOp::IsEmpty);
+        assert_eq!(parse("note=![]").1, FilterOp::IsNotEmpty);
+        assert!(Filter::from_str("deleted=NULL").is_err());
+        assert!(Filter::from_str("deleted=!NULL").is_err());
+        // the literal string is matched by bracketing it
+        assert_eq!(
+            parse("note=[NULL]"),
+            ("note".to_string(), FilterOp::In, strings(&["NULL"]))
+        );
+        assert!(Filter::from_str("=1").is_err());
+        assert!(Filter::from_str("name=foo%").is_err());
+        assert!(Filter::from_str("id=[1,2,3]").is_err());
+    }
+
+    #[test]
+    fn test_output_path_template() {
+        let path = |args: &[&str]| {
+            let args = ["cli"].iter().chain(args).collect::<Vec<_>>();
+            <Arguments as Parser>::try_parse_from(args)
+                .unwrap()
+                .get_output_path()
+        };
+        assert_eq!(
+            path(&[
+                "get",
+                "products",
+                "--conf",
+                "c.toml",
+                "--output-format",
+                "parquet",
+                "--output-path",
+                "dumps/{resource}.{ext}"
+            ])
+            .unwrap(),
+            Some(PathBuf::from("dumps/products.parquet"))
+        );
+        assert_eq!(
+            path(&[
+                "get-schema",
+                "orders",
+                "--conf",
+                "c.toml",
+                "--format",
+                "text"
+            ])
+            .unwrap(),
+            None
+        );
+        assert_eq!(
+            path(&[
+                "get",
+                "products",
+                "--conf",
+                "c.toml",
+                "--raw",
+                "--output-path",
+                "{resource}.{ext}"
+            ])
+            .unwrap(),
+            Some(PathBuf::from("products.xml"))
+        );
+        assert_eq!(
+            path(&[
+                "get-blank",
+                "customers",
+                "--conf",
+                "c.toml",
+                "--raw",
+                "--output-path",
+                "blank_{resource}.{ext}"
+            ])
+            .unwrap(),
+            Some(PathBuf::from("blank_customers.xml"))
+        );
+        assert!(path(&[
+            "get-available-resources",
+            "--conf",
+            "c.toml",
+            "--output-path",
+            "{resource}.json"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_default_conf_path() {
+        assert_eq!(
+            default_conf_path(Some("/xdg".into()), Some("/home/u".into())),
+            Some(PathBuf::from("/xdg/ps17-cli/config.toml"))
+        );
+        // relative XDG_CONFIG_HOME is invalid per the spec and ignored
+        assert_eq!(
+            default_conf_path(Some("xdg".into()), Some("/home/u".into())),
+            Some(PathBuf::from("/home/u/.config/ps17-cli/config.toml"))
+        );
+        assert_eq!(default_conf_path(None, None), None);
+    }
+
+    #[test]
+    fn test_sort() {
+        let x = Sort::from_str("name:asc,price:DESC,id").unwrap();
+        assert_eq!(
+            x.fields,
+            vec![
+                ("name".to_string(), SortDir::Asc),
+                ("price".to_string(), SortDir::Desc),
+                ("id".to_string(), SortDir::Asc)
+            ]
+        );
+        assert!(Sort::from_str("name:up").is_err());
+        assert!(Sort::from_str("name:asc,").is_err());
+    }
+
+    #[test]
+    fn test_override_config() {
+        let config = |args: &[&str]| {
+            let args = ["cli", "languages", "--conf", "c.toml"]
+                .iter()
+                .chain(args)
+                .collect::<Vec<_>>();
+            let mut config = HttpConfig::from_toml(
+                "host = \"https://shop\"\nauthorization_kind = \"Header\"\n\
+                 request_timeout_secs = 30\nmax_retries = 3\n",
+                None,
+            )
+            .unwrap();
+            <Arguments as Parser>::try_parse_from(args)
+                .unwrap()
+                .get_common()
+                .unwrap()
+                .override_config(&mut config);
+            (config.request_timeout_secs, config.max_retries)
+        };
+        assert_eq!(config(&[]), (Some(30), Some(3)));
+        assert_eq!(
+            config(&["--timeout-secs", "5", "--retries", "0"]),
+            (Some(5), Some(0))
+        );
+    }
+
+    #[test]
+    fn test_verbosity() {
+        let level = |args: &[&str]| {
+            let args = ["cli"].iter().chain(args).collect::<Vec<_>>();
+            <Arguments as Parser>::try_parse_from(args)
+                .unwrap()
+                .log_level()
+        };
+        assert_eq!(level(&["completions", "bash"]), LevelFilter::WARN);
+        assert_eq!(level(&["completions", "bash", "-v"]), LevelFilter::INFO);
+        assert_eq!(level(&["-vv", "completions", "bash"]), LevelFilter::DEBUG);
+        assert_eq!(level(&["completions", "bash", "-vvvv"]), LevelFilter::TRACE);
+    }
+
+    #[test]
+    fn test_limit() {
+        assert!(matches!(Limit::from_str("all"), Ok(Limit::All)));
+        assert!(matches!(Limit::from_str("0"), Ok(Limit::All)));
+        assert!(matches!(Limit::from_str("10"), Ok(Limit::Count(10))));
+        assert!(matches!(
+            Limit::from_str("10,20"),
+            Ok(Limit::FromIndex(10, 20))
+        ));
+        assert!(matches!(Limit::from_str("0,5"), Ok(Limit::FromIndex(0, 5))));
+        let err = Limit::from_str("10,0").unwrap_err().to_string();
+        assert!(err.contains("at least 1"), "{}", err);
+        assert!(Limit::from_str("10,").is_err());
+        assert!(Limit::from_str("-1").is_err());
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub enum Limit {
+    #[default]
     All,
-    Limit(usize),
-    LimitFromIndex(usize, usize),
+    Count(usize),
+    FromIndex(usize, usize),
 }
 
 impl std::fmt::Display for Limit {
@@ -142,23 +762,32 @@ impl std::fmt::Display for Limit {
     }
 }
 
-impl Default for Limit {
-    fn default() -> Self {
-        Limit::All
-    }
-}
-
 impl FromStr for Limit {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |part: &str, what: &str| {
+            part.trim()
+                .parse::<usize>()
+                .map_err(|e| anyhow!("invalid {} {:?} in limit {:?}: {}", what, part, s, e))
+        };
         if s == "all" {
             Ok(Limit::All)
-        } else if let Some((a, b)) = s.split_once(",") {
-            let a = a.parse::<usize>()?;
-            let b = b.parse::<usize>()?;
-            Ok(Limit::LimitFromIndex(a, b))
+        } else if let Some((a, b)) = s.split_once(',') {
+            let index = parse(a, "start index")?;
+            let count = parse(b, "count")?;
+            if count == 0 {
+                return Err(anyhow!(
+                    "count in limit {:?} must be at least 1, the second number is a count and not an end index",
+                    s
+                ));
+            }
+            Ok(Limit::FromIndex(index, count))
         } else {
-            Ok(Limit::Limit(s.parse::<usize>()?))
+            match parse(s, "count")? {
+                // the webservice does not treat limit=0 as "no records"
+                0 => Ok(Limit::All),
+                n => Ok(Limit::Count(n)),
+            }
         }
     }
 }
@@ -168,28 +797,115 @@ pub struct Get {
     #[arg(required = true)]
     pub resource: String,
 
-    /// Supported formats are 'all', '10' and '10,20' where 'all' disables
-    /// limiting, '10' limits to 10 records and '10,20' limits to 10 records from index 10
+    /// Supported formats are 'all', '10' and '10,20' where 'all' or '0' disables
+    /// limiting, '10' limits to 10 records and '10,20' fetches 20 records starting
+    /// at index 10 (an index and a count, not a range)
     #[arg(short, long, required = false)]
     pub limit: Option<Limit>,
 
+    /// Fetch the resource in pages of this many records, following limit offsets
+    /// until an empty page is returned. Pages are written to the output as they arrive
+    #[arg(long, required = false, value_name = "n", conflicts_with = "limit")]
+    pub page_size: Option<usize>,
+
+    /// Show the number of pages and rows fetched so far on stderr while paginating
+    /// with --page-size. Only shown when stderr is a terminal
+    #[arg(
+        long,
+        required = false,
+        default_value_t = false,
+        requires = "page_size"
+    )]
+    pub progress: bool,
+
+    /// Print the non-null and null counts of every column to stderr once
+    /// written, with the min and max of numbers and dates
+    #[arg(long, required = false, default_value_t = false)]
+    pub stats: bool,
+
+    /// Only fetch these fields. Names are checked against the schema of the
+    /// resource before the data is requested. Nested fields like
+    /// 'associations.images' are not selected by the shop: the whole top-level
+    /// field is fetched and the other subfields are dropped after parsing
     #[arg(short, long, required = false, value_name = "field")]
     pub fields: Option<Vec<String>>,
 
+    /// Fetch every top-level field except these. The schema is fetched first
+    /// to list the other fields. Can be repeated
+    #[arg(
+        long,
+        required = false,
+        value_name = "field",
+        conflicts_with = "fields"
+    )]
+    pub exclude_fields: Option<Vec<String>>,
+
+    /// Match any of the values of a field, e.g. 'id=1|5'. Values can't contain '|'
     #[arg(long, required = false)]
     pub field_value_in: Option<FieldValueIn>,
 
+    /// Filter by field, e.g. 'price=[10,100]' for a range, 'id=[1|5]' for any of,
+    /// 'name=[foo]%', 'name=%[foo]%' and 'name=%[foo]' for begins, contains and ends
+    /// with, or 'active=1' for equality. 'note=[]' and 'note=![]' match empty and
+    /// non-empty values, NULL by neither as the webservice can't filter on it. Use
+    /// 'note=[NULL]' for the literal string. Can be repeated
+    #[arg(long, required = false, value_name = "field=value")]
+    pub filter: Option<Vec<Filter>>,
+
+    /// Override inferred types, e.g. 'reference=utf8,price=decimal(10,2)'. Types are
+    /// utf8, bool, int32, uint32, int64, uint64, float64, date and, with --arrow2,
+    /// decimal(precision,scale). Can be repeated
+    #[arg(long, required = false, value_name = "field=type")]
+    pub cast: Option<Vec<Cast>>,
+
+    /// Only return strings of the language with this id for multilingual fields,
+    /// which become plain strings, null where that language is missing
+    #[arg(long, required = false, value_name = "id")]
+    pub language: Option<usize>,
+
+    /// Like --language with the language's iso code, e.g. 'en', looked up in the
+    /// shop's languages
+    #[arg(
+        long,
+        required = false,
+        value_name = "iso",
+        conflicts_with = "language"
+    )]
+    pub select_language: Option<String>,
+
+    /// Id of the shop to scope a multistore shop to. The default shop is used otherwise
+    #[arg(long, required = false, value_name = "id")]
+    pub shop: Option<usize>,
+
+    /// Id of the shop group to scope a multistore shop to
+    #[arg(long, required = false, value_name = "id")]
+    pub shop_group: Option<usize>,
+
+    /// Sort order, e.g. 'name:asc,price:desc'. The direction defaults to ascending
+    #[arg(long, required = false)]
+    pub sort: Option<Sort>,
+
+    /// Have the shop compute a price into an extra field of every record, e.g.
+    /// 'my_price[use_tax]=0' is sent as 'price[my_price][use_tax]=0' and adds a
+    /// 'my_price' column. Settings are use_tax, use_reduction, currency, country,
+    /// group, product_attribute, quantity and decimals. Can be repeated, settings
+    /// of the same field apply to one price
+    #[arg(long, required = false, value_name = "field[setting]=value")]
+    pub price: Option<Vec<Price>>,
+
     #[command(flatten)]
     pub common: Common,
 
     #[command(flatten)]
     pub output_format_args: OutputFormatArgs,
 
-    /// Date range passed as filter on date_upd field. Argument format: 2020-10-10..2021-10-10
+    /// Date range passed as filter on date_upd field. Argument format: 2020-10-10..2021-10-10,
+    /// times are optional: 2020-10-10T00:00:00..2020-10-11T12:00:00
     #[arg(long, required = false)]
     pub date_upd: Option<DateRange>,
 
-    /// Date range passed as filter on date_add field. Argument format: 2020-10-10..2021-10-10
+    /// Date range passed as filter on date_add field. Argument format: 2020-10-10..2021-10-10,
+    /// times are optional: 2020-10-10T00:00:00..2020-10-11T12:00:00
     #[arg(long, required = false)]
     pub date_add: Option<DateRange>,
 
@@ -198,37 +914,332 @@ pub struct Get {
     #[arg(long, required = false, default_value_t = false)]
     pub flatten1: bool,
 
+    /// Replace struct columns by their fields up to this many levels deep,
+    /// naming them by path, e.g. 'customer.address.city'. List columns are kept
+    #[arg(long, required = false, value_name = "n")]
+    pub flatten_depth: Option<usize>,
+
+    /// Drop rows that repeat the values of the given key column(s), keeping the first.
+    /// Struct fields can be addressed as 'product.id'. The whole result is buffered
+    /// so duplicates are removed globally
+    #[arg(long, required = false, value_name = "column")]
+    pub dedup_on: Option<Vec<String>>,
+
+    /// Skip records that fail to parse instead of aborting the whole export.
+    /// Skipped records are logged with their position and id
+    #[arg(long, required = false, default_value_t = false)]
+    pub continue_on_error: bool,
+
+    /// Read values that fail to parse as null instead of aborting the whole
+    /// export, keeping the rest of their record. Each is logged with its field,
+    /// the record's position and id
+    #[arg(
+        long,
+        required = false,
+        default_value_t = false,
+        conflicts_with = "continue_on_error"
+    )]
+    pub skip_errors: bool,
+
+    /// Parse responses one record at a time instead of building a tree of the
+    /// whole response first. Lowers peak memory for large pages, but expects
+    /// the records directly in the single child of the root
+    #[arg(long, required = false, default_value_t = false)]
+    pub streaming: bool,
+
+    /// Print the URL of the request instead of executing it. With --page-size
+    /// this is the URL of the first page. Can't be combined with --exclude-fields,
+    /// which needs the schema from the shop
+    #[arg(
+        long,
+        required = false,
+        default_value_t = false,
+        conflicts_with = "exclude_fields"
+    )]
+    pub dry_run: bool,
+
+    /// Print the number of matching records instead of the records. Only
+    /// ids are fetched, so this is cheap even for large resources
+    #[arg(
+        long,
+        required = false,
+        default_value_t = false,
+        conflicts_with = "page_size"
+    )]
+    pub count: bool,
+
+    /// Write the XML returned by the server as is, without parsing it. Useful
+    /// for reporting resources that fail to parse
+    #[arg(
+        long,
+        required = false,
+        default_value_t = false,
+        conflicts_with_all = ["page_size", "count"]
+    )]
+    pub raw: bool,
+
     /// Use arrow2 instead of arrow1 where implemented
     /// This always means --flatten1 too
     #[arg(long, required = false, default_value_t = false)]
     pub arrow2: bool,
+
+    /// Don't add the 'id' field that is assumed for every resource, for resources
+    /// whose records have no id. An 'id' the schema describes itself is kept. With
+    /// --arrow2 records that do have an id then fail to parse, use --cast id=utf8
+    /// for ids that aren't numbers instead
+    #[arg(long, required = false, default_value_t = false)]
+    pub no_id_field: bool,
+
+    /// Directory where schemas are cached between runs, per shop and resource
+    #[arg(long, required = false, value_name = "dir")]
+    pub schema_cache: Option<PathBuf>,
+
+    /// How long cached schemas stay valid, in seconds
+    #[arg(long, required = false, value_name = "n", default_value_t = 24 * 60 * 60)]
+    pub schema_cache_ttl_secs: u64,
+
+    /// Fail on field formats that are unknown or have no type mapping instead
+    /// of reading those fields as strings
+    #[arg(long, required = false, default_value_t = false)]
+    pub strict_schema: bool,
+
+    /// Fetch the schema even when it is cached, replacing the cached one
+    #[arg(
+        long,
+        required = false,
+        default_value_t = false,
+        requires = "schema_cache"
+    )]
+    pub refresh_schema: bool,
+
+    /// Add an '@href' column to association items with the URL of the
+    /// associated resource, e.g. for building link tables between resources
+    #[arg(long, required = false, default_value_t = false, requires = "arrow2")]
+    pub hrefs: bool,
 }
 
+#[derive(Parser)]
+pub struct GetOne {
+    #[arg(required = true)]
+    pub resource: String,
+
+    /// Id of the entity to fetch
+    #[arg(required = true)]
+    pub id: u32,
+
+    /// Nesting depth after which objects and lists are printed as '...'
+    #[arg(long, required = false, value_name = "n")]
+    pub json_max_depth: Option<usize>,
+
+    /// Only return strings of the language with this id for multilingual fields
+    #[arg(long, required = false, value_name = "id")]
+    pub language: Option<usize>,
+
+    #[command(flatten)]
+    pub common: Common,
+}
+
+impl Get {
+    pub fn schema_cache(&self) -> Option<Cache> {
+        // with a zero TTL every cached schema is expired, so it is fetched and stored again
+        let ttl = match self.refresh_schema {
+            true => Duration::ZERO,
+            false => Duration::from_secs(self.schema_cache_ttl_secs),
+        };
+        self.schema_cache
+            .as_ref()
+            .map(|dir| Cache::new(dir.clone(), ttl))
+    }
+}
+
+#[derive(Parser)]
+pub struct Create {
+    #[arg(required = true)]
+    pub resource: String,
+
+    /// JSON or XML file with the entity to create. Reads stdin when omitted or '-'.
+    /// JSON uses the same shape as `get` output, e.g. {"product": {...}}
+    #[arg(long, required = false)]
+    pub input: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub common: Common,
+}
+
+#[derive(Parser)]
+pub struct GetBlank {
+    #[arg(required = true)]
+    pub resource: String,
+
+    /// Write the XML returned by the server as is, ready to fill in and pass
+    /// to `create --input`
+    #[arg(long, required = false, default_value_t = false)]
+    pub raw: bool,
+
+    #[command(flatten)]
+    pub common: Common,
+}
+
+#[derive(Parser)]
+pub struct Update {
+    #[arg(required = true)]
+    pub resource: String,
+
+    /// Id of the entity to update
+    #[arg(required = true)]
+    pub id: u32,
+
+    /// JSON or XML file with the fields to change, in the same shape as for
+    /// `create`, e.g. {"product": {"price": 12.5}}. Reads stdin when omitted or '-'.
+    /// Other fields keep their current values
+    #[arg(long, required = false)]
+    pub input: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub common: Common,
+}
+
+#[derive(Parser)]
+pub struct Delete {
+    #[arg(required = true)]
+    pub resource: String,
+
+    /// Ids of the entities to delete
+    #[arg(required = true, num_args = 1..)]
+    pub ids: Vec<u32>,
+
+    #[command(flatten)]
+    pub common: Common,
+}
+
+#[derive(Parser)]
+pub struct Completions {
+    #[arg(required = true)]
+    pub shell: clap_complete::Shell,
+}
+
+impl Completions {
+    pub fn generate<W: std::io::Write>(&self, writer: &mut W) {
+        let mut command = <Arguments as clap::CommandFactory>::command();
+        clap_complete::generate(self.shell, &mut command, env!("CARGO_BIN_NAME"), writer);
+    }
+}
+
+// parsed once per run, boxing Get buys nothing
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Command {
     Get(Get),
     GetSchema(GetSchema),
-    GetAvailableResources(Common),
+    /// Fetches a single entity by id
+    GetOne(GetOne),
+    GetAvailableResources(GetAvailableResources),
+    Create(Create),
+    /// Fetches the empty template of an entity with all of its writable
+    /// fields, to fill in for `create`
+    GetBlank(GetBlank),
+    /// Changes some fields of an entity, keeping the others
+    Update(Update),
+    /// Deletes entities by id, reporting the outcome of every id
+    Delete(Delete),
+    /// Prints the shop's language id to iso code mapping
+    Languages(Languages),
+    /// Prints a shell completion script
+    Completions(Completions),
 }
 
 #[derive(Parser)]
 pub struct Arguments {
     #[command(subcommand)]
     pub command: Command,
+
+    /// Log more to stderr, once for info, twice for debug and three times for
+    /// trace. Only warnings and errors are logged by default. RUST_LOG, when set,
+    /// takes precedence
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
 }
 
 impl Arguments {
     pub fn parse() -> Self {
         <Self as Parser>::parse()
     }
-    pub fn get_output_path(&self) -> &Option<PathBuf> {
-        &self.get_common().output_path
+    pub fn log_level(&self) -> LevelFilter {
+        match self.verbose {
+            0 => LevelFilter::WARN,
+            1 => LevelFilter::INFO,
+            2 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        }
+    }
+    /// The output path with `{resource}` and `{ext}` replaced by the command's
+    /// resource and the file extension of the chosen format
+    pub fn get_output_path(&self) -> anyhow::Result<Option<PathBuf>> {
+        let Some(template) = self.get_common().and_then(|c| c.output_path.as_ref()) else {
+            return Ok(None);
+        };
+        let template = template
+            .to_str()
+            .ok_or_else(|| anyhow!("output path is not valid UTF-8: {:?}", template))?;
+        let path = match (template.contains("{resource}"), self.get_resource()) {
+            (true, Some(resource)) => template.replace("{resource}", resource),
+            (true, None) => {
+                return Err(anyhow!(
+                    "output path uses {{resource}} but the command has no resource"
+                ))
+            }
+            (false, _) => template.to_string(),
+        };
+        Ok(Some(PathBuf::from(
+            path.replace("{ext}", self.get_output_extension()),
+        )))
+    }
+    pub fn get_resource(&self) -> Option<&str> {
+        match self.command {
+            Command::Get(ref args) => Some(&args.resource),
+            Command::GetSchema(ref args) => args.resource.as_deref(),
+            Command::GetAvailableResources(ref _args) => None,
+            Command::Create(ref args) => Some(&args.resource),
+            Command::GetBlank(ref args) => Some(&args.resource),
+            Command::GetOne(ref args) => Some(&args.resource),
+            Command::Update(ref args) => Some(&args.resource),
+            Command::Delete(ref args) => Some(&args.resource),
+            Command::Languages(ref _args) => Some("languages"),
+            Command::Completions(ref _args) => None,
+        }
+    }
+    fn get_output_extension(&self) -> &'static str {
+        match self.command {
+            Command::Get(Get { raw: true, .. })
+            | Command::GetSchema(GetSchema { raw: true, .. })
+            | Command::GetBlank(GetBlank { raw: true, .. }) => "xml",
+            Command::Get(ref args) => match args.output_format_args.output_format {
+                None | Some(OutputFormat::Ndjson) => "ndjson",
+                Some(OutputFormat::Json) => "json",
+                Some(OutputFormat::Parquet) => "parquet",
+                Some(OutputFormat::Csv) => "csv",
+                Some(OutputFormat::Arrow) => "arrow",
+                Some(OutputFormat::Avro) => "avro",
+            },
+            Command::GetSchema(GetSchema {
+                format: SchemaFormat::Text,
+                ..
+            }) => "txt",
+            _ => "json",
+        }
     }
-    pub fn get_common(&self) -> &Common {
+    pub fn get_common(&self) -> Option<&Common> {
         match self.command {
-            Command::Get(ref args) => &args.common,
-            Command::GetSchema(ref args) => &args.common,
-            Command::GetAvailableResources(ref args) => &args,
+            Command::Get(ref args) => Some(&args.common),
+            Command::GetSchema(ref args) => Some(&args.common),
+            Command::GetAvailableResources(ref args) => Some(&args.common),
+            Command::Create(ref args) => Some(&args.common),
+            Command::GetBlank(ref args) => Some(&args.common),
+            Command::GetOne(ref args) => Some(&args.common),
+            Command::Update(ref args) => Some(&args.common),
+            Command::Delete(ref args) => Some(&args.common),
+            Command::Languages(ref args) => Some(&args.common),
+            Command::Completions(ref _args) => None,
         }
     }
     pub fn get_output_format(&self) -> &Option<OutputFormat> {
@@ -236,6 +1247,13 @@ impl Arguments {
             Command::Get(ref args) => &args.output_format_args.output_format,
             Command::GetSchema(ref _args) => &None,
             Command::GetAvailableResources(ref _args) => &None,
+            Command::Create(ref _args) => &None,
+            Command::GetBlank(ref _args) => &None,
+            Command::GetOne(ref _args) => &None,
+            Command::Update(ref _args) => &None,
+            Command::Delete(ref _args) => &None,
+            Command::Languages(ref _args) => &None,
+            Command::Completions(ref _args) => &None,
         }
     }
 }
@@ -1,63 +1,317 @@
 extern crate core;
 
+use std::io::IsTerminal;
 use std::ops::Sub;
 
-use ::tracing::level_filters::LevelFilter;
-use anyhow::{anyhow, Result};
-use arrow::array::{Array, StructArray};
+use ::tracing::{info, warn};
+use anyhow::{anyhow, Context, Result};
 use arrow::record_batch::RecordBatch;
+use arrow2::array::Array as Array2;
+use arrow2::chunk::Chunk;
+use std::future::Future;
 
 use common::http::{
-    configure_http, query_param, ws_get_available_resources, ws_get_resource2_arrow,
-    ws_get_resource2_arrow2, ws_get_resource_schema2, ws_get_resource_schema3, DateField, Http,
-    QueryParam, Resource,
+    query_param, ws_count_resource, ws_create_resource, ws_delete_resource,
+    ws_get_available_resources, ws_get_languages, ws_get_resource2_arrow2, ws_get_resource_blank,
+    ws_get_resource_blank_string, ws_get_resource_by_id, ws_get_resource_schema2,
+    ws_get_resource_schema2_cached, ws_get_resource_schema3_cached, ws_get_resource_schema_string,
+    ws_get_resource_string, ws_probe_resource, ws_resource_url, ws_update_resource, DateField,
+    Http, Pages, QueryParam, Resource,
 };
 
-use crate::arguments::{Arguments, Command, Limit, OutputFormat};
-use crate::output::{OutputFile, OutputStdout, OutputT};
+use crate::arguments::{Arguments, Command, Limit, OutputFormat, SchemaFormat};
+use crate::output::{BatchLen, ItemsFailed, OutputFile, OutputStdout, OutputT, OutputTee};
+use crate::stats::{ResourceCounts, Stats};
 
 mod arguments;
 mod output;
+mod stats;
+mod transform;
 
-use common::utils;
-fn flatten_single_toplevel_struct(batch: &RecordBatch) -> Result<RecordBatch> {
-    if batch.num_columns() != 1 {
-        return Err(anyhow!(
-            "cannot flatten1 when top level has more than one field"
-        ));
+use common::arrow2::schema3;
+use common::arrow2::utils as arrow2_utils;
+use common::fetch::{fetch_resource_batch, FetchOptions};
+use common::on_error::OnError;
+use common::{schema2, utils};
+
+/// Writes the JSON description of the output schema for --schema-out
+fn write_schema_out(path: &std::path::Path, schema: &serde_json::Value) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(schema)?)
+        .with_context(|| format!("failed to write schema to {}", path.display()))
+}
+
+/// Pages fetched and rows accumulated, redrawn on a single stderr line for --progress
+struct Progress {
+    enabled: bool,
+    pages: usize,
+    rows: usize,
+}
+
+impl Progress {
+    /// Only enabled when stderr is a terminal, so logs redirected to files stay clean
+    fn new(requested: bool) -> Self {
+        Self {
+            enabled: requested && std::io::stderr().is_terminal(),
+            pages: 0,
+            rows: 0,
+        }
+    }
+
+    fn page_fetched(&mut self, rows: usize) {
+        self.pages += 1;
+        self.rows += rows;
+        if self.enabled {
+            eprint!("\rfetched {} pages, {} rows", self.pages, self.rows);
+        }
+    }
+
+    fn finish(&mut self) {
+        if std::mem::take(&mut self.enabled) {
+            eprintln!();
+        }
+    }
+}
+
+/// The shop only selects top-level fields, nested ones are pruned after parsing
+fn top_level_field_names(fields: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for field in fields {
+        let name = field.split('.').next().unwrap_or_default().to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn display_param(fields: Option<&[String]>) -> QueryParam {
+    match fields {
+        Some(fields) => {
+            QueryParam::Display(query_param::Display::Fields(top_level_field_names(fields)))
+        }
+        None => QueryParam::Display(query_param::Display::Full),
     }
-    let sa = batch
-        .column(0)
-        .as_any()
-        .downcast_ref::<StructArray>()
-        .ok_or_else(|| anyhow!("failed casting ot StructArray"))?;
-    let new_batch = RecordBatch::from(sa);
-    Ok(new_batch)
 }
 
-pub async fn run_command<W, O>(args: Arguments, _http: Http, output: O) -> Result<()>
+/// Lazily fetches `limit=offset,page_size` pages, blocking on each request as the
+/// output pulls the next batch. Must be consumed inside [tokio::task::block_in_place].
+/// An empty page ends the iteration, it is only yielded when it's the first one so
+/// that the output still gets a schema.
+fn fetch_pages<'a, T, F, Fut>(
+    page_size: usize,
+    params: &'a [QueryParam],
+    mut progress: Progress,
+    mut fetch: F,
+) -> Result<impl Iterator<Item = Result<T>> + 'a>
+where
+    T: BatchLen,
+    F: FnMut(Vec<QueryParam>) -> Fut + 'a,
+    Fut: Future<Output = Result<T>>,
+{
+    let handle = tokio::runtime::Handle::current();
+    let mut pages = Pages::new(page_size)?;
+    Ok(std::iter::from_fn(move || {
+        let Some(page_params) = pages.next_params(params) else {
+            progress.finish();
+            return None;
+        };
+        let first = pages.is_first();
+        let result = handle.block_on(fetch(page_params));
+        match &result {
+            Ok(batch) => {
+                let rows = batch.batch_len();
+                info!("fetched page of {} rows", rows);
+                pages.page_fetched(rows);
+                progress.page_fetched(rows);
+                if rows == 0 && !first {
+                    progress.finish();
+                    return None;
+                }
+            }
+            // stop after the error, the output reports what was written so far
+            Err(_) => {
+                pages.page_fetched(0);
+                progress.finish();
+            }
+        }
+        Some(result)
+    }))
+}
+
+/// Runs a command with the client of the run, so that all of its requests share
+/// one connection pool
+pub async fn run_command<W, O>(args: Arguments, http: Http, output: O) -> Result<()>
 where
     W: std::io::Write + Send,
     O: OutputT<W>,
 {
     match args.command {
         Command::GetAvailableResources(args) => {
-            let http = configure_http(args.conf.as_str())?;
             let r = ws_get_available_resources(&http).await?;
-            output.json(std::iter::once(r))?;
+            if args.available_only {
+                let mut accesses = vec![];
+                for res in &r {
+                    accesses.push(ws_probe_resource(&http, res).await?);
+                }
+                output.json(args.common.pretty, std::iter::once(accesses))?;
+            } else if args.count_only {
+                let mut counts = ResourceCounts::default();
+                for res in &r {
+                    let count = match ws_count_resource(&http, res, &[]).await {
+                        Ok(count) => Some(count),
+                        Err(e) => {
+                            warn!("failed to count {}: {:#}", res.identifier(), e);
+                            None
+                        }
+                    };
+                    counts.0.push((res.identifier().to_string(), count));
+                }
+                output.text(counts.to_string().trim_end())?;
+            } else {
+                output.json(args.common.pretty, std::iter::once(r))?;
+            }
         }
         Command::GetSchema(args) => {
-            let http = configure_http(args.common.conf.as_str())?;
-            let r = ws_get_resource_schema2(&http, &Resource::new(args.resource)).await?;
-            output.json(std::iter::once(r))?;
+            let max_depth = args.max_depth.unwrap_or(usize::MAX);
+            let Some(resource) = args.resource else {
+                let mut schemas = vec![];
+                let mut failed = vec![];
+                for res in ws_get_available_resources(&http).await? {
+                    match ws_get_resource_schema2(&http, &res).await {
+                        Ok(s) => schemas.push((res.identifier().to_string(), s)),
+                        Err(e) => {
+                            warn!("failed to fetch schema of {}: {:#}", res.identifier(), e);
+                            failed.push(res.identifier().to_string());
+                        }
+                    }
+                }
+                match args.format {
+                    SchemaFormat::Json => {
+                        let map = schemas
+                            .into_iter()
+                            .map(|(name, s)| Ok((name, serde_json::to_value(s)?)))
+                            .collect::<Result<serde_json::Map<_, _>>>()?;
+                        output.json(args.common.pretty, std::iter::once(map))?
+                    }
+                    SchemaFormat::Text => output.text(
+                        &schemas
+                            .iter()
+                            .map(|(name, s)| {
+                                format!(
+                                    "{}: {}",
+                                    name,
+                                    schema2::pretty_print_max_depth(s, max_depth)
+                                )
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    )?,
+                }
+                if !failed.is_empty() {
+                    return Err(ItemsFailed(anyhow!(
+                        "failed to fetch schemas of {} resources: {}",
+                        failed.len(),
+                        failed.join(", ")
+                    ))
+                    .into());
+                }
+                return Ok(());
+            };
+            if args.raw {
+                let xml = ws_get_resource_schema_string(&http, &Resource::new(resource)).await?;
+                output.text(&xml)?;
+                return Ok(());
+            }
+            let r = ws_get_resource_schema2(&http, &Resource::new(resource)).await?;
+            match args.format {
+                SchemaFormat::Json => output.json(args.common.pretty, std::iter::once(r))?,
+                SchemaFormat::Text => {
+                    output.text(&schema2::pretty_print_max_depth(&r, max_depth))?
+                }
+            }
+        }
+        // has no Common, so main generates it without getting here
+        Command::Completions(_) => unreachable!(),
+        Command::Languages(args) => {
+            let languages = ws_get_languages(&http, args.cache.cache().as_ref()).await?;
+            output.json(args.common.pretty, languages.iter())?;
+        }
+        Command::Create(args) => {
+            let payload = match args.input {
+                Some(path) if path.as_os_str() != "-" => std::fs::read_to_string(path)?,
+                _ => std::io::read_to_string(std::io::stdin())?,
+            };
+            let res = Resource::new(args.resource);
+            let s = ws_get_resource_schema2(&http, &res).await?;
+            let r = ws_create_resource(&http, &res, &s, payload.as_str()).await?;
+            output.json(args.common.pretty, std::iter::once(r))?;
+        }
+        Command::GetBlank(args) => {
+            let res = Resource::new(args.resource);
+            if args.raw {
+                output.text(&ws_get_resource_blank_string(&http, &res).await?)?;
+                return Ok(());
+            }
+            let s = ws_get_resource_schema2(&http, &res).await?;
+            let r = ws_get_resource_blank(&http, &res, &s).await?;
+            output.json(args.common.pretty, std::iter::once(r))?;
+        }
+        Command::GetOne(args) => {
+            let res = Resource::new(args.resource);
+            let params = args
+                .language
+                .map(QueryParam::Language)
+                .into_iter()
+                .collect::<Vec<_>>();
+            let s = ws_get_resource_schema2(&http, &res).await?;
+            let mut r = ws_get_resource_by_id(&http, &res, &s, args.id, &params).await?;
+            if let Some(max_depth) = args.json_max_depth {
+                r = schema2::truncate_json_max_depth(r, max_depth);
+            }
+            output.json(args.common.pretty, std::iter::once(r))?;
+        }
+        Command::Update(args) => {
+            let patch = match args.input {
+                Some(path) if path.as_os_str() != "-" => std::fs::read_to_string(path)?,
+                _ => std::io::read_to_string(std::io::stdin())?,
+            };
+            let res = Resource::new(args.resource);
+            let s = ws_get_resource_schema2(&http, &res).await?;
+            let r = ws_update_resource(&http, &res, &s, args.id, patch.as_str()).await?;
+            output.json(args.common.pretty, std::iter::once(r))?;
+        }
+        Command::Delete(args) => {
+            let res = Resource::new(args.resource);
+            let mut outcomes = vec![];
+            let mut failed = 0;
+            for id in args.ids.iter().copied() {
+                let outcome = match ws_delete_resource(&http, &res, id).await {
+                    Ok(()) => serde_json::json!({"id": id, "deleted": true}),
+                    Err(e) => {
+                        failed += 1;
+                        serde_json::json!({"id": id, "deleted": false, "error": format!("{:#}", e)})
+                    }
+                };
+                outcomes.push(outcome);
+            }
+            output.json(args.common.pretty, outcomes)?;
+            if failed > 0 {
+                return Err(ItemsFailed(anyhow!(
+                    "failed to delete {} of {} {}",
+                    failed,
+                    args.ids.len(),
+                    res.identifier()
+                ))
+                .into());
+            }
         }
         Command::Get(args) => {
-            let http = configure_http(args.common.conf.as_str())?;
+            let schema_cache = args.schema_cache();
             let mut params = vec![];
             match args.limit.unwrap_or_default() {
                 Limit::All => (),
-                Limit::Limit(n) => params.push(QueryParam::Limit(n)),
-                Limit::LimitFromIndex(i, n) => params.push(QueryParam::LimitFromIndex(i, n)),
+                Limit::Count(n) => params.push(QueryParam::Limit(n)),
+                Limit::FromIndex(i, n) => params.push(QueryParam::LimitFromIndex(i, n)),
             }
             if let Some(arguments::DateRange { from, to }) = args.date_add {
                 params.push(QueryParam::DateRange(DateField::DateAdd, from, to));
@@ -65,47 +319,326 @@ where
             if let Some(arguments::DateRange { from, to }) = args.date_upd {
                 params.push(QueryParam::DateRange(DateField::DateUpd, from, to));
             }
-            params.push(if let Some(fields) = args.fields {
-                QueryParam::Display(query_param::Display::Fields(fields))
-            } else {
-                QueryParam::Display(query_param::Display::Full)
-            });
+            let res = Resource::new(args.resource.clone());
+            let nested_fields = args.fields.iter().flatten().any(|f| f.contains('.'));
+            // --exclude-fields needs the schema, the display is added once it's fetched
+            if args.exclude_fields.is_none() {
+                params.push(display_param(args.fields.as_deref()));
+            }
 
             if let Some(fvi) = args.field_value_in {
                 params.push(QueryParam::FieldValueIn(fvi.field_name, fvi.values));
             }
+            for arguments::Filter { field, op, values } in args.filter.unwrap_or_default() {
+                params.push(QueryParam::Filter { field, op, values });
+            }
+            if let Some(sort) = args.sort {
+                params.push(QueryParam::Sort(sort.fields));
+            }
+            let mut price_fields = vec![];
+            for arguments::Price { field, setting } in args.price.unwrap_or_default() {
+                if !price_fields.contains(&field) {
+                    price_fields.push(field.clone());
+                }
+                params.push(QueryParam::PriceParam { field, setting });
+            }
+            if let Some(id) = args.language {
+                params.push(QueryParam::Language(id));
+            }
+            let mut selected_language = None;
+            if let Some(iso_code) = &args.select_language {
+                let languages = ws_get_languages(&http, schema_cache.as_ref()).await?;
+                let id = languages.id_of(iso_code).ok_or_else(|| {
+                    anyhow!(
+                        "no language with iso code {:?}, the shop has {:?}",
+                        iso_code,
+                        languages.iter().map(|l| &l.iso_code).collect::<Vec<_>>()
+                    )
+                })?;
+                params.push(QueryParam::Language(id as usize));
+                selected_language = Some(id);
+            }
+            let language = selected_language.or(args.language.map(|id| id as u32));
+            if let Some(id) = args.shop {
+                params.push(QueryParam::Shop(id));
+            }
+            if let Some(id) = args.shop_group {
+                params.push(QueryParam::ShopGroup(id));
+            }
             let _from = chrono::Utc::now().sub(chrono::Duration::days(60));
             let _to = chrono::Utc::now();
             //
             // let from = SystemTime::now().sub(Duration::
-            let res = Resource::new(args.resource.clone());
+            let on_error = if args.continue_on_error {
+                OnError::SkipRecord
+            } else if args.skip_errors {
+                OnError::NullValue
+            } else {
+                OnError::Fail
+            };
+            let csv_options = args.output_format_args.csv_options();
+            let json_options = args.output_format_args.json_options();
+            let compression = args.output_format_args.compression;
+            let row_group_size = args.output_format_args.row_group_size;
+            let streaming = args.streaming;
+            let progress = args.progress;
+            let casts = args
+                .cast
+                .iter()
+                .flatten()
+                .flat_map(|c| c.fields.iter().cloned())
+                .collect::<Vec<_>>();
+            if args.dry_run {
+                let params = match args.page_size {
+                    Some(page_size) => Pages::new(page_size)?.next_params(&params).unwrap(),
+                    None => params,
+                };
+                println!("{}", ws_resource_url(&http, &res, &params)?);
+                return Ok(());
+            }
+            if args.raw {
+                if let Some(excluded) = &args.exclude_fields {
+                    let s = ws_get_resource_schema2_cached(
+                        &http,
+                        &res,
+                        schema_cache.as_ref(),
+                        args.strict_schema,
+                        !args.no_id_field,
+                    )
+                    .await?;
+                    let fields = utils::exclude_field_names(excluded, &s.field_names())?;
+                    params.push(display_param(Some(&fields)));
+                }
+                output.text(&ws_get_resource_string(&http, &res, &params).await?)?;
+                return Ok(());
+            }
+            if args.count {
+                let count = ws_count_resource(&http, &res, &params).await?;
+                output.text(&count.to_string())?;
+                return Ok(());
+            }
             if args.arrow2 {
-                let s = ws_get_resource_schema3(&http, &res).await?;
-                let r = ws_get_resource2_arrow2(&http, &res, &s, &params).await?;
-                match args.output_format_args.output_format.unwrap_or_default() {
-                    OutputFormat::JSON => {
-                        output.json2(s.to_arrow2(), std::iter::once(r))?;
+                if args.dedup_on.is_some() {
+                    return Err(anyhow!("--dedup-on is not supported with --arrow2"));
+                }
+                if args.flatten_depth.is_some() {
+                    return Err(anyhow!("--flatten-depth is not supported with --arrow2"));
+                }
+                if row_group_size.is_some() {
+                    return Err(anyhow!("--row-group-size is not supported with --arrow2"));
+                }
+                if nested_fields {
+                    return Err(anyhow!("nested --fields are not supported with --arrow2"));
+                }
+                let mut s = ws_get_resource_schema3_cached(
+                    &http,
+                    &res,
+                    schema_cache.as_ref(),
+                    args.strict_schema,
+                    !args.no_id_field,
+                )
+                .await?;
+                if let Some(excluded) = &args.exclude_fields {
+                    let fields = utils::exclude_field_names(excluded, &s.field_names())?;
+                    params.push(display_param(Some(&fields)));
+                }
+                // exact like the shop's own prices, see Format::IsPrice
+                for field in &price_fields {
+                    s.add_field(field, schema3::DataType::Decimal(20, 6))?;
+                }
+                for (field, ty) in &casts {
+                    s.cast_field(field, schema3::DataType::from_cast_name(ty)?)?;
+                }
+                if args.hrefs {
+                    s = s.with_association_hrefs();
+                }
+                if let Some(fields) = &args.fields {
+                    utils::check_field_names(fields, &s.field_names())?;
+                }
+                let mut chunks: Box<dyn Iterator<Item = Result<Chunk<Box<dyn Array2>>>>> =
+                    match args.page_size {
+                        Some(page_size) => {
+                            let (http, res, s) = (&http, &res, &s);
+                            Box::new(fetch_pages(
+                                page_size,
+                                &params,
+                                Progress::new(progress),
+                                move |params| async move {
+                                    ws_get_resource2_arrow2(
+                                        http, res, s, &params, on_error, None, streaming,
+                                    )
+                                    .await
+                                },
+                            )?)
+                        }
+                        None => Box::new(std::iter::once(Ok(ws_get_resource2_arrow2(
+                            &http, &res, &s, &params, on_error, None, streaming,
+                        )
+                        .await?))),
+                    };
+                let mut arrow2_schema = s.to_arrow2();
+                if let Some(id) = language {
+                    arrow2_schema.fields = arrow2_schema
+                        .fields
+                        .iter()
+                        .map(|f| {
+                            let data_type = arrow2_utils::collapse_language_type(&f.data_type);
+                            arrow2::datatypes::Field::new(&f.name, data_type, f.is_nullable)
+                        })
+                        .collect();
+                    chunks = Box::new(chunks.map(move |r| {
+                        r.map(|chunk| {
+                            let arrays = chunk
+                                .arrays()
+                                .iter()
+                                .map(|a| arrow2_utils::collapse_languages(a.as_ref(), id))
+                                .collect();
+                            Chunk::new(arrays)
+                        })
+                    }));
+                }
+                if let Some(path) = &args.output_format_args.schema_out {
+                    write_schema_out(path, &arrow2_utils::schema_to_json(&arrow2_schema))?;
+                }
+                let mut stats = args.stats.then(Stats::default);
+                let chunks = chunks.inspect(|r| {
+                    if let (Some(stats), Ok(chunk)) = (stats.as_mut(), r) {
+                        stats.add_chunk(&arrow2_schema, chunk);
+                    }
+                });
+                let output_format = args.output_format_args.output_format.unwrap_or_default();
+                let written = tokio::task::block_in_place(|| match output_format {
+                    OutputFormat::Ndjson => {
+                        output.ndjson2(arrow2_schema.clone(), &json_options, chunks)
+                    }
+                    OutputFormat::Json => {
+                        output.json2(arrow2_schema.clone(), &json_options, chunks)
                     }
                     OutputFormat::Parquet => {
-                        output.parquet2(s.to_arrow2(), std::iter::once(r))?;
+                        output.parquet2(arrow2_schema.clone(), compression, chunks)
                     }
-                };
+                    OutputFormat::Csv => output.csv2(arrow2_schema.clone(), &csv_options, chunks),
+                    OutputFormat::Arrow => output.arrow_ipc2(arrow2_schema.clone(), chunks),
+                    OutputFormat::Avro => output.avro2(arrow2_schema.clone(), chunks),
+                });
+                if let Some(stats) = stats {
+                    eprint!("{}", stats);
+                }
+                written?;
             } else {
-                let s = ws_get_resource_schema2(&http, &res).await?;
-                let r = ws_get_resource2_arrow(&http, &res, &s, &params).await?;
-                let r = if args.flatten1 {
-                    flatten_single_toplevel_struct(&r)?
-                } else {
-                    r
+                let mut s = ws_get_resource_schema2_cached(
+                    &http,
+                    &res,
+                    schema_cache.as_ref(),
+                    args.strict_schema,
+                    !args.no_id_field,
+                )
+                .await?;
+                if let Some(excluded) = &args.exclude_fields {
+                    let fields = utils::exclude_field_names(excluded, &s.field_names())?;
+                    params.push(display_param(Some(&fields)));
+                }
+                for field in &price_fields {
+                    s.add_field(field, schema2::Type::Float64)?;
+                }
+                for (field, ty) in &casts {
+                    s.cast_field(field, schema2::Type::from_cast_name(ty)?)?;
+                }
+                if let Some(fields) = &args.fields {
+                    utils::check_field_names(&top_level_field_names(fields), &s.field_names())?;
+                }
+                if nested_fields {
+                    s.select_subfields(args.fields.as_deref().unwrap_or_default())?;
+                }
+                // pages are fetched one by one below, so they can be written as they arrive
+                let options = FetchOptions {
+                    on_error,
+                    page_size: None,
+                    streaming,
+                    flatten1: args.flatten1,
                 };
-                match args.output_format_args.output_format.unwrap_or_default() {
-                    OutputFormat::JSON => {
-                        output.arrow_json(std::iter::once(r))?;
-                    }
-                    OutputFormat::Parquet => {
-                        output.parquet(std::iter::once(r))?;
+                let batches: Box<dyn Iterator<Item = Result<RecordBatch>>> = match args.page_size {
+                    Some(page_size) => {
+                        let (http, res, s) = (&http, &res, &s);
+                        Box::new(fetch_pages(
+                            page_size,
+                            &params,
+                            Progress::new(progress),
+                            move |params| async move {
+                                fetch_resource_batch(http, res, s, &params, options).await
+                            },
+                        )?)
                     }
+                    None => Box::new(std::iter::once(Ok(fetch_resource_batch(
+                        &http, &res, &s, &params, options,
+                    )
+                    .await?))),
                 };
+                let flatten_depth = args.flatten_depth;
+                // the schema is only known after flattening, so it is taken from the first batch
+                let mut schema_out = args.output_format_args.schema_out.clone();
+                let batches = batches.map(move |r| {
+                    let r = match language {
+                        Some(id) => r.and_then(|r| transform::collapse_languages(&r, id)),
+                        None => r,
+                    };
+                    let r = match flatten_depth {
+                        Some(depth) => r.and_then(|r| transform::flatten_structs(&r, depth, ".")),
+                        None => r,
+                    };
+                    match r {
+                        Ok(batch) => match schema_out.take() {
+                            Some(path) => {
+                                let json = schema2::arrow_schema_to_json(&batch.schema());
+                                write_schema_out(&path, &json).map(|_| batch)
+                            }
+                            None => Ok(batch),
+                        },
+                        r => r,
+                    }
+                });
+                let output_format = args.output_format_args.output_format.unwrap_or_default();
+                let dedup_on = args.dedup_on;
+                let mut stats = args.stats.then(Stats::default);
+                let stats_ref = &mut stats;
+                let written = tokio::task::block_in_place(move || {
+                    let batches: Box<dyn Iterator<Item = Result<RecordBatch>>> = if let Some(keys) =
+                        dedup_on
+                    {
+                        // duplicates are removed globally, so all pages are buffered
+                        let batches = batches.collect::<Result<Vec<_>>>()?;
+                        match batches.first() {
+                            Some(first) => {
+                                let r = arrow::compute::concat_batches(&first.schema(), &batches)?;
+                                let (r, removed) = transform::dedup_batch(&r, &keys)?;
+                                info!("removed {} duplicate rows", removed);
+                                Box::new(std::iter::once(Ok(r)))
+                            }
+                            None => Box::new(std::iter::empty()),
+                        }
+                    } else {
+                        Box::new(batches)
+                    };
+                    let batches = batches.inspect(|r| {
+                        if let (Some(stats), Ok(batch)) = (stats_ref.as_mut(), r) {
+                            stats.add_batch(batch);
+                        }
+                    });
+                    match output_format {
+                        OutputFormat::Ndjson => output.arrow_ndjson(&json_options, batches),
+                        OutputFormat::Json => output.arrow_json(&json_options, batches),
+                        OutputFormat::Parquet => {
+                            output.parquet(compression, row_group_size, batches)
+                        }
+                        OutputFormat::Csv => output.csv(&csv_options, batches),
+                        OutputFormat::Arrow => output.arrow_ipc(batches),
+                        OutputFormat::Avro => Err(anyhow!("avro output requires --arrow2")),
+                    }
+                });
+                if let Some(stats) = stats {
+                    eprint!("{}", stats);
+                }
+                written?;
             }
         }
     };
@@ -114,15 +647,57 @@ where
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    utils::setup_tracing(LevelFilter::TRACE);
     let args = Arguments::parse();
-    let http = configure_http(args.get_common().conf.as_str())?;
-    if let Some(output_path) = args.get_output_path() {
-        let output = OutputFile::new(output_path);
-        run_command(args, http, output).await?;
+    utils::setup_tracing(args.log_level());
+    let Some(common) = args.get_common() else {
+        // commands without --conf don't talk to the shop
+        if let Command::Completions(completions) = &args.command {
+            completions.generate(&mut std::io::stdout());
+        }
+        return Ok(());
+    };
+    let http = common.configure_http()?;
+    if let Some(output_path) = args.get_output_path()? {
+        let file = OutputFile::via_partial(&output_path)?;
+        let result = if common.tee {
+            run_command(args, http, OutputTee::new(file)).await
+        } else {
+            run_command(args, http, file).await
+        };
+        output::finish_partial(&output_path, result)?;
     } else {
         let output = OutputStdout::new();
         run_command(args, http, output).await?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow::array::Int32Array;
+    use std::sync::Arc;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_fetch_pages_stops_at_empty_page() {
+        let sizes = [2, 2, 1, 0];
+        let fetch = |params: Vec<QueryParam>| async move {
+            let offset = match params.last() {
+                Some(QueryParam::LimitFromIndex(offset, 2)) => *offset,
+                _ => return Err(anyhow!("expected a page limit")),
+            };
+            let values = Int32Array::from(vec![0; sizes[offset / 2]]);
+            Ok(RecordBatch::try_from_iter(vec![(
+                "id",
+                Arc::new(values) as _,
+            )])?)
+        };
+        let rows = tokio::task::block_in_place(|| {
+            fetch_pages(2, &[], Progress::new(false), fetch)?
+                .map(|r| r.map(|b| b.num_rows()))
+                .collect::<Result<Vec<_>>>()
+        })
+        .unwrap();
+        assert_eq!(rows, vec![2, 2, 1]);
+    }
+}
@@ -1,67 +1,696 @@
 use anyhow::Result;
 
+use arrow::array::Array;
+use arrow::datatypes::{DataType, Fields};
 use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
 
+use arrow2::array::Utf8Array;
 use arrow2::chunk::Chunk;
+use arrow2::io::csv::write::SerializeOptions;
+use arrow2::io::json::write::{FallibleStreamingIterator, RecordSerializer};
 use arrow2::io::parquet::write::{transverse, FileWriter, RowGroupIterator, WriteOptions};
-use common::arrow2::utils::{chunk_to_array, write_ndjson};
+use common::arrow2::utils::{chunk_to_array, decimals_to_utf8, write_ndjson};
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use parquet2::compression::CompressionOptions;
 use parquet2::encoding::Encoding;
 use parquet2::write::Version;
-use std::io::Stdout;
+use std::io::{BufWriter, Stdout};
 use std::path::{Path, PathBuf};
-use tracing::info;
+use tracing::{error, info, warn};
 
+/// Dialect used by [OutputT::csv]
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            header: true,
+        }
+    }
+}
+
+/// Options of the JSON formats, [OutputT::arrow_ndjson] and the like
+#[derive(Debug, Clone, Default)]
+pub struct JsonOptions {
+    /// Leave out null fields instead of writing `"field":null`
+    pub skip_nulls: bool,
+}
+
+impl JsonOptions {
+    fn apply(&self, row: &mut serde_json::Value) {
+        if self.skip_nulls {
+            remove_nulls(row);
+        }
+    }
+
+    /// Applies the options to rows of newline delimited JSON
+    fn apply_ndjson(&self, ndjson: Vec<u8>) -> Result<Vec<u8>> {
+        if !self.skip_nulls {
+            return Ok(ndjson);
+        }
+        let mut applied = Vec::with_capacity(ndjson.len());
+        for line in ndjson.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            let mut row: serde_json::Value = serde_json::from_slice(line)?;
+            self.apply(&mut row);
+            serde_json::to_writer(&mut applied, &row)?;
+            applied.push(b'\n');
+        }
+        Ok(applied)
+    }
+}
+
+/// Removes null fields from objects, also nested ones. Nulls in arrays are kept
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            fields.retain(|_, v| !v.is_null());
+            fields.values_mut().for_each(remove_nulls);
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// Compression codec of Parquet column chunks
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+pub enum ParquetCompression {
+    None,
+    Snappy,
+    #[default]
+    Zstd,
+    Gzip,
+}
+
+impl ParquetCompression {
+    fn to_parquet(self) -> parquet::basic::Compression {
+        use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+        match self {
+            ParquetCompression::None => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+            ParquetCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+        }
+    }
+
+    fn to_parquet2(self) -> CompressionOptions {
+        match self {
+            ParquetCompression::None => CompressionOptions::Uncompressed,
+            ParquetCompression::Snappy => CompressionOptions::Snappy,
+            ParquetCompression::Zstd => CompressionOptions::Zstd(None),
+            ParquetCompression::Gzip => CompressionOptions::Gzip(None),
+        }
+    }
+}
+
+fn is_nested(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Struct(_)
+            | DataType::List(_)
+            | DataType::LargeList(_)
+            | DataType::FixedSizeList(_, _)
+            | DataType::Map(_, _)
+    )
+}
+
+enum CsvCell<'a> {
+    Display(ArrayFormatter<'a>),
+    /// nested values can't be represented as a single CSV value, so they are JSON-encoded
+    Json(Vec<String>),
+}
+
+impl<'a> CsvCell<'a> {
+    fn try_new(array: &'a dyn Array, options: &'a FormatOptions) -> Result<Self> {
+        if is_nested(array.data_type()) {
+            let values = arrow::json::writer::array_to_json_array(array)?
+                .into_iter()
+                .map(|v| {
+                    if v.is_null() {
+                        String::new()
+                    } else {
+                        v.to_string()
+                    }
+                })
+                .collect();
+            Ok(CsvCell::Json(values))
+        } else {
+            Ok(CsvCell::Display(ArrayFormatter::try_new(array, options)?))
+        }
+    }
+    fn value(&self, row: usize) -> String {
+        match self {
+            CsvCell::Display(f) => f.value(row).to_string(),
+            CsvCell::Json(values) => values[row].clone(),
+        }
+    }
+}
+
+/// Writes batches as CSV, returning the number of rows written
+pub fn write_csv<W, I>(writer: W, options: &CsvOptions, iter: I) -> Result<usize>
+where
+    W: std::io::Write,
+    I: IntoIterator<Item = RecordBatch>,
+{
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .quote(options.quote)
+        .from_writer(writer);
+    let format_options = FormatOptions::default();
+    let mut header_written = !options.header;
+    let mut total = 0;
+    for batch in iter {
+        if !header_written {
+            writer.write_record(batch.schema().fields().iter().map(|f| f.name()))?;
+            header_written = true;
+        }
+        let cells = batch
+            .columns()
+            .iter()
+            .map(|c| CsvCell::try_new(c.as_ref(), &format_options))
+            .collect::<Result<Vec<_>>>()?;
+        for row in 0..batch.num_rows() {
+            writer.write_record(cells.iter().map(|c| c.value(row)))?;
+        }
+        total += batch.num_rows();
+    }
+    writer.flush()?;
+    Ok(total)
+}
+
+fn json_encode_nested2(
+    schema: &arrow2::datatypes::Schema,
+    chunk: Chunk<Box<dyn arrow2::array::Array>>,
+) -> Result<Chunk<Box<dyn arrow2::array::Array>>> {
+    let mut arrays = vec![];
+    for (field, array) in schema.fields.iter().zip(chunk.into_arrays()) {
+        let array = decimals_to_utf8(array.as_ref());
+        if !matches!(
+            array.data_type(),
+            arrow2::datatypes::DataType::Struct(_) | arrow2::datatypes::DataType::List(_)
+        ) {
+            arrays.push(array);
+            continue;
+        }
+        let field = arrow2::datatypes::Field::new(
+            &field.name,
+            array.data_type().clone(),
+            field.is_nullable,
+        );
+        let column_schema = arrow2::datatypes::Schema::from(vec![field.clone()]);
+        let column = Chunk::new(vec![array]);
+        let mut rows = RecordSerializer::new(column_schema, &column, vec![]);
+        let mut values = vec![];
+        while let Some(row) = rows.next()? {
+            let mut row: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(row)?;
+            values.push(match row.remove(&field.name) {
+                None | Some(serde_json::Value::Null) => None,
+                Some(v) => Some(v.to_string()),
+            });
+        }
+        arrays.push(Utf8Array::<i32>::from(values).boxed());
+    }
+    Ok(Chunk::new(arrays))
+}
+
+/// Writes arrow2 chunks as CSV, returning the number of rows written
+pub fn write_csv2<W, I>(
+    mut writer: W,
+    schema: &arrow2::datatypes::Schema,
+    options: &CsvOptions,
+    iter: I,
+) -> Result<usize>
+where
+    W: std::io::Write,
+    I: IntoIterator<Item = Chunk<Box<dyn arrow2::array::Array>>>,
+{
+    let serialize_options = SerializeOptions {
+        delimiter: options.delimiter,
+        quote: options.quote,
+        ..SerializeOptions::default()
+    };
+    if options.header {
+        let names = schema
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        arrow2::io::csv::write::write_header(&mut writer, &names, &serialize_options)?;
+    }
+    let mut total = 0;
+    for chunk in iter {
+        let chunk = json_encode_nested2(schema, chunk)?;
+        arrow2::io::csv::write::write_chunk(&mut writer, &chunk, &serialize_options)?;
+        total += chunk.len();
+    }
+    writer.flush()?;
+    Ok(total)
+}
+
+/// Avro names are restricted to `[A-Za-z_][A-Za-z0-9_]*`, e.g. `@id` becomes `_id`
+fn avro_name(name: &str) -> String {
+    let mut out = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn avro_field(field: &arrow2::datatypes::Field) -> arrow2::datatypes::Field {
+    arrow2::datatypes::Field::new(
+        avro_name(&field.name),
+        avro_data_type(&field.data_type),
+        field.is_nullable,
+    )
+}
+
+/// Avro has no unsigned integers and no timestamps in seconds
+fn avro_data_type(data_type: &arrow2::datatypes::DataType) -> arrow2::datatypes::DataType {
+    use arrow2::datatypes::{DataType, TimeUnit};
+    match data_type {
+        DataType::UInt32 | DataType::UInt64 => DataType::Int64,
+        DataType::Timestamp(TimeUnit::Second, tz) => {
+            DataType::Timestamp(TimeUnit::Millisecond, tz.clone())
+        }
+        DataType::Struct(fields) => DataType::Struct(fields.iter().map(avro_field).collect()),
+        DataType::List(field) => DataType::List(Box::new(avro_field(field))),
+        other => other.clone(),
+    }
+}
+
+/// Converts `array` to `data_type` as returned by [avro_data_type]
+fn avro_array(
+    array: &dyn arrow2::array::Array,
+    data_type: &arrow2::datatypes::DataType,
+) -> Result<Box<dyn arrow2::array::Array>> {
+    use arrow2::array::{ListArray, PrimitiveArray, StructArray};
+    use arrow2::datatypes::{DataType, TimeUnit};
+    fn downcast<T: 'static>(array: &dyn arrow2::array::Array) -> &T {
+        array
+            .as_any()
+            .downcast_ref::<T>()
+            .expect("array of its data type")
+    }
+    Ok(match (array.data_type(), data_type) {
+        (DataType::UInt32, DataType::Int64) => {
+            let values = downcast::<PrimitiveArray<u32>>(array)
+                .iter()
+                .map(|v| v.map(|v| *v as i64))
+                .collect::<Vec<_>>();
+            PrimitiveArray::<i64>::from(values).boxed()
+        }
+        (DataType::UInt64, DataType::Int64) => {
+            let values = downcast::<PrimitiveArray<u64>>(array)
+                .iter()
+                .map(|v| v.map(|v| i64::try_from(*v)).transpose())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| anyhow::anyhow!("value does not fit an avro long: {}", e))?;
+            PrimitiveArray::<i64>::from(values).boxed()
+        }
+        (
+            DataType::Timestamp(TimeUnit::Second, _),
+            DataType::Timestamp(TimeUnit::Millisecond, _),
+        ) => {
+            let values = downcast::<PrimitiveArray<i64>>(array)
+                .iter()
+                .map(|v| v.map(|v| v * 1000))
+                .collect::<Vec<_>>();
+            PrimitiveArray::<i64>::from(values)
+                .to(data_type.clone())
+                .boxed()
+        }
+        (DataType::Struct(_), DataType::Struct(fields)) => {
+            let array = downcast::<StructArray>(array);
+            let values = array
+                .values()
+                .iter()
+                .zip(fields)
+                .map(|(v, f)| avro_array(v.as_ref(), &f.data_type))
+                .collect::<Result<Vec<_>>>()?;
+            StructArray::new(data_type.clone(), values, array.validity().cloned()).boxed()
+        }
+        (DataType::List(_), DataType::List(field)) => {
+            let array = downcast::<ListArray<i32>>(array);
+            ListArray::<i32>::new(
+                data_type.clone(),
+                array.offsets().clone(),
+                avro_array(array.values().as_ref(), &field.data_type)?,
+                array.validity().cloned(),
+            )
+            .boxed()
+        }
+        _ => array.to_boxed(),
+    })
+}
+
+/// Number of rows in a batch, for progress and partial export reporting
+pub trait BatchLen {
+    fn batch_len(&self) -> usize;
+}
+
+impl BatchLen for RecordBatch {
+    fn batch_len(&self) -> usize {
+        self.num_rows()
+    }
+}
+
+impl BatchLen for Chunk<Box<dyn arrow2::array::Array>> {
+    fn batch_len(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Returned when a batch fails after earlier batches were already written.
+/// The output is still finalized so that it holds the batches before the failure,
+/// though a file from [OutputFile::via_partial] is left at its partial path.
+#[derive(Debug)]
+pub struct PartialExport {
+    pub batches: usize,
+    pub rows: usize,
+    pub source: anyhow::Error,
+}
+
+impl std::fmt::Display for PartialExport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "export is partial: wrote {} rows from {} batches, stopped at batch {}: {:#}",
+            self.rows,
+            self.batches,
+            self.batches + 1,
+            self.source
+        )
+    }
+}
+
+impl std::error::Error for PartialExport {}
+
+/// Returned by commands that wrote a complete report of their items, some of
+/// which failed, e.g. `delete` of several ids. [finish_partial] still moves the
+/// report into place
+#[derive(Debug)]
+pub struct ItemsFailed(pub anyhow::Error);
+
+impl std::fmt::Display for ItemsFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for ItemsFailed {}
+
+/// Writes rows as a single pretty printed JSON array, taking them as
+/// newline delimited JSON so that it can follow the NDJSON writers
+struct JsonArrayWriter<W: std::io::Write> {
+    writer: W,
+    rows: usize,
+}
+
+impl<W: std::io::Write> JsonArrayWriter<W> {
+    fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(b"[")?;
+        Ok(Self { writer, rows: 0 })
+    }
+
+    fn write_ndjson(&mut self, ndjson: &[u8]) -> Result<()> {
+        for line in ndjson.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            let row: serde_json::Value = serde_json::from_slice(line)?;
+            self.writer
+                .write_all(if self.rows == 0 { b"\n  " } else { b",\n  " })?;
+            // newlines within strings are escaped, so this only indents
+            let row = serde_json::to_string_pretty(&row)?.replace('\n', "\n  ");
+            self.writer.write_all(row.as_bytes())?;
+            self.rows += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<usize> {
+        self.writer
+            .write_all(if self.rows == 0 { b"]\n" } else { b"\n]\n" })?;
+        self.writer.flush()?;
+        Ok(self.rows)
+    }
+}
+
+/// Newline delimited JSON of `batch` in the shape of arrow2's writer, so that
+/// the JSON formats don't depend on --arrow2: null fields are written as
+/// `null` rather than left out, and timestamps are `2020-09-13 12:26:40`
+fn batch_to_ndjson(batch: &RecordBatch, options: &JsonOptions) -> Result<Vec<u8>> {
+    let mut ndjson = vec![];
+    for row in arrow::json::writer::record_batches_to_json_rows(&[batch])? {
+        let mut row = serde_json::Value::Object(fill_json_row(batch.schema().fields(), row));
+        options.apply(&mut row);
+        serde_json::to_writer(&mut ndjson, &row)?;
+        ndjson.push(b'\n');
+    }
+    Ok(ndjson)
+}
+
+/// Rebuilds a row in the order of `fields`, as the arrow writer leaves out
+/// null fields and they would otherwise be appended at the end
+fn fill_json_row(
+    fields: &Fields,
+    mut row: serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Map<String, serde_json::Value> {
+    fields
+        .iter()
+        .map(|field| {
+            let mut value = row.remove(field.name()).unwrap_or_default();
+            fill_json_value(field.data_type(), &mut value);
+            (field.name().clone(), value)
+        })
+        .collect()
+}
+
+fn fill_json_value(data_type: &DataType, value: &mut serde_json::Value) {
+    match (data_type, value) {
+        (DataType::Struct(fields), serde_json::Value::Object(row)) => {
+            *row = fill_json_row(fields, std::mem::take(row))
+        }
+        (DataType::List(field), serde_json::Value::Array(values)) => {
+            for value in values.iter_mut() {
+                fill_json_value(field.data_type(), value);
+            }
+        }
+        (DataType::Timestamp(_, None), serde_json::Value::String(s)) => {
+            *s = s.replacen('T', " ", 1);
+        }
+        _ => {}
+    }
+}
+
+/// Yields batches until the first error, which is kept for [UntilError::finish]
+struct UntilError<I> {
+    iter: I,
+    batches: usize,
+    rows: usize,
+    error: Option<anyhow::Error>,
+}
+
+impl<I> UntilError<I> {
+    fn new<T>(iter: impl IntoIterator<IntoIter = I>) -> Self
+    where
+        I: Iterator<Item = Result<T>>,
+    {
+        Self {
+            iter: iter.into_iter(),
+            batches: 0,
+            rows: 0,
+            error: None,
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self.error {
+            None => Ok(()),
+            Some(source) if self.batches == 0 => Err(source),
+            Some(source) => {
+                let partial = PartialExport {
+                    batches: self.batches,
+                    rows: self.rows,
+                    source,
+                };
+                error!("{}", partial);
+                Err(partial.into())
+            }
+        }
+    }
+}
+
+impl<I, T> Iterator for UntilError<I>
+where
+    I: Iterator<Item = Result<T>>,
+    T: BatchLen,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.iter.next()? {
+            Ok(batch) => {
+                self.batches += 1;
+                self.rows += batch.batch_len();
+                Some(batch)
+            }
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+}
+
+/// Batch writing methods take fallible iterators: batches are written as they
+/// arrive and on the first error the output is finalized with what was written
+/// so far, returning a [PartialExport] error.
 pub trait OutputT<W>
 where
     W: std::io::Write + Send,
 {
     fn to_writer(&self) -> Result<W>;
 
-    #[tracing::instrument(skip(self, iter))]
-    fn json2<I>(self, schema: arrow2::datatypes::Schema, iter: I) -> Result<()>
+    /// Writer for the text formats, which may compress unlike [OutputT::to_writer]
+    fn to_text_writer(&self) -> Result<W> {
+        self.to_writer()
+    }
+
+    /// Completes the output of a writer from [OutputT::to_text_writer], e.g.
+    /// the trailer of a gzip file, returning the errors that dropping it hides
+    fn finish_writer(mut writer: W) -> Result<()>
     where
-        I: IntoIterator<Item = Chunk<Box<dyn arrow2::array::Array>>>,
         Self: Sized,
     {
-        let iter = iter.into_iter().map(|chunk| chunk_to_array(&schema, chunk));
-        write_ndjson(self.to_writer()?, iter);
+        writer.flush()?;
         Ok(())
     }
 
+    /// Writes one JSON object per line
+    #[tracing::instrument(skip(self, iter))]
+    fn ndjson2<I>(
+        self,
+        schema: arrow2::datatypes::Schema,
+        options: &JsonOptions,
+        iter: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<Chunk<Box<dyn arrow2::array::Array>>>>,
+        Self: Sized,
+    {
+        let mut batches = UntilError::new(iter);
+        let iter = batches
+            .by_ref()
+            .map(|chunk| decimals_to_utf8(chunk_to_array(&schema, chunk).as_ref()));
+        let mut writer = self.to_text_writer()?;
+        for array in iter {
+            let mut ndjson = vec![];
+            write_ndjson(&mut ndjson, std::iter::once(array));
+            if options.skip_nulls {
+                ndjson = options.apply_ndjson(ndjson)?;
+            }
+            writer.write_all(&ndjson)?;
+            // a chunk is a page, flushed for the same reason as in arrow_ndjson
+            writer.flush()?;
+        }
+        Self::finish_writer(writer)?;
+        batches.finish()
+    }
+
+    /// Writes a single pretty printed JSON array of objects
     #[tracing::instrument(skip(self, iter))]
-    fn parquet<I>(self, iter: I) -> Result<()>
+    fn json2<I>(
+        self,
+        schema: arrow2::datatypes::Schema,
+        options: &JsonOptions,
+        iter: I,
+    ) -> Result<()>
     where
-        I: IntoIterator<Item = RecordBatch>,
+        I: IntoIterator<Item = Result<Chunk<Box<dyn arrow2::array::Array>>>>,
         Self: Sized,
     {
-        let mut iter = iter.into_iter();
-        let first = if let Some(batch) = iter.next() {
+        let mut batches = UntilError::new(iter);
+        let mut writer = self.to_text_writer()?;
+        let mut json = JsonArrayWriter::new(&mut writer)?;
+        for chunk in batches.by_ref() {
+            let array = decimals_to_utf8(chunk_to_array(&schema, chunk).as_ref());
+            let mut ndjson = vec![];
+            write_ndjson(&mut ndjson, std::iter::once(array));
+            json.write_ndjson(&options.apply_ndjson(ndjson)?)?;
+        }
+        let total = json.finish()?;
+        Self::finish_writer(writer)?;
+        info!("wrote {} rows", total);
+        batches.finish()
+    }
+
+    #[tracing::instrument(skip(self, iter))]
+    /// Rows are buffered across batches and written in row groups of
+    /// `row_group_size` rows, or the writer's default
+    fn parquet<I>(
+        self,
+        compression: ParquetCompression,
+        row_group_size: Option<usize>,
+        iter: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<RecordBatch>>,
+        Self: Sized,
+    {
+        if row_group_size == Some(0) {
+            return Err(anyhow::anyhow!("row group size must be at least 1"));
+        }
+        let mut batches = UntilError::new(iter);
+        let first = if let Some(batch) = batches.next() {
             batch
         } else {
-            return Ok(());
+            return batches.finish();
         };
-        let mut writer = self.to_writer()?;
-        let mut writer = parquet::arrow::ArrowWriter::try_new(&mut writer, first.schema(), None)?;
+        let mut file = self.to_writer()?;
+        let mut props = parquet::file::properties::WriterProperties::builder()
+            .set_compression(compression.to_parquet());
+        if let Some(rows) = row_group_size {
+            props = props.set_max_row_group_size(rows);
+        }
+        let mut writer =
+            parquet::arrow::ArrowWriter::try_new(&mut file, first.schema(), Some(props.build()))?;
         let mut total = first.num_rows();
         writer.write(&first)?;
-        for other in iter {
+        for other in batches.by_ref() {
             total += other.num_rows();
             writer.write(&other)?;
         }
         info!("wrote {} rows", total);
         writer.close()?;
-        Ok(())
+        file.flush()?;
+        batches.finish()
     }
     #[tracing::instrument(skip(self, iter))]
-    fn parquet2<I>(self, schema: arrow2::datatypes::Schema, iter: I) -> Result<()>
+    fn parquet2<I>(
+        self,
+        schema: arrow2::datatypes::Schema,
+        compression: ParquetCompression,
+        iter: I,
+    ) -> Result<()>
     where
-        I: IntoIterator<Item = Chunk<Box<dyn arrow2::array::Array>>>,
+        I: IntoIterator<Item = Result<Chunk<Box<dyn arrow2::array::Array>>>>,
         Self: Sized,
     {
         let options = WriteOptions {
             write_statistics: true,
-            compression: CompressionOptions::Uncompressed,
+            compression: compression.to_parquet2(),
             version: Version::V2,
             data_pagesize_limit: None,
         };
@@ -72,60 +701,209 @@ where
             .map(|f| transverse(&f.data_type, |_| Encoding::Plain))
             .collect();
 
-        let row_groups = RowGroupIterator::try_new(
-            iter.into_iter().map(|x| Ok(x)),
-            &schema,
-            options,
-            encodings,
-        )?;
+        let mut batches = UntilError::new(iter);
+        let row_groups =
+            RowGroupIterator::try_new(batches.by_ref().map(Ok), &schema, options, encodings)?;
         let file = self.to_writer()?;
         let mut writer = FileWriter::try_new(file, schema, options)?;
         for group in row_groups {
             writer.write(group?)?;
         }
         let sz = writer.end(None)?;
+        writer.into_inner().flush()?;
         info!("wrote {} bytes", sz);
-        Ok(())
+        batches.finish()
     }
+    /// Avro object container file with deflate compressed blocks, one per chunk
     #[tracing::instrument(skip(self, iter))]
-    fn arrow_json<I>(self, iter: I) -> Result<()>
+    fn avro2<I>(self, schema: arrow2::datatypes::Schema, iter: I) -> Result<()>
     where
-        I: IntoIterator<Item = RecordBatch>,
+        I: IntoIterator<Item = Result<Chunk<Box<dyn arrow2::array::Array>>>>,
         Self: Sized,
     {
-        let mut iter = iter.into_iter();
-        let first = if let Some(batch) = iter.next() {
+        use arrow2::io::avro::avro_schema;
+        use arrow2::io::avro::write;
+        let compression = Some(avro_schema::file::Compression::Deflate);
+        let fields = schema.fields.iter().map(avro_field).collect::<Vec<_>>();
+        let mut record = write::to_record(&arrow2::datatypes::Schema::from(fields.clone()))?;
+        record.name = "record".to_string();
+        let mut batches = UntilError::new(iter);
+        let mut writer = self.to_writer()?;
+        avro_schema::write::write_metadata(&mut writer, record.clone(), compression)
+            .map_err(arrow2::error::Error::from)?;
+        let mut total = 0;
+        for chunk in batches.by_ref() {
+            let arrays = chunk
+                .arrays()
+                .iter()
+                .zip(&fields)
+                .map(|(array, field)| avro_array(array.as_ref(), &field.data_type))
+                .collect::<Result<Vec<_>>>()?;
+            let mut serializers = arrays
+                .iter()
+                .zip(&record.fields)
+                .map(|(array, field)| write::new_serializer(array.as_ref(), &field.schema))
+                .collect::<Vec<_>>();
+            let mut block = avro_schema::file::Block::new(chunk.len(), vec![]);
+            write::serialize(&mut serializers, &mut block);
+            let mut compressed = avro_schema::file::CompressedBlock::default();
+            avro_schema::write::compress(&mut block, &mut compressed, compression)
+                .map_err(arrow2::error::Error::from)?;
+            avro_schema::write::write_block(&mut writer, &compressed)
+                .map_err(arrow2::error::Error::from)?;
+            total += chunk.len();
+        }
+        info!("wrote {} rows", total);
+        writer.flush()?;
+        batches.finish()
+    }
+    /// Arrow IPC file format, also known as Feather v2
+    #[tracing::instrument(skip(self, iter))]
+    fn arrow_ipc<I>(self, iter: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<RecordBatch>>,
+        Self: Sized,
+    {
+        let mut batches = UntilError::new(iter);
+        let first = if let Some(batch) = batches.next() {
             batch
         } else {
-            return Ok(());
+            return batches.finish();
         };
         let writer = self.to_writer()?;
-        let mut writer = arrow::json::LineDelimitedWriter::new(writer);
+        let mut writer = arrow::ipc::writer::FileWriter::try_new(writer, &first.schema())?;
         let mut total = first.num_rows();
         writer.write(&first)?;
-        for other in iter {
+        for other in batches.by_ref() {
             total += other.num_rows();
             writer.write(&other)?;
         }
         info!("wrote {} rows", total);
+        writer.into_inner()?.flush()?;
+        batches.finish()
+    }
+    #[tracing::instrument(skip(self, iter))]
+    fn arrow_ipc2<I>(self, schema: arrow2::datatypes::Schema, iter: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<Chunk<Box<dyn arrow2::array::Array>>>>,
+        Self: Sized,
+    {
+        let mut batches = UntilError::new(iter);
+        let options = arrow2::io::ipc::write::WriteOptions { compression: None };
+        let mut writer =
+            arrow2::io::ipc::write::FileWriter::try_new(self.to_writer()?, schema, None, options)?;
+        let mut total = 0;
+        for chunk in batches.by_ref() {
+            total += chunk.len();
+            writer.write(&chunk, None)?;
+        }
+        info!("wrote {} rows", total);
         writer.finish()?;
-        Ok(())
+        writer.into_inner().flush()?;
+        batches.finish()
+    }
+    #[tracing::instrument(skip(self, iter))]
+    fn arrow_ndjson<I>(self, options: &JsonOptions, iter: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<RecordBatch>>,
+        Self: Sized,
+    {
+        let mut batches = UntilError::new(iter);
+        let first = if let Some(batch) = batches.next() {
+            batch
+        } else {
+            return batches.finish();
+        };
+        let mut writer = self.to_text_writer()?;
+        let mut total = 0;
+        for batch in std::iter::once(first).chain(batches.by_ref()) {
+            total += batch.num_rows();
+            writer.write_all(&batch_to_ndjson(&batch, options)?)?;
+            // so that e.g. `| head` sees every page as soon as it's fetched
+            writer.flush()?;
+        }
+        Self::finish_writer(writer)?;
+        info!("wrote {} rows", total);
+        batches.finish()
+    }
+
+    /// Writes a single pretty printed JSON array of objects
+    #[tracing::instrument(skip(self, iter))]
+    fn arrow_json<I>(self, options: &JsonOptions, iter: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<RecordBatch>>,
+        Self: Sized,
+    {
+        let mut batches = UntilError::new(iter);
+        let mut writer = self.to_text_writer()?;
+        let mut json = JsonArrayWriter::new(&mut writer)?;
+        for batch in batches.by_ref() {
+            json.write_ndjson(&batch_to_ndjson(&batch, options)?)?;
+        }
+        let total = json.finish()?;
+        Self::finish_writer(writer)?;
+        info!("wrote {} rows", total);
+        batches.finish()
+    }
+
+    #[tracing::instrument(skip(self, iter))]
+    fn csv<I>(self, options: &CsvOptions, iter: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<RecordBatch>>,
+        Self: Sized,
+    {
+        let mut batches = UntilError::new(iter);
+        let mut writer = self.to_text_writer()?;
+        let total = write_csv(&mut writer, options, batches.by_ref())?;
+        Self::finish_writer(writer)?;
+        info!("wrote {} rows", total);
+        batches.finish()
+    }
+
+    #[tracing::instrument(skip(self, iter))]
+    fn csv2<I>(self, schema: arrow2::datatypes::Schema, options: &CsvOptions, iter: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Result<Chunk<Box<dyn arrow2::array::Array>>>>,
+        Self: Sized,
+    {
+        let mut batches = UntilError::new(iter);
+        let mut writer = self.to_text_writer()?;
+        let total = write_csv2(&mut writer, &schema, options, batches.by_ref())?;
+        Self::finish_writer(writer)?;
+        info!("wrote {} rows", total);
+        batches.finish()
+    }
+
+    #[tracing::instrument(skip(self, text))]
+    fn text(self, text: &str) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let mut writer = self.to_text_writer()?;
+        writer.write_all(text.as_bytes())?;
+        writer.write_all(b"\n")?;
+        Self::finish_writer(writer)
     }
 
     #[tracing::instrument(skip(self, iter))]
-    fn json<I, A>(self, iter: I) -> Result<()>
+    fn json<I, A>(self, pretty: bool, iter: I) -> Result<()>
     where
         A: serde::Serialize,
         I: IntoIterator<Item = A>,
         Self: Sized,
     {
-        let mut writer = self.to_writer()?;
+        let mut writer = self.to_text_writer()?;
         let mut total = 0;
         for a in iter {
-            serde_json::to_writer(&mut writer, &a)?;
-            writer.write(b"\n")?;
+            if pretty {
+                serde_json::to_writer_pretty(&mut writer, &a)?;
+            } else {
+                serde_json::to_writer(&mut writer, &a)?;
+            }
+            writer.write_all(b"\n")?;
             total += 1;
         }
+        Self::finish_writer(writer)?;
         info!("wrote {} rows", total);
         Ok(())
     }
@@ -133,17 +911,140 @@ where
 
 pub struct OutputFile {
     path: PathBuf,
+    partial: bool,
 }
 impl OutputFile {
     pub fn new<A: AsRef<Path>>(path: A) -> Self {
         OutputFile {
             path: path.as_ref().to_path_buf(),
+            partial: false,
+        }
+    }
+
+    /// Writes to [partial_path] instead, so that the file at `path` only
+    /// changes once [finish_partial] moves over a complete output. A partial
+    /// file left by an earlier run is removed, so that it can't be taken for
+    /// the output of this one
+    pub fn via_partial<A: AsRef<Path>>(path: A) -> Result<Self> {
+        let partial = partial_path(path.as_ref());
+        if partial.exists() {
+            warn!("removing {} left by an earlier run", partial.display());
+            std::fs::remove_file(&partial)?;
+        }
+        Ok(OutputFile {
+            partial: true,
+            ..Self::new(path)
+        })
+    }
+}
+
+/// Where [OutputFile::via_partial] writes: `path` with `.partial` appended
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+/// Renames the output written via [OutputFile::via_partial] to `path` when
+/// `result` is ok or [ItemsFailed]. A [PartialExport] keeps it at its partial
+/// path, any other error removes it and leaves the file at `path` untouched
+pub fn finish_partial(path: &Path, result: Result<()>) -> Result<()> {
+    let partial = partial_path(path);
+    if !partial.exists() {
+        return result;
+    }
+    match result {
+        Ok(()) => {
+            std::fs::rename(&partial, path)?;
+            Ok(())
+        }
+        Err(e) if e.is::<ItemsFailed>() => {
+            std::fs::rename(&partial, path)?;
+            Err(e)
+        }
+        Err(e) if e.is::<PartialExport>() => {
+            error!("kept the partial output in {}", partial.display());
+            Err(e)
+        }
+        Err(e) => {
+            if let Err(remove_error) = std::fs::remove_file(&partial) {
+                error!("failed to remove {}: {}", partial.display(), remove_error);
+            }
+            Err(e)
         }
     }
 }
-impl OutputT<std::fs::File> for OutputFile {
-    fn to_writer(&self) -> Result<std::fs::File> {
-        Ok(std::fs::File::create(&self.path)?)
+
+/// A plain output file, or a gzip compressed one. Both are buffered, so
+/// writers must be completed with [OutputFileWriter::finish] once done
+pub enum OutputFileWriter {
+    Plain(BufWriter<std::fs::File>),
+    Gzip(BufWriter<GzEncoder<std::fs::File>>),
+}
+
+impl std::io::Write for OutputFileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFileWriter::Plain(w) => w.write(buf),
+            OutputFileWriter::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFileWriter::Plain(w) => w.flush(),
+            OutputFileWriter::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+impl OutputFileWriter {
+    /// Flushes the buffer and writes the gzip trailer
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            OutputFileWriter::Plain(mut w) => std::io::Write::flush(&mut w),
+            OutputFileWriter::Gzip(w) => {
+                w.into_inner().map_err(|e| e.into_error())?.finish()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl OutputFile {
+    fn create(&self) -> Result<std::fs::File> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if self.partial {
+            Ok(std::fs::File::create(partial_path(&self.path))?)
+        } else {
+            Ok(std::fs::File::create(&self.path)?)
+        }
+    }
+}
+
+impl OutputT<OutputFileWriter> for OutputFile {
+    fn to_writer(&self) -> Result<OutputFileWriter> {
+        Ok(OutputFileWriter::Plain(BufWriter::new(self.create()?)))
+    }
+
+    /// Compresses when the path ends in `.gz`. Binary formats compress
+    /// internally and always use [OutputT::to_writer]
+    fn to_text_writer(&self) -> Result<OutputFileWriter> {
+        let file = self.create()?;
+        if self.path.extension().is_some_and(|ext| ext == "gz") {
+            Ok(OutputFileWriter::Gzip(BufWriter::new(GzEncoder::new(
+                file,
+                Compression::default(),
+            ))))
+        } else {
+            Ok(OutputFileWriter::Plain(BufWriter::new(file)))
+        }
+    }
+
+    fn finish_writer(writer: OutputFileWriter) -> Result<()> {
+        Ok(writer.finish()?)
     }
 }
 impl OutputStdout {
@@ -153,8 +1054,582 @@ impl OutputStdout {
 }
 
 pub struct OutputStdout {}
-impl OutputT<Stdout> for OutputStdout {
-    fn to_writer(&self) -> Result<Stdout> {
-        Ok(std::io::stdout())
+/// Buffered, as stdout itself only buffers up to the end of each line
+impl OutputT<BufWriter<Stdout>> for OutputStdout {
+    fn to_writer(&self) -> Result<BufWriter<Stdout>> {
+        Ok(BufWriter::new(std::io::stdout()))
+    }
+}
+
+/// Writes everything to both writers
+pub struct TeeWriter<A, B>(A, B);
+
+impl<A: std::io::Write, B: std::io::Write> std::io::Write for TeeWriter<A, B> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+/// Writes to a file and to stdout at once. A `.gz` file is compressed, stdout is not
+pub struct OutputTee {
+    file: OutputFile,
+}
+
+impl OutputTee {
+    pub fn new(file: OutputFile) -> Self {
+        Self { file }
+    }
+}
+
+impl OutputT<TeeWriter<OutputFileWriter, BufWriter<Stdout>>> for OutputTee {
+    fn to_writer(&self) -> Result<TeeWriter<OutputFileWriter, BufWriter<Stdout>>> {
+        Ok(TeeWriter(
+            self.file.to_writer()?,
+            OutputStdout::new().to_writer()?,
+        ))
+    }
+
+    fn finish_writer(writer: TeeWriter<OutputFileWriter, BufWriter<Stdout>>) -> Result<()> {
+        let TeeWriter(file, mut stdout) = writer;
+        std::io::Write::flush(&mut stdout)?;
+        Ok(file.finish()?)
+    }
+
+    fn to_text_writer(&self) -> Result<TeeWriter<OutputFileWriter, BufWriter<Stdout>>> {
+        Ok(TeeWriter(
+            self.file.to_text_writer()?,
+            OutputStdout::new().to_writer()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow::array::{Int32Array, StringArray, StructArray};
+    use arrow::datatypes::Field;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_tee_writer() {
+        use std::io::Write;
+        let mut tee = TeeWriter(vec![], vec![]);
+        write!(tee, "a").unwrap();
+        tee.write_all(b"bc").unwrap();
+        tee.flush().unwrap();
+        let TeeWriter(a, b) = tee;
+        assert_eq!(a, b"abc");
+        assert_eq!(b, b"abc");
+    }
+
+    #[test]
+    fn test_ndjson_matches_arrow2() {
+        use arrow::array::TimestampSecondArray;
+        use arrow2::array as a2;
+        use arrow2::datatypes as d2;
+        let ids = Arc::new(Int32Array::from(vec![Some(1), None, None])) as _;
+        let dates = vec![Some(1_600_000_000), None, Some(1_600_000_000)];
+        let batch = RecordBatch::try_from_iter(vec![
+            ("id", ids),
+            ("date", Arc::new(TimestampSecondArray::from(dates)) as _),
+        ])
+        .unwrap();
+        let ndjson =
+            String::from_utf8(batch_to_ndjson(&batch, &JsonOptions::default()).unwrap()).unwrap();
+        // a null id stays in front of the date
+        assert_eq!(
+            ndjson,
+            "{\"id\":1,\"date\":\"2020-09-13 12:26:40\"}\n{\"id\":null,\"date\":null}\n\
+             {\"id\":null,\"date\":\"2020-09-13 12:26:40\"}\n"
+        );
+
+        let timestamp = d2::DataType::Timestamp(d2::TimeUnit::Second, None);
+        let schema = d2::Schema::from(vec![
+            d2::Field::new("id", d2::DataType::Int32, true),
+            d2::Field::new("date", timestamp.clone(), true),
+        ]);
+        let chunk = Chunk::new(vec![
+            a2::Int32Array::from(vec![Some(1), None, None]).boxed(),
+            a2::Int64Array::from(vec![Some(1_600_000_000), None, Some(1_600_000_000)])
+                .to(timestamp)
+                .boxed(),
+        ]);
+        let mut ndjson2 = vec![];
+        write_ndjson(
+            &mut ndjson2,
+            std::iter::once(chunk_to_array(&schema, chunk)),
+        );
+        assert_eq!(String::from_utf8(ndjson2).unwrap(), ndjson);
+    }
+
+    #[test]
+    fn test_skip_nulls() {
+        let options = JsonOptions { skip_nulls: true };
+        let ids = Arc::new(Int32Array::from(vec![Some(1), None])) as _;
+        let names = Arc::new(StringArray::from(vec![None, Some("a")])) as _;
+        let batch = RecordBatch::try_from_iter(vec![("id", ids), ("name", names)]).unwrap();
+        let ndjson = String::from_utf8(batch_to_ndjson(&batch, &options).unwrap()).unwrap();
+        assert_eq!(ndjson, "{\"id\":1}\n{\"name\":\"a\"}\n");
+
+        let ndjson2 = br#"{"id":1,"name":null,"tags":[null,{"a":null,"b":2}]}"#.to_vec();
+        assert_eq!(
+            String::from_utf8(options.apply_ndjson(ndjson2.clone()).unwrap()).unwrap(),
+            "{\"id\":1,\"tags\":[null,{\"b\":2}]}\n"
+        );
+        let kept = JsonOptions::default()
+            .apply_ndjson(ndjson2.clone())
+            .unwrap();
+        assert_eq!(kept, ndjson2);
+    }
+
+    #[test]
+    fn test_csv_semicolon_delimiter() {
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "name",
+                Arc::new(StringArray::from(vec![Some("a;b"), None, Some("c")])) as _,
+            ),
+            ("n", Arc::new(Int32Array::from(vec![1, 2, 3])) as _),
+        ])
+        .unwrap();
+        let options = CsvOptions {
+            delimiter: b';',
+            ..CsvOptions::default()
+        };
+        let mut out = vec![];
+        write_csv(&mut out, &options, std::iter::once(batch.clone())).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "name;n\n\"a;b\";1\n;2\nc;3\n"
+        );
+
+        let options = CsvOptions {
+            delimiter: b';',
+            quote: b'\'',
+            header: false,
+        };
+        let mut out = vec![];
+        write_csv(&mut out, &options, std::iter::once(batch)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "'a;b';1\n;2\nc;3\n");
+    }
+
+    #[test]
+    fn test_csv_nested_columns_are_json() {
+        let inner = StructArray::from(vec![(
+            Arc::new(Field::new("a", DataType::Int32, true)),
+            Arc::new(Int32Array::from(vec![Some(1), None])) as _,
+        )]);
+        let batch = RecordBatch::try_from_iter(vec![("s", Arc::new(inner) as _)]).unwrap();
+        let mut out = vec![];
+        write_csv(&mut out, &CsvOptions::default(), std::iter::once(batch)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "s\n\"{\"\"a\"\":1}\"\n{}\n"
+        );
+    }
+
+    #[test]
+    fn test_csv2_decimals_are_exact() {
+        let price = arrow2::array::PrimitiveArray::<i128>::from(vec![Some(24_900_000), None])
+            .to(arrow2::datatypes::DataType::Decimal(20, 6));
+        let schema = arrow2::datatypes::Schema::from(vec![arrow2::datatypes::Field::new(
+            "price",
+            price.data_type().clone(),
+            true,
+        )]);
+        let chunk = Chunk::new(vec![price.boxed()]);
+        let mut out = vec![];
+        write_csv2(&mut out, &schema, &CsvOptions::default(), [chunk]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "price\n24.900000\n\n");
+    }
+
+    #[test]
+    fn test_partial_export_keeps_written_batches() {
+        let batch =
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(vec![1, 2])) as _)])
+                .unwrap();
+        let path = std::env::temp_dir().join("ps17_test_partial_export.csv");
+        let output = OutputFile::new(&path);
+        let batches = vec![Ok(batch), Err(anyhow::anyhow!("page 2 failed"))];
+        let err = output.csv(&CsvOptions::default(), batches).unwrap_err();
+        let partial = err.downcast_ref::<PartialExport>().unwrap();
+        assert_eq!((partial.batches, partial.rows), (1, 2));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "n\n1\n2\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_finish_partial() {
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(values)) as _)])
+                .unwrap()
+        };
+        let path = std::env::temp_dir().join("ps17_test_finish_partial.csv");
+        let partial = partial_path(&path);
+        assert_eq!(
+            partial.file_name().unwrap(),
+            "ps17_test_finish_partial.csv.partial"
+        );
+
+        let output = || OutputFile::via_partial(&path).unwrap();
+        let result = output().csv(&CsvOptions::default(), vec![Ok(batch(vec![1]))]);
+        finish_partial(&path, result).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "n\n1\n");
+        assert!(!partial.exists());
+
+        let batches = vec![Ok(batch(vec![2])), Err(anyhow::anyhow!("page 2 failed"))];
+        let result = output().csv(&CsvOptions::default(), batches);
+        assert!(finish_partial(&path, result).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "n\n1\n");
+        assert_eq!(std::fs::read_to_string(&partial).unwrap(), "n\n2\n");
+
+        // a run that writes nothing doesn't move the stale partial into place
+        let file = output();
+        assert!(!partial.exists());
+        drop(file);
+        finish_partial(&path, Ok(())).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "n\n1\n");
+
+        let batches = vec![Err(anyhow::anyhow!("page 1 failed"))];
+        let result = output().csv(&CsvOptions::default(), batches);
+        assert!(finish_partial(&path, result).is_err());
+        assert!(!partial.exists());
+
+        // a complete report of items of which some failed is kept
+        let result = output()
+            .text("deleted 1, failed 2")
+            .and(Err(
+                ItemsFailed(anyhow::anyhow!("failed to delete 1 of 2")).into()
+            ));
+        let e = finish_partial(&path, result).unwrap_err();
+        assert!(e.is::<ItemsFailed>());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "deleted 1, failed 2\n"
+        );
+        assert!(!partial.exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_arrow_ipc_round_trip() {
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(values)) as _)])
+                .unwrap()
+        };
+        let path = std::env::temp_dir().join("ps17_test_arrow_ipc.arrow");
+        let output = OutputFile::new(&path);
+        output
+            .arrow_ipc(vec![Ok(batch(vec![1, 2])), Ok(batch(vec![3]))])
+            .unwrap();
+        let reader =
+            arrow::ipc::reader::FileReader::try_new(std::fs::File::open(&path).unwrap(), None)
+                .unwrap();
+        let read = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, vec![batch(vec![1, 2]), batch(vec![3])]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_parquet_row_group_size() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(values)) as _)])
+                .unwrap()
+        };
+        let path = std::env::temp_dir().join("ps17_test_row_group_size.parquet");
+        let batches = vec![
+            Ok(batch(vec![1])),
+            Ok(batch(vec![2])),
+            Ok(batch(vec![3, 4, 5])),
+            Ok(batch(vec![6])),
+            Ok(batch(vec![7])),
+        ];
+        OutputFile::new(&path)
+            .parquet(ParquetCompression::Snappy, Some(2), batches)
+            .unwrap();
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let rows = reader
+            .metadata()
+            .row_groups()
+            .iter()
+            .map(|g| g.num_rows())
+            .collect::<Vec<_>>();
+        // rows are carried over between batches, so only the last group is short
+        assert_eq!(rows, vec![2, 2, 2, 1]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_parquet_compression() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        let batch =
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(vec![1, 2])) as _)])
+                .unwrap();
+        let path = std::env::temp_dir().join("ps17_test_compression.parquet");
+        OutputFile::new(&path)
+            .parquet(ParquetCompression::Snappy, None, vec![Ok(batch)])
+            .unwrap();
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let column = reader.metadata().row_group(0).column(0).compression();
+        assert_eq!(column, parquet::basic::Compression::SNAPPY);
+
+        let n = arrow2::array::Int32Array::from_slice([1, 2]);
+        let schema = arrow2::datatypes::Schema::from(vec![arrow2::datatypes::Field::new(
+            "n",
+            n.data_type().clone(),
+            true,
+        )]);
+        OutputFile::new(&path)
+            .parquet2(
+                schema,
+                ParquetCompression::Zstd,
+                vec![Ok(Chunk::new(vec![n.boxed()]))],
+            )
+            .unwrap();
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let column = reader.metadata().row_group(0).column(0).compression();
+        assert!(matches!(column, parquet::basic::Compression::ZSTD(_)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_gz_output_is_compressed() {
+        use std::io::Read;
+        let batch =
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(vec![1, 2])) as _)])
+                .unwrap();
+        let path = std::env::temp_dir().join("ps17_test_output.csv.gz");
+        OutputFile::new(&path)
+            .csv(&CsvOptions::default(), vec![Ok(batch)])
+            .unwrap();
+        let mut csv = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&path).unwrap())
+            .read_to_string(&mut csv)
+            .unwrap();
+        assert_eq!(csv, "n\n1\n2\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_gz_output_is_complete_after_failure() {
+        use std::io::Read;
+        let batch =
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(vec![1, 2])) as _)])
+                .unwrap();
+        let path = std::env::temp_dir().join("ps17_test_partial.ndjson.gz");
+        let result = OutputFile::new(&path).arrow_ndjson(
+            &JsonOptions::default(),
+            vec![Ok(batch), Err(anyhow::anyhow!("page 2 failed"))],
+        );
+        assert!(result.unwrap_err().is::<PartialExport>());
+        // the trailer is written, so the rows before the failure can be read back
+        let mut ndjson = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&path).unwrap())
+            .read_to_string(&mut ndjson)
+            .unwrap();
+        assert_eq!(ndjson, "{\"n\":1}\n{\"n\":2}\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_buffered_output_is_complete() {
+        let values = (0..10_000).collect::<Vec<i32>>();
+        let batch =
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(values)) as _)])
+                .unwrap();
+        let path = std::env::temp_dir().join("ps17_test_buffered.ndjson");
+        OutputFile::new(&path)
+            .arrow_ndjson(&JsonOptions::default(), vec![Ok(batch)])
+            .unwrap();
+        let ndjson = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(ndjson.lines().count(), 10_000);
+        assert_eq!(ndjson.lines().last(), Some(r#"{"n":9999}"#));
+
+        OutputFile::new(&path).text("done").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "done\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_avro_round_trip() {
+        use arrow2::array::{PrimitiveArray, Utf8Array};
+        use arrow2::io::avro::{avro_schema, read};
+        let id = PrimitiveArray::<u32>::from(vec![Some(1), None]);
+        let date = PrimitiveArray::<i64>::from(vec![Some(1577934245), None]).to(
+            arrow2::datatypes::DataType::Timestamp(arrow2::datatypes::TimeUnit::Second, None),
+        );
+        let text = Utf8Array::<i32>::from(vec![Some("a"), Some("b")]);
+        let schema = arrow2::datatypes::Schema::from(vec![
+            arrow2::datatypes::Field::new("id", id.data_type().clone(), true),
+            arrow2::datatypes::Field::new("date_add", date.data_type().clone(), true),
+            arrow2::datatypes::Field::new("#text", text.data_type().clone(), true),
+        ]);
+        let path = std::env::temp_dir().join("ps17_test_output.avro");
+        let chunk = Chunk::new(vec![id.boxed(), date.boxed(), text.boxed()]);
+        OutputFile::new(&path)
+            .avro2(schema, vec![Ok(chunk)])
+            .unwrap();
+
+        let mut file = std::fs::File::open(&path).unwrap();
+        let metadata = avro_schema::read::read_metadata(&mut file).unwrap();
+        let schema = read::infer_schema(&metadata.record).unwrap();
+        let names = schema
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["id", "date_add", "_text"]);
+        let chunks = read::Reader::new(file, metadata, schema.fields, None)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let arrays = chunks[0].arrays();
+        assert_eq!(
+            arrays[0].as_ref(),
+            &PrimitiveArray::<i64>::from(vec![Some(1), None]) as &dyn arrow2::array::Array
+        );
+        let date = arrays[1]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<i64>>()
+            .unwrap();
+        assert_eq!(date.value(0), 1577934245000);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_avro_multilingual_field() {
+        use common::arrow2::schema3::{DataType, Field, Schema3};
+        let schema = Schema3 {
+            fields: vec![Field::new("name", DataType::MultilingualUtf8)],
+            associations: vec![],
+        };
+        let source = r#"<prestashop><products>
+            <product><name><language id="1">a</language><language id="2">b</language></name></product>
+            <product/>
+        </products></prestashop>"#;
+        let chunk = common::arrow2::parse_response::parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            common::on_error::OnError::Fail,
+            &mut common::on_error::Skipped::default(),
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join("ps17_test_multilingual.avro");
+        OutputFile::new(&path)
+            .avro2(schema.to_arrow2(), vec![Ok(chunk)])
+            .unwrap();
+        let mut file = std::fs::File::open(&path).unwrap();
+        let metadata = arrow2::io::avro::avro_schema::read::read_metadata(&mut file).unwrap();
+        let schema = arrow2::io::avro::read::infer_schema(&metadata.record).unwrap();
+        let chunks = arrow2::io::avro::read::Reader::new(file, metadata, schema.fields, None)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(chunks[0].len(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Records what was written and at which points the output was flushed
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<std::sync::Mutex<(Vec<u8>, Vec<usize>)>>);
+
+    impl std::io::Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            let mut inner = self.0.lock().unwrap();
+            let len = inner.0.len();
+            inner.1.push(len);
+            Ok(())
+        }
+    }
+
+    impl OutputT<Recorder> for Recorder {
+        fn to_writer(&self) -> Result<Recorder> {
+            Ok(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_arrow_ndjson_flushes_every_batch() {
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(values)) as _)])
+                .unwrap()
+        };
+        let recorder = Recorder::default();
+        recorder
+            .clone()
+            .arrow_ndjson(
+                &JsonOptions::default(),
+                vec![Ok(batch(vec![1, 2])), Ok(batch(vec![3]))],
+            )
+            .unwrap();
+        let (written, flushes) = recorder.0.lock().unwrap().clone();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "{\"n\":1}\n{\"n\":2}\n{\"n\":3}\n"
+        );
+        assert_eq!(flushes, vec![16, 24, 24]);
+
+        let n = arrow2::array::Int32Array::from_slice([1, 2]);
+        let schema = arrow2::datatypes::Schema::from(vec![arrow2::datatypes::Field::new(
+            "n",
+            n.data_type().clone(),
+            true,
+        )]);
+        let chunks = vec![
+            Ok(Chunk::new(vec![n.boxed()])),
+            Ok(Chunk::new(vec![
+                arrow2::array::Int32Array::from_slice([3]).boxed()
+            ])),
+        ];
+        let recorder = Recorder::default();
+        recorder
+            .clone()
+            .ndjson2(schema, &JsonOptions::default(), chunks)
+            .unwrap();
+        let (written, flushes) = recorder.0.lock().unwrap().clone();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "{\"n\":1}\n{\"n\":2}\n{\"n\":3}\n"
+        );
+        assert_eq!(flushes, vec![16, 24, 24]);
+    }
+
+    #[test]
+    fn test_arrow_json_is_one_array() {
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_from_iter(vec![("n", Arc::new(Int32Array::from(values)) as _)])
+                .unwrap()
+        };
+        let recorder = Recorder::default();
+        recorder
+            .clone()
+            .arrow_json(
+                &JsonOptions::default(),
+                vec![Ok(batch(vec![1, 2])), Ok(batch(vec![3]))],
+            )
+            .unwrap();
+        let written = recorder.0.lock().unwrap().0.clone();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "[\n  {\n    \"n\": 1\n  },\n  {\n    \"n\": 2\n  },\n  {\n    \"n\": 3\n  }\n]\n"
+        );
+
+        let recorder = Recorder::default();
+        recorder
+            .clone()
+            .arrow_json(&JsonOptions::default(), vec![Ok(batch(vec![]))])
+            .unwrap();
+        let written = recorder.0.lock().unwrap().0.clone();
+        assert_eq!(String::from_utf8(written).unwrap(), "[]\n");
     }
 }
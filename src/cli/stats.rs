@@ -0,0 +1,257 @@
+//! Per column summary of the records written by get, see --stats
+
+use arrow::array::{Array, Float64Array};
+use arrow::compute::{cast, max, min};
+use arrow::datatypes::DataType;
+use arrow::record_batch::RecordBatch;
+use arrow2::array::{Array as Array2, PrimitiveArray};
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::DataType as DataType2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RangeKind {
+    Number,
+    /// Seconds since the epoch, shown as dates
+    Date,
+}
+
+#[derive(Debug)]
+struct ColumnStats {
+    name: String,
+    non_null: usize,
+    nulls: usize,
+    range: Option<(RangeKind, f64, f64)>,
+}
+
+impl ColumnStats {
+    fn add(&mut self, len: usize, nulls: usize, range: Option<(RangeKind, f64, f64)>) {
+        self.non_null += len - nulls;
+        self.nulls += nulls;
+        self.range = match (self.range, range) {
+            (Some((kind, lo, hi)), Some((_, l, h))) => Some((kind, lo.min(l), hi.max(h))),
+            (a, b) => a.or(b),
+        };
+    }
+
+    fn format_bound(&self, bound: impl Fn(f64, f64) -> f64) -> String {
+        match self.range {
+            None => String::new(),
+            Some((RangeKind::Number, lo, hi)) => bound(lo, hi).to_string(),
+            Some((RangeKind::Date, lo, hi)) => {
+                chrono::DateTime::from_timestamp(bound(lo, hi) as i64, 0)
+                    .map(|d| d.naive_utc().format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+/// Non-null and null counts of every top-level column, with the min and max
+/// of numbers and dates, accumulated over all batches
+#[derive(Debug, Default)]
+pub struct Stats {
+    columns: Vec<ColumnStats>,
+}
+
+impl Stats {
+    fn column(&mut self, i: usize, name: &str) -> &mut ColumnStats {
+        if i == self.columns.len() {
+            self.columns.push(ColumnStats {
+                name: name.to_string(),
+                non_null: 0,
+                nulls: 0,
+                range: None,
+            });
+        }
+        &mut self.columns[i]
+    }
+
+    pub fn add_batch(&mut self, batch: &RecordBatch) {
+        let schema = batch.schema();
+        for (i, (field, array)) in schema.fields().iter().zip(batch.columns()).enumerate() {
+            let range = range(array.as_ref());
+            self.column(i, field.name())
+                .add(array.len(), array.null_count(), range);
+        }
+    }
+
+    pub fn add_chunk(
+        &mut self,
+        schema: &arrow2::datatypes::Schema,
+        chunk: &Chunk<Box<dyn Array2>>,
+    ) {
+        for (i, (field, array)) in schema.fields.iter().zip(chunk.arrays()).enumerate() {
+            let range = range2(array.as_ref());
+            self.column(i, &field.name)
+                .add(array.len(), array.null_count(), range);
+        }
+    }
+}
+
+/// Min and max with arrow's kernels, after casting numbers to f64 and
+/// timestamps to their seconds
+fn range(array: &dyn Array) -> Option<(RangeKind, f64, f64)> {
+    let (kind, array) = match array.data_type() {
+        DataType::Timestamp(_, _) => {
+            let seconds = cast(
+                array,
+                &DataType::Timestamp(arrow::datatypes::TimeUnit::Second, None),
+            )
+            .ok()?;
+            let seconds = cast(&seconds, &DataType::Int64).ok()?;
+            (RangeKind::Date, cast(&seconds, &DataType::Float64).ok()?)
+        }
+        t if t.is_numeric() => (RangeKind::Number, cast(array, &DataType::Float64).ok()?),
+        _ => return None,
+    };
+    let array = array.as_any().downcast_ref::<Float64Array>()?;
+    Some((kind, min(array)?, max(array)?))
+}
+
+fn range2(array: &dyn Array2) -> Option<(RangeKind, f64, f64)> {
+    fn values<T: arrow2::types::NativeType>(
+        array: &dyn Array2,
+        to_f64: impl Fn(T) -> f64,
+    ) -> Option<(f64, f64)> {
+        let array = array.as_any().downcast_ref::<PrimitiveArray<T>>()?;
+        array
+            .iter()
+            .flatten()
+            .map(|v| to_f64(*v))
+            .fold(None, |r, v| match r {
+                None => Some((v, v)),
+                Some((lo, hi)) => Some((v.min(lo), v.max(hi))),
+            })
+    }
+    let (kind, (lo, hi)) = match array.data_type() {
+        DataType2::Int32 => (RangeKind::Number, values(array, |v: i32| v as f64)?),
+        DataType2::UInt32 => (RangeKind::Number, values(array, |v: u32| v as f64)?),
+        DataType2::Int64 => (RangeKind::Number, values(array, |v: i64| v as f64)?),
+        DataType2::UInt64 => (RangeKind::Number, values(array, |v: u64| v as f64)?),
+        DataType2::Float64 => (RangeKind::Number, values(array, |v: f64| v)?),
+        DataType2::Decimal(_, scale) => {
+            let scale = 10f64.powi(*scale as i32);
+            (
+                RangeKind::Number,
+                values(array, |v: i128| v as f64 / scale)?,
+            )
+        }
+        DataType2::Timestamp(arrow2::datatypes::TimeUnit::Second, _) => {
+            (RangeKind::Date, values(array, |v: i64| v as f64)?)
+        }
+        _ => return None,
+    };
+    Some((kind, lo, hi))
+}
+
+/// Writes `rows` as left aligned columns, the first row being the header
+fn write_table<const N: usize>(
+    f: &mut std::fmt::Formatter<'_>,
+    rows: &[[String; N]],
+) -> std::fmt::Result {
+    let widths = (0..N)
+        .map(|i| rows.iter().map(|r| r[i].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(f, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header = ["column", "non-null", "null", "min", "max"].map(String::from);
+        let rows = std::iter::once(header)
+            .chain(self.columns.iter().map(|c| {
+                [
+                    c.name.clone(),
+                    c.non_null.to_string(),
+                    c.nulls.to_string(),
+                    c.format_bound(|lo, _| lo),
+                    c.format_bound(|_, hi| hi),
+                ]
+            }))
+            .collect::<Vec<_>>();
+        write_table(f, &rows)
+    }
+}
+
+/// Number of records of each resource, `None` for the ones that could not be
+/// counted, e.g. because the key cannot read them
+#[derive(Debug, Default)]
+pub struct ResourceCounts(pub Vec<(String, Option<usize>)>);
+
+impl std::fmt::Display for ResourceCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header = ["resource", "count"].map(String::from);
+        let rows = std::iter::once(header)
+            .chain(self.0.iter().map(|(resource, count)| {
+                let count = count.map_or_else(|| "-".to_string(), |n| n.to_string());
+                [resource.clone(), count]
+            }))
+            .collect::<Vec<_>>();
+        write_table(f, &rows)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow::array::{Int32Array, StringArray, TimestampSecondArray};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_stats() {
+        let batch = |ids: Vec<Option<i32>>, dates: Vec<Option<i64>>| {
+            let names = StringArray::from(vec![Some("a"); ids.len()]);
+            RecordBatch::try_from_iter(vec![
+                ("id", Arc::new(Int32Array::from(ids)) as _),
+                ("date_add", Arc::new(TimestampSecondArray::from(dates)) as _),
+                ("name", Arc::new(names) as _),
+            ])
+            .unwrap()
+        };
+        let mut stats = Stats::default();
+        stats.add_batch(&batch(vec![Some(3), None], vec![Some(1_600_000_000), None]));
+        stats.add_batch(&batch(vec![Some(-1)], vec![None]));
+        assert_eq!(
+            stats.to_string(),
+            "column    non-null  null  min                  max\n\
+             id        2         1     -1                   3\n\
+             date_add  1         2     2020-09-13 12:26:40  2020-09-13 12:26:40\n\
+             name      3         0\n"
+        );
+
+        let price = PrimitiveArray::<i128>::from(vec![Some(1250), Some(99), None])
+            .to(DataType2::Decimal(20, 2));
+        let schema = arrow2::datatypes::Schema::from(vec![arrow2::datatypes::Field::new(
+            "price",
+            price.data_type().clone(),
+            true,
+        )]);
+        let mut stats = Stats::default();
+        stats.add_chunk(&schema, &Chunk::new(vec![price.boxed()]));
+        assert_eq!(
+            stats.to_string(),
+            "column  non-null  null  min   max\nprice   2         1     0.99  12.5\n"
+        );
+    }
+
+    #[test]
+    fn test_resource_counts() {
+        let counts = ResourceCounts(vec![
+            ("addresses".to_string(), Some(120)),
+            ("carriers".to_string(), None),
+        ]);
+        assert_eq!(
+            counts.to_string(),
+            "resource   count\naddresses  120\ncarriers   -\n"
+        );
+    }
+}
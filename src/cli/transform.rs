@@ -0,0 +1,347 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{
+    make_array, Array, ArrayRef, BooleanArray, ListArray, StringArray, StructArray, UInt32Array,
+};
+use arrow::buffer::NullBuffer;
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use arrow::row::{RowConverter, SortField};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Looks up a column by name, descending into struct columns for dotted
+/// paths like `product.id`. Columns flattened with [flatten_structs] match by
+/// their full name first.
+fn column_by_path(batch: &RecordBatch, path: &str) -> Result<ArrayRef> {
+    if let Some(column) = batch.column_by_name(path) {
+        return Ok(column.clone());
+    }
+    let mut parts = path.split('.');
+    let first = parts.next().unwrap_or_default();
+    let mut column = batch.column_by_name(first).cloned().ok_or_else(|| {
+        anyhow!(
+            "no column '{}', available columns: {:?}",
+            first,
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().to_string())
+                .collect::<Vec<_>>()
+        )
+    })?;
+    for part in parts {
+        let sa = column
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .ok_or_else(|| anyhow!("'{}' is not a struct column", path))?;
+        column = sa
+            .column_by_name(part)
+            .cloned()
+            .ok_or_else(|| anyhow!("no field '{}' in '{}'", part, path))?;
+    }
+    Ok(column)
+}
+
+/// Replaces struct columns by their fields up to `depth` levels deep, naming
+/// them by joining the path with `separator`, e.g. `customer.address.city`.
+/// A null struct makes all its flattened fields null. List columns are kept as is.
+pub fn flatten_structs(batch: &RecordBatch, depth: usize, separator: &str) -> Result<RecordBatch> {
+    let mut fields = vec![];
+    let mut columns = vec![];
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        flatten_column(
+            field.as_ref().clone(),
+            column.clone(),
+            depth,
+            separator,
+            &mut fields,
+            &mut columns,
+        )?;
+    }
+    let schema = Schema::new_with_metadata(fields, batch.schema().metadata().clone());
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+fn flatten_column(
+    field: Field,
+    column: ArrayRef,
+    depth: usize,
+    separator: &str,
+    fields: &mut Vec<Field>,
+    columns: &mut Vec<ArrayRef>,
+) -> Result<()> {
+    let children = match field.data_type() {
+        DataType::Struct(children) if depth > 0 => children.clone(),
+        _ => {
+            fields.push(field);
+            columns.push(column);
+            return Ok(());
+        }
+    };
+    let sa = column
+        .as_any()
+        .downcast_ref::<StructArray>()
+        .ok_or_else(|| anyhow!("column '{}' is not a StructArray", field.name()))?;
+    for (child_field, child) in children.iter().zip(sa.columns()) {
+        let nulls = NullBuffer::union(sa.nulls(), child.nulls());
+        let child = make_array(child.to_data().into_builder().nulls(nulls).build()?);
+        let child_field = child_field
+            .as_ref()
+            .clone()
+            .with_name(format!(
+                "{}{}{}",
+                field.name(),
+                separator,
+                child_field.name()
+            ))
+            .with_nullable(field.is_nullable() || child_field.is_nullable());
+        flatten_column(child_field, child, depth - 1, separator, fields, columns)?;
+    }
+    Ok(())
+}
+
+/// Replaces multilingual columns, lists of `{id, language}`, by the text of
+/// the language with `language_id`, also within struct columns. Rows without
+/// that language are null
+pub fn collapse_languages(batch: &RecordBatch, language_id: u32) -> Result<RecordBatch> {
+    let mut fields = vec![];
+    let mut columns = vec![];
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        let (field, column) = collapse_language_column(field, column, language_id)?;
+        fields.push(field);
+        columns.push(column);
+    }
+    let schema = Schema::new_with_metadata(fields, batch.schema().metadata().clone());
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+fn is_language_list(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::List(item) => match item.data_type() {
+            DataType::Struct(fields) => {
+                let names = fields.iter().map(|f| f.name().as_str()).collect::<Vec<_>>();
+                names == ["id", "language"]
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn collapse_language_column(
+    field: &Field,
+    column: &ArrayRef,
+    language_id: u32,
+) -> Result<(Field, ArrayRef)> {
+    match field.data_type() {
+        t if is_language_list(t) => {
+            let text = language_text(column.as_ref(), language_id)
+                .ok_or_else(|| anyhow!("column '{}' is not a list of languages", field.name()))?;
+            let field = Field::new(field.name(), DataType::Utf8, true);
+            Ok((field, Arc::new(text)))
+        }
+        DataType::Struct(_) => {
+            let sa = column
+                .as_any()
+                .downcast_ref::<StructArray>()
+                .ok_or_else(|| anyhow!("column '{}' is not a StructArray", field.name()))?;
+            let (fields, columns): (Vec<_>, Vec<_>) = sa
+                .fields()
+                .iter()
+                .zip(sa.columns())
+                .map(|(f, c)| collapse_language_column(f, c, language_id))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .unzip();
+            let sa = StructArray::try_new(fields.into(), columns, sa.nulls().cloned())?;
+            let field = Field::new(field.name(), sa.data_type().clone(), field.is_nullable());
+            Ok((field, Arc::new(sa)))
+        }
+        _ => Ok((field.clone(), column.clone())),
+    }
+}
+
+fn language_text(column: &dyn Array, language_id: u32) -> Option<StringArray> {
+    let list = column.as_any().downcast_ref::<ListArray>()?;
+    let items = list.values().as_any().downcast_ref::<StructArray>()?;
+    let ids = items.column(0).as_any().downcast_ref::<UInt32Array>()?;
+    let texts = items.column(1).as_any().downcast_ref::<StringArray>()?;
+    let offsets = list.value_offsets();
+    let text = (0..list.len())
+        .map(|row| {
+            if list.is_null(row) {
+                return None;
+            }
+            let (start, end) = (offsets[row] as usize, offsets[row + 1] as usize);
+            (start..end)
+                .find(|&i| ids.is_valid(i) && ids.value(i) == language_id)
+                .filter(|&i| texts.is_valid(i))
+                .map(|i| texts.value(i))
+        })
+        .collect::<StringArray>();
+    Some(text)
+}
+
+/// Drops rows whose key columns equal those of an earlier row, keeping the
+/// first occurrence. Returns the deduplicated batch and the number of
+/// removed rows.
+pub fn dedup_batch(batch: &RecordBatch, keys: &[String]) -> Result<(RecordBatch, usize)> {
+    let columns = keys
+        .iter()
+        .map(|k| column_by_path(batch, k))
+        .collect::<Result<Vec<_>>>()?;
+    let converter = RowConverter::new(
+        columns
+            .iter()
+            .map(|c| SortField::new(c.data_type().clone()))
+            .collect(),
+    )?;
+    let rows = converter.convert_columns(&columns)?;
+    let mut seen = HashSet::with_capacity(rows.num_rows());
+    let keep = BooleanArray::from(rows.iter().map(|r| seen.insert(r)).collect::<Vec<_>>());
+    let removed = batch.num_rows() - seen.len();
+    Ok((filter_record_batch(batch, &keep)?, removed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow::buffer::OffsetBuffer;
+    use arrow::datatypes::{Fields, Int32Type};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_dedup_keeps_first_occurrence() {
+        let batch = RecordBatch::try_from_iter(vec![
+            ("id", Arc::new(UInt32Array::from(vec![1, 2, 1, 3, 2])) as _),
+            (
+                "name",
+                Arc::new(StringArray::from(vec!["a", "b", "c", "d", "e"])) as _,
+            ),
+        ])
+        .unwrap();
+        let (deduped, removed) = dedup_batch(&batch, &["id".to_string()]).unwrap();
+        assert_eq!(removed, 2);
+        let names = deduped
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![Some("a"), Some("b"), Some("d")]);
+        assert!(dedup_batch(&batch, &["missing".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_flatten_structs() {
+        let city = Arc::new(StringArray::from(vec![Some("Vilnius"), Some("Kaunas")])) as ArrayRef;
+        let address = StructArray::from(vec![(
+            Arc::new(Field::new("city", DataType::Utf8, true)),
+            city,
+        )]);
+        let id = Arc::new(UInt32Array::from(vec![1, 2])) as ArrayRef;
+        let customer = StructArray::try_new(
+            vec![
+                Arc::new(Field::new("id", DataType::UInt32, false)),
+                Arc::new(Field::new("address", address.data_type().clone(), true)),
+            ]
+            .into(),
+            vec![id, Arc::new(address)],
+            Some(NullBuffer::from(vec![true, false])),
+        )
+        .unwrap();
+        let tags = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(1)]),
+            Some(vec![]),
+        ]);
+        let batch = RecordBatch::try_from_iter(vec![
+            ("customer", Arc::new(customer) as ArrayRef),
+            ("tags", Arc::new(tags) as ArrayRef),
+        ])
+        .unwrap();
+
+        let names = |b: &RecordBatch| {
+            b.schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        let one = flatten_structs(&batch, 1, ".").unwrap();
+        assert_eq!(names(&one), vec!["customer.id", "customer.address", "tags"]);
+        let all = flatten_structs(&batch, 2, ".").unwrap();
+        assert_eq!(
+            names(&all),
+            vec!["customer.id", "customer.address.city", "tags"]
+        );
+        let city = all.column(1);
+        assert!(city.is_valid(0));
+        // the second customer is null, so are its fields
+        assert!(city.is_null(1));
+        assert!(all.column(0).is_null(1));
+        assert!(all.schema().field(0).is_nullable());
+        // flattened names can be used as dedup keys
+        let (_, removed) = dedup_batch(&all, &["customer.address.city".to_string()]).unwrap();
+        assert_eq!(removed, 0);
+    }
+
+    #[test]
+    fn test_collapse_languages() {
+        let item_fields = Fields::from(vec![
+            Field::new("id", DataType::UInt32, true),
+            Field::new("language", DataType::Utf8, true),
+        ]);
+        let items = StructArray::new(
+            item_fields.clone(),
+            vec![
+                Arc::new(UInt32Array::from(vec![1, 2, 2])) as ArrayRef,
+                Arc::new(StringArray::from(vec!["a", "b", "d"])) as ArrayRef,
+            ],
+            None,
+        );
+        // [a, b], null, [d]: only language 2 in the last row
+        let name = ListArray::new(
+            Arc::new(Field::new("item", DataType::Struct(item_fields), true)),
+            OffsetBuffer::new(vec![0, 2, 2, 3].into()),
+            Arc::new(items),
+            Some(NullBuffer::from(vec![true, false, true])),
+        );
+        let product = StructArray::try_from(vec![
+            ("id", Arc::new(UInt32Array::from(vec![7, 8, 9])) as ArrayRef),
+            ("name", Arc::new(name) as ArrayRef),
+        ])
+        .unwrap();
+        let batch =
+            RecordBatch::try_from_iter(vec![("product", Arc::new(product) as ArrayRef)]).unwrap();
+
+        let collapsed = collapse_languages(&batch, 1).unwrap();
+        let product = collapsed
+            .column(0)
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap();
+        let name = product
+            .column_by_name("name")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(name.iter().collect::<Vec<_>>(), vec![Some("a"), None, None]);
+        let collapsed = collapse_languages(&batch, 2).unwrap();
+        let name = collapsed
+            .column(0)
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap()
+            .column(1)
+            .clone();
+        let name = name.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(
+            name.iter().collect::<Vec<_>>(),
+            vec![Some("b"), None, Some("d")]
+        );
+    }
+}
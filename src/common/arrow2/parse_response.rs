@@ -1,17 +1,24 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
 use anyhow::{anyhow, Context, Result};
 use arrow2::array::*;
 use arrow2::chunk::Chunk;
-use arrow2::datatypes::{DataType as Arrow2DataType, TimeUnit};
 use arrow2::datatypes::Field;
+use arrow2::datatypes::{DataType as Arrow2DataType, TimeUnit};
 use arrow2::types::{NativeType, Offset};
-use chrono::NaiveDateTime;
+use tracing::warn;
 
 use crate::arrow2::schema3;
 use crate::arrow2::schema3::{Association, DataType, Schema3};
 use crate::arrow2::utils::{elements_of, parse_xml};
+use crate::on_error::{describe_record, OnError, Skipped};
+use crate::parser::Parser;
+use crate::schema2::parse_date;
+use crate::utils::{self, node_text};
+use crate::xml_stream;
+
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
 
 fn to_box<M>(m: M) -> Box<dyn MutableArray>
 where
@@ -57,7 +64,14 @@ fn data_type_to_mutable_array(data_type: &DataType) -> Result<Box<dyn MutableArr
     Ok(match &data_type {
         DataType::Int32 => to_box(MutablePrimitiveArray::<i32>::new()),
         DataType::UInt32 => to_box(MutablePrimitiveArray::<u32>::new()),
+        DataType::Int64 => to_box(MutablePrimitiveArray::<i64>::new()),
+        DataType::UInt64 => to_box(MutablePrimitiveArray::<u64>::new()),
         DataType::Float64 => to_box(MutablePrimitiveArray::<f64>::new()),
+        DataType::Decimal(precision, scale) => to_box(MutablePrimitiveArray::<i128>::try_new(
+            arrow2::datatypes::DataType::Decimal(*precision, *scale),
+            vec![],
+            None,
+        )?),
         DataType::Date => to_box(MutablePrimitiveArray::<i64>::try_new(
             arrow2::datatypes::DataType::Timestamp(TimeUnit::Second, None),
             vec![],
@@ -108,10 +122,8 @@ fn parse_utf8<O: Offset>(dst: &mut Box<dyn MutableArray>, src: Option<&str>) ->
 fn parse_bool(dst: &mut Box<dyn MutableArray>, src: Option<&str>) -> Result<()> {
     let dst = downcast::<MutableBooleanArray>(dst)?;
     match src {
-        Some("1") => dst.try_push(Some(true))?,
-        Some("0") => dst.try_push(Some(false))?,
+        Some(s) => dst.try_push(utils::parse_bool(s)?)?,
         None => dst.push_null(),
-        Some(other) => return Err(anyhow!("invalid bool value {}", other)),
     };
     Ok(())
 }
@@ -127,13 +139,19 @@ fn non_empty(s: Option<&str>) -> Option<&str> {
 fn parse_from_str<A: FromStr + NativeType>(
     dst: &mut Box<dyn MutableArray>,
     src: Option<&str>,
+    field: &str,
+    top_level: bool,
 ) -> Result<()>
 where
     <A as FromStr>::Err: std::error::Error + Sync + Send + 'static,
 {
+    let data_type = dst.data_type().clone();
     let dst = downcast::<MutablePrimitiveArray<A>>(dst)?;
     if let Some(s) = non_empty(src) {
-        dst.try_push(Some(s.parse::<A>()?))?;
+        let value = s
+            .parse::<A>()
+            .map_err(|e| number_error(&data_type, field, s, e, top_level))?;
+        dst.try_push(Some(value))?;
     } else {
         dst.push_null();
     }
@@ -143,27 +161,120 @@ where
 fn parse_field_from_str<A: FromStr + NativeType>(
     dst: &mut Box<dyn MutableArray>,
     src: &roxmltree::Node,
+    top_level: bool,
 ) -> Result<()>
 where
     <A as FromStr>::Err: std::error::Error + Sync + Send + 'static,
 {
-    parse_from_str::<A>(dst, src.text())
+    parse_from_str::<A>(dst, src.text(), src.tag_name().name(), top_level)
 }
 
-fn parse_f64(dst: &mut Box<dyn MutableArray>, src: Option<&str>) -> Result<()> {
-    let dst = downcast::<MutablePrimitiveArray<f64>>(dst)?;
-    if let Some(s) = src {
-        dst.try_push(Some(s.parse::<f64>()?))?;
-    } else {
-        dst.push_null();
+/// Error for a `value` of `field` that isn't a number of `data_type`. For an
+/// integer that is only out of range, suggests a `--cast` to a type it fits
+/// when the field is a `top_level` one, the only kind `--cast` applies to
+fn number_error(
+    data_type: &Arrow2DataType,
+    field: &str,
+    value: &str,
+    e: impl std::fmt::Display,
+    top_level: bool,
+) -> anyhow::Error {
+    let type_name = match data_type {
+        Arrow2DataType::Int32 => "int32",
+        Arrow2DataType::UInt32 => "uint32",
+        Arrow2DataType::Int64 => "int64",
+        Arrow2DataType::UInt64 => "uint64",
+        Arrow2DataType::Float64 => "float64",
+        _ => return anyhow!("invalid value {:?} of field {:?}: {}", value, field, e),
+    };
+    match (data_type, value.parse::<i128>()) {
+        (Arrow2DataType::Float64, _) | (_, Err(_)) => anyhow!(
+            "invalid {} value {:?} of field {:?}: {}",
+            type_name,
+            value,
+            field,
+            e
+        ),
+        (_, Ok(_)) if !top_level => anyhow!(
+            "value {:?} of field {:?} is out of range for {}",
+            value,
+            field,
+            type_name
+        ),
+        (_, Ok(n)) => {
+            let wider = if i64::try_from(n).is_ok() {
+                "int64"
+            } else if u64::try_from(n).is_ok() {
+                "uint64"
+            } else {
+                "utf8"
+            };
+            anyhow!(
+                "value {:?} of field {:?} is out of range for {}, try --cast {}={}",
+                value,
+                field,
+                type_name,
+                field,
+                wider
+            )
+        }
     }
-    Ok(())
 }
 
-fn parse_u32(dst: &mut Box<dyn MutableArray>, src: Option<&str>) -> Result<()> {
-    let dst = downcast::<MutablePrimitiveArray<u32>>(dst)?;
-    if let Some(s) = src {
-        dst.try_push(Some(s.parse::<u32>()?))?;
+/// Parses a decimal string like `-12.5` into an integer scaled by `10^scale`
+fn parse_decimal(s: &str, precision: usize, scale: usize) -> Result<i128> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+    if int_part.is_empty() && frac_part.is_empty()
+        || !int_part
+            .chars()
+            .chain(frac_part.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(anyhow!("invalid decimal {:?}", s));
+    }
+    let (kept, dropped) = frac_part.split_at(frac_part.len().min(scale));
+    if dropped.chars().any(|c| c != '0') {
+        return Err(anyhow!(
+            "decimal {:?} has more than {} fraction digits",
+            s,
+            scale
+        ));
+    }
+    let int_part = int_part.trim_start_matches('0');
+    if int_part.len() + scale > precision {
+        return Err(anyhow!(
+            "decimal {:?} does not fit precision {}",
+            s,
+            precision
+        ));
+    }
+    let scaled = format!(
+        "{}{}{:0<width$}",
+        int_part,
+        kept,
+        "",
+        width = scale - kept.len()
+    );
+    let value = if scaled.is_empty() {
+        0
+    } else {
+        scaled.parse::<i128>()?
+    };
+    Ok(if negative { -value } else { value })
+}
+
+fn parse_field_decimal(dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node) -> Result<()> {
+    let (precision, scale) = match dst.data_type() {
+        Arrow2DataType::Decimal(precision, scale) => (*precision, *scale),
+        other => return Err(anyhow!("expected decimal, got {:?}", other)),
+    };
+    let dst = downcast::<MutablePrimitiveArray<i128>>(dst)?;
+    if let Some(s) = non_empty(src.text()) {
+        dst.try_push(Some(parse_decimal(s.trim(), precision, scale)?))?;
     } else {
         dst.push_null();
     }
@@ -174,7 +285,7 @@ fn parse_field_utf8<O: Offset>(
     dst: &mut Box<dyn MutableArray>,
     src: &roxmltree::Node,
 ) -> Result<()> {
-    parse_utf8::<O>(dst, src.text())
+    parse_utf8::<O>(dst, node_text(src).as_deref())
 }
 
 fn parse_field_date64(dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node) -> Result<()> {
@@ -183,27 +294,15 @@ fn parse_field_date64(dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node) ->
         &Arrow2DataType::Timestamp(TimeUnit::Second, None)
     );
     let dst = downcast::<MutablePrimitiveArray<i64>>(dst)?;
-    if let Some(s) = src.text() {
-        let date = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")?;
-        dst.try_push(Some(date.timestamp_millis()))?;
-    } else {
-        dst.push_null();
-    }
+    let date = parse_date(src.text())?;
+    dst.try_push(date.map(|date| date.and_utc().timestamp()))?;
     Ok(())
 }
 
-fn parse_field_f64(dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node) -> Result<()> {
-    parse_f64(dst, src.text())
-}
-
 fn parse_field_bool(dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node) -> Result<()> {
     parse_bool(dst, src.text())
 }
 
-fn parse_field_u32(dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node) -> Result<()> {
-    parse_u32(dst, src.text())
-}
-
 fn parse_field_list<O: Offset>(
     dst: &mut Box<dyn MutableArray>,
     src: &roxmltree::Node,
@@ -217,10 +316,22 @@ fn parse_field_list<O: Offset>(
     Ok(())
 }
 
-fn parse_field_struct<O: Offset>(
-    dst: &mut Box<dyn MutableArray>,
-    src: &roxmltree::Node,
-) -> Result<()> {
+/// Parses the value of an attribute into a field named `@attribute`
+fn parse_attribute(dst: &mut Box<dyn MutableArray>, value: Option<&str>, name: &str) -> Result<()> {
+    match dst.data_type() {
+        Arrow2DataType::Utf8 => parse_utf8::<i32>(dst, value),
+        Arrow2DataType::LargeUtf8 => parse_utf8::<i64>(dst, value),
+        Arrow2DataType::Boolean => parse_bool(dst, non_empty(value)),
+        Arrow2DataType::Int32 => parse_from_str::<i32>(dst, value, name, false),
+        Arrow2DataType::UInt32 => parse_from_str::<u32>(dst, value, name, false),
+        Arrow2DataType::Int64 => parse_from_str::<i64>(dst, value, name, false),
+        Arrow2DataType::UInt64 => parse_from_str::<u64>(dst, value, name, false),
+        Arrow2DataType::Float64 => parse_from_str::<f64>(dst, value, name, false),
+        other => Err(anyhow!("attributes can't be read as {:?}", other)),
+    }
+}
+
+fn parse_field_struct(dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node) -> Result<()> {
     let dst = downcast::<MutableStructArray>(dst)?;
     let data_type = dst.data_type().clone();
     let fields = match &data_type {
@@ -228,19 +339,20 @@ fn parse_field_struct<O: Offset>(
         _ => return Err(anyhow!("expected struct")),
     };
     let _tag_name = src.tag_name().name();
-    let mut parsed_any = false;
     let initial_len = dst.len();
 
     // XML specific parsing similar to https://pypi.org/project/xmltodict/
     // to support <language> tags 'generically'
     for (i, field) in fields.iter().enumerate() {
-        if &field.name[0..1] == "@" && field.data_type == Arrow2DataType::UInt32 {
-            let attribute_name = &field.name[1..field.name.len()];
-            parse_u32(&mut dst.mut_values()[i], src.attribute(attribute_name))?;
-            parsed_any = true;
+        if field.name == "@href" && field.data_type == Arrow2DataType::Utf8 {
+            // association items link to their resource with xlink:href
+            parse_utf8::<i32>(&mut dst.mut_values()[i], src.attribute((XLINK_NS, "href")))?;
+        } else if let Some(attribute_name) = field.name.strip_prefix('@') {
+            let value = src.attribute(attribute_name);
+            parse_attribute(&mut dst.mut_values()[i], value, &field.name)
+                .with_context(|| format!("attribute {:?}", attribute_name))?;
         } else if field.name == "#text" && field.data_type == Arrow2DataType::Utf8 {
-            parse_utf8::<i32>(&mut dst.mut_values()[i], src.text())?;
-            parsed_any = true;
+            parse_utf8::<i32>(&mut dst.mut_values()[i], node_text(src).as_deref())?;
         }
     }
     for el in elements_of(src) {
@@ -250,14 +362,13 @@ fn parse_field_struct<O: Offset>(
             .position(|x| x.name == field_name)
             .ok_or_else(|| anyhow!("unknown field {}", field_name))?;
         parse_field(&mut dst.mut_values()[field_index], &el)?;
-        parsed_any = true;
     }
-    if parsed_any {
-        for i in 0..dst.mut_values().len() {
-            if dst.mut_values()[i].len() != initial_len + 1 {
-                assert_eq!(dst.mut_values()[i].len(), initial_len);
-                dst.mut_values()[i].push_null();
-            }
+    // fields missing from the element are null, for an empty element
+    // like `<associations/>` that is all of them
+    for values in dst.mut_values().iter_mut() {
+        if values.len() != initial_len + 1 {
+            assert_eq!(values.len(), initial_len);
+            values.push_null();
         }
     }
     dst.push(true);
@@ -265,61 +376,243 @@ fn parse_field_struct<O: Offset>(
 }
 
 fn parse_field(dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node) -> Result<()> {
+    parse_value(dst, src, false)
+}
+
+/// Same as [parse_field], for a field of the record itself rather than of an
+/// association
+fn parse_top_level_field(dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node) -> Result<()> {
+    parse_value(dst, src, true)
+}
+
+fn parse_value(
+    dst: &mut Box<dyn MutableArray>,
+    src: &roxmltree::Node,
+    top_level: bool,
+) -> Result<()> {
     match dst.data_type() {
         Arrow2DataType::Utf8 => parse_field_utf8::<i32>(dst, src).context("parse_field_utf8"),
-        Arrow2DataType::UInt32 => parse_field_from_str::<u32>(dst, src).context("parse_field_u32"),
-        Arrow2DataType::Int32 => parse_field_from_str::<i32>(dst, src).context("parse_field_i32"),
-        Arrow2DataType::Float64 => parse_field_from_str::<f64>(dst, src).context("parse_field_f64"),
+        Arrow2DataType::UInt32 => {
+            parse_field_from_str::<u32>(dst, src, top_level).context("parse_field_u32")
+        }
+        Arrow2DataType::Int32 => {
+            parse_field_from_str::<i32>(dst, src, top_level).context("parse_field_i32")
+        }
+        Arrow2DataType::UInt64 => {
+            parse_field_from_str::<u64>(dst, src, top_level).context("parse_field_u64")
+        }
+        Arrow2DataType::Int64 => {
+            parse_field_from_str::<i64>(dst, src, top_level).context("parse_field_i64")
+        }
+        Arrow2DataType::Float64 => {
+            parse_field_from_str::<f64>(dst, src, top_level).context("parse_field_f64")
+        }
+        Arrow2DataType::Decimal(_, _) => {
+            parse_field_decimal(dst, src).context("parse_field_decimal")
+        }
         Arrow2DataType::Timestamp(TimeUnit::Second, None) => {
             parse_field_date64(dst, src).context("parse_field_ts")
         }
         Arrow2DataType::Boolean => parse_field_bool(dst, src).context("parse_field_bool"),
         Arrow2DataType::List(_) => parse_field_list::<i32>(dst, src)
             .with_context(|| format!("parse_field_list {:?}", src.tag_name().name())),
-        Arrow2DataType::Struct(_) => parse_field_struct::<i32>(dst, src)
+        Arrow2DataType::Struct(_) => parse_field_struct(dst, src)
             .with_context(|| format!("parse_field_struct {:?}", src.tag_name().name())),
-        other => return Err(anyhow!("arrow parsing for {:?} is not implemented", other)),
+        other => Err(anyhow!("arrow parsing for {:?} is not implemented", other)),
     }
 }
 
-pub fn parse_response_to_arrow(schema: &Schema3, bytes: &[u8]) -> Result<Chunk<Box<dyn Array>>> {
-    let doc = parse_xml(bytes)?;
-    let container = doc
-        .root_element()
-        .first_element_child()
-        .ok_or(anyhow!("no elements in root"))?;
+type FieldArrays = HashMap<String, (usize, Box<dyn MutableArray>)>;
 
+fn schema_to_mutable_arrays(schema: &Schema3) -> Result<FieldArrays> {
     let mut h = HashMap::new();
     for (i, f) in schema.fields.iter().enumerate() {
         let mutable_array = data_type_to_mutable_array(&f.data_type)?;
         h.insert(f.name.to_string(), (i, mutable_array));
     }
     if !schema.associations.is_empty() {
+        // associations come last, as in Schema3::to_arrow2
         h.insert(
             "associations".to_string(),
             (
-                h.len(),
+                schema.fields.len(),
                 associations_to_mutable_array(&schema.associations)?,
             ),
         );
     }
-    for (i, el) in elements_of(&container).enumerate() {
-        for field in elements_of(&el) {
-            let field_name = field.tag_name().name();
-            let (_, ref mut array) = h
-                .get_mut(field_name)
-                .ok_or_else(|| anyhow!("unknown field {}", field_name))?;
-            parse_field(array, &field)
-                .with_context(|| format!("parse_field {:?}", el.tag_name().name()))?;
+    Ok(h)
+}
+
+fn parse_element(h: &mut FieldArrays, el: &roxmltree::Node) -> Result<()> {
+    parse_element_with(h, el, parse_top_level_field)
+}
+
+/// Same as [parse_element], with `parse` reading each field
+fn parse_element_with(
+    h: &mut FieldArrays,
+    el: &roxmltree::Node,
+    parse: impl Fn(&mut Box<dyn MutableArray>, &roxmltree::Node) -> Result<()>,
+) -> Result<()> {
+    for field in elements_of(el) {
+        let field_name = field.tag_name().name();
+        let (_, ref mut array) = h
+            .get_mut(field_name)
+            .ok_or_else(|| anyhow!("unknown field {}", field_name))?;
+        parse(array, &field).with_context(|| format!("parse_field {:?}", el.tag_name().name()))?;
+    }
+    Ok(())
+}
+
+/// Same as [parse_element], but a field that is unknown, repeated or fails to
+/// parse is logged and left out, so that it is read as null. Returns how many
+/// fields were left out
+fn parse_element_lenient(
+    h: &mut FieldArrays,
+    schema: &Schema3,
+    position: usize,
+    el: &roxmltree::Node,
+) -> Result<usize> {
+    let mut seen = HashSet::new();
+    let mut nulled = 0;
+    for field in elements_of(el) {
+        let field_name = field.tag_name().name();
+        let result = match h.get_mut(field_name) {
+            Some((_, array)) if seen.insert(field_name) => {
+                parse_field_or_nothing(array, schema, &field)
+            }
+            Some(_) => Err(anyhow!("field occurs more than once")),
+            None => Err(anyhow!("unknown field")),
+        };
+        if let Err(e) = result {
+            warn!(
+                "reading field {:?} of {} as null: {:#}",
+                field_name,
+                describe_record(position, el),
+                e
+            );
+            nulled += 1;
         }
-        for (_, ref mut array) in h.values_mut() {
-            if array.len() == i {
+    }
+    Ok(nulled)
+}
+
+/// Parses a field so that a failure leaves nothing pushed to `dst`. Scalars
+/// fail before pushing anything, while a list or struct can fail halfway
+/// through its children, so those are parsed into a scratch array first
+fn parse_field_or_nothing(
+    dst: &mut Box<dyn MutableArray>,
+    schema: &Schema3,
+    src: &roxmltree::Node,
+) -> Result<()> {
+    if let Arrow2DataType::List(_) | Arrow2DataType::Struct(_) = dst.data_type() {
+        let name = src.tag_name().name();
+        let mut scratch = if name == "associations" && !schema.associations.is_empty() {
+            associations_to_mutable_array(&schema.associations)?
+        } else {
+            let field = schema
+                .fields
+                .iter()
+                .find(|f| f.name == name)
+                .ok_or_else(|| anyhow!("unknown field {}", name))?;
+            data_type_to_mutable_array(&field.data_type)?
+        };
+        parse_field(&mut scratch, src)?;
+    }
+    parse_top_level_field(dst, src)
+}
+
+/// Accumulates records into mutable arrays, one `<element>` at a time
+struct ChunkBuilder<'a> {
+    schema: &'a Schema3,
+    on_error: OnError,
+    h: FieldArrays,
+    len: usize,
+    /// Records before the last one that was skipped halfway, see
+    /// [ChunkBuilder::drop_partial_record]
+    done: Vec<Chunk<Box<dyn Array>>>,
+    skipped: &'a mut Skipped,
+}
+
+impl<'a> ChunkBuilder<'a> {
+    fn new(schema: &'a Schema3, on_error: OnError, skipped: &'a mut Skipped) -> Result<Self> {
+        Ok(Self {
+            schema,
+            on_error,
+            h: schema_to_mutable_arrays(schema)?,
+            len: 0,
+            done: vec![],
+            skipped,
+        })
+    }
+
+    fn push(&mut self, position: usize, el: &roxmltree::Node) -> Result<()> {
+        match self.on_error {
+            OnError::Fail => parse_element(&mut self.h, el)?,
+            OnError::SkipRecord => {
+                let schema = self.schema;
+                let parse = |dst: &mut Box<dyn MutableArray>, src: &roxmltree::Node| {
+                    parse_field_or_nothing(dst, schema, src)
+                };
+                if let Err(e) = parse_element_with(&mut self.h, el, parse) {
+                    warn!("skipping {}: {:#}", describe_record(position, el), e);
+                    self.skipped.records += 1;
+                    return self.drop_partial_record();
+                }
+            }
+            OnError::NullValue => {
+                self.skipped.values +=
+                    parse_element_lenient(&mut self.h, self.schema, position, el)?;
+            }
+        }
+        for (_, ref mut array) in self.h.values_mut() {
+            if array.len() == self.len {
                 array.push_null();
             } else {
-                assert_eq!(array.len(), i + 1);
+                assert_eq!(array.len(), self.len + 1);
             }
         }
+        self.len += 1;
+        Ok(())
     }
+
+    /// Cuts off the fields that a skipped record pushed before the one that
+    /// failed. Each field is pushed whole or not at all, so the records so far
+    /// are the first `len` values of every array. They are finished into a
+    /// chunk of their own and the next records go to new arrays
+    fn drop_partial_record(&mut self) -> Result<()> {
+        if self.h.values().all(|(_, array)| array.len() == self.len) {
+            return Ok(());
+        }
+        let h = std::mem::replace(&mut self.h, schema_to_mutable_arrays(self.schema)?);
+        let chunk = arrays_to_chunk(self.schema, h, self.len)?;
+        self.done.push(chunk);
+        self.len = 0;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Chunk<Box<dyn Array>>> {
+        let last = arrays_to_chunk(self.schema, self.h, self.len)?;
+        if self.done.is_empty() {
+            return Ok(last);
+        }
+        self.done.push(last);
+        let columns = (0..self.done[0].arrays().len())
+            .map(|i| {
+                let arrays = self
+                    .done
+                    .iter()
+                    .map(|c| c.arrays()[i].as_ref())
+                    .collect::<Vec<_>>();
+                arrow2::compute::concatenate::concatenate(&arrays)
+            })
+            .collect::<arrow2::error::Result<Vec<_>>>()?;
+        Ok(Chunk::try_new(columns)?)
+    }
+}
+
+/// Finishes the arrays into a chunk of their first `len` values, in the order
+/// of the schema
+fn arrays_to_chunk(schema: &Schema3, h: FieldArrays, len: usize) -> Result<Chunk<Box<dyn Array>>> {
     let num_fields = schema.fields.len()
         + if !schema.associations.is_empty() {
             1
@@ -328,25 +621,69 @@ pub fn parse_response_to_arrow(schema: &Schema3, bytes: &[u8]) -> Result<Chunk<B
         };
     let mut arrays: Vec<Option<Box<dyn Array>>> = vec![None; num_fields];
     for (i, mut array) in h.into_values() {
-        arrays[i] = Some(array.as_box());
+        let array = array.as_box();
+        arrays[i] = Some(if array.len() > len {
+            array.sliced(0, len)
+        } else {
+            array
+        });
+    }
+    let arrays = arrays.into_iter().flatten().collect::<Vec<_>>();
+    Ok(Chunk::try_new(arrays)?)
+}
+
+/// Parses the records of a response, adding what `on_error` left out to
+/// `skipped` for the caller to report
+pub fn parse_response_to_arrow(
+    schema: &Schema3,
+    bytes: &[u8],
+    on_error: OnError,
+    skipped: &mut Skipped,
+) -> Result<Chunk<Box<dyn Array>>> {
+    let doc = parse_xml(bytes)?;
+    let container = Parser::new(doc.root_element()).records_container()?.node();
+
+    let mut builder = ChunkBuilder::new(schema, on_error, skipped)?;
+    for (position, el) in elements_of(&container).enumerate() {
+        builder.push(position, &el)?;
+    }
+    builder.finish()
+}
+
+/// Same as [`parse_response_to_arrow`], but only ever holds the DOM of a
+/// single record instead of the whole response
+pub fn parse_response_to_arrow_streaming(
+    schema: &Schema3,
+    bytes: &[u8],
+    on_error: OnError,
+    skipped: &mut Skipped,
+) -> Result<Chunk<Box<dyn Array>>> {
+    let source = simdutf8::basic::from_utf8(bytes)?;
+    let mut builder = ChunkBuilder::new(schema, on_error, skipped)?;
+    for (position, record) in xml_stream::records(source).enumerate() {
+        let record = record?;
+        let doc = xml_stream::parse_record(&record)?;
+        builder.push(position, &xml_stream::record_of(&doc)?)?;
     }
-    let arrays = arrays.into_iter().filter_map(|x| x).collect::<Vec<_>>();
-    Ok(Chunk::new(arrays))
+    builder.finish()
 }
 
 #[cfg(test)]
 mod test {
-    use arrow2::array::Utf8Array;
+    use arrow2::array::{Array, BooleanArray, ListArray, PrimitiveArray, StructArray, Utf8Array};
 
-    use crate::arrow2::parse_response::parse_response_to_arrow;
+    use crate::arrow2::parse_response::{
+        number_error, parse_decimal, parse_response_to_arrow, parse_response_to_arrow_streaming,
+    };
     use crate::arrow2::schema3::{Association, DataType, Field, Schema3};
+    use crate::on_error::{OnError, Skipped};
 
     #[test]
     fn test_parse_simple_response() {
         let schema = Schema3 {
             fields: vec![Field {
                 name: "name".to_string(),
-                data_type: DataType::Utf8
+                data_type: DataType::Utf8,
             }],
             associations: vec![],
         };
@@ -365,7 +702,13 @@ mod test {
         </toplevel>
         "#;
 
-        let result = parse_response_to_arrow(&schema, source.as_bytes()).unwrap();
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
         let vec = result.arrays()[0]
             .as_any()
             .downcast_ref::<Utf8Array<i32>>()
@@ -375,6 +718,91 @@ mod test {
         assert_eq!(vec, vec![Some("a"), None, Some("c")]);
     }
 
+    #[test]
+    fn test_parse_cdata_text() {
+        let schema = Schema3 {
+            fields: vec![Field {
+                name: "description".to_string(),
+                data_type: DataType::Utf8,
+            }],
+            associations: vec![],
+        };
+        let source = r#"<prestashop><products>
+            <product><description><![CDATA[<p>hi &amp; bye</p>]]></description></product>
+            <product><description>a &amp; <![CDATA[<b>b</b>]]> c</description></product>
+        </products></prestashop>"#;
+        for streaming in [false, true] {
+            let result = if streaming {
+                parse_response_to_arrow_streaming(
+                    &schema,
+                    source.as_bytes(),
+                    OnError::Fail,
+                    &mut Skipped::default(),
+                )
+            } else {
+                parse_response_to_arrow(
+                    &schema,
+                    source.as_bytes(),
+                    OnError::Fail,
+                    &mut Skipped::default(),
+                )
+            }
+            .unwrap();
+            let vec = result.arrays()[0]
+                .as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>();
+            assert_eq!(
+                vec,
+                vec![Some("<p>hi &amp; bye</p>"), Some("a & <b>b</b> c")]
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_namespaced_elements() {
+        let schema = Schema3 {
+            fields: vec![Field {
+                name: "reference".to_string(),
+                data_type: DataType::Utf8,
+            }],
+            associations: vec![],
+        };
+        let source = r#"<prestashop xmlns="urn:ps" xmlns:ps="urn:ps">
+            <ps:products>
+                <ps:product><ps:reference>a</ps:reference></ps:product>
+                <product><reference>b</reference></product>
+            </ps:products>
+        </prestashop>"#;
+        for streaming in [false, true] {
+            let result = if streaming {
+                parse_response_to_arrow_streaming(
+                    &schema,
+                    source.as_bytes(),
+                    OnError::Fail,
+                    &mut Skipped::default(),
+                )
+            } else {
+                parse_response_to_arrow(
+                    &schema,
+                    source.as_bytes(),
+                    OnError::Fail,
+                    &mut Skipped::default(),
+                )
+            }
+            .unwrap();
+            let vec = result.arrays()[0]
+                .as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>();
+            assert_eq!(vec, vec![Some("a"), Some("b")]);
+        }
+    }
+
     #[test]
     fn test_parse_multilingual_field() {
         let schema = Schema3 {
@@ -405,7 +833,13 @@ mod test {
         </toplevel>
         "#;
 
-        let _result = parse_response_to_arrow(&schema, source.as_bytes()).unwrap();
+        let _result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
         //assert_eq!(vec, vec![Some("a"), None, Some("c")]);
     }
 
@@ -465,8 +899,782 @@ mod test {
         </toplevel>
         "#;
 
-        let result = parse_response_to_arrow(&schema, source.as_bytes()).unwrap();
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
         eprintln!("{:#?}", result);
         //assert_eq!(vec, vec![Some("a"), None, Some("c")]);
     }
+
+    #[test]
+    fn test_parse_missing_and_empty_associations() {
+        let schema = Schema3 {
+            fields: vec![Field::new("id", DataType::UInt32)],
+            associations: vec![
+                Association {
+                    name: "categories".to_string(),
+                    element_name: "category".to_string(),
+                    fields: vec![Field::new("id", DataType::UInt32)],
+                },
+                Association {
+                    name: "tags".to_string(),
+                    element_name: "tag".to_string(),
+                    fields: vec![Field::new("id", DataType::UInt32)],
+                },
+            ],
+        };
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element>
+                    <id>1</id>
+                    <associations>
+                        <categories>
+                            <category><id>1</id></category>
+                            <category><id>2</id></category>
+                        </categories>
+                    </associations>
+                </element>
+                <element><id>2</id></element>
+                <element><id>3</id><associations/></element>
+                <element>
+                    <id>4</id>
+                    <associations><categories/><tags><tag><id>5</id></tag></tags></associations>
+                </element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        assert_eq!(result.len(), 4);
+        let associations = result.arrays()[1]
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap();
+        assert!(associations.is_valid(0));
+        assert!(associations.is_null(1));
+        let lengths = |i: usize| {
+            let lists = associations.values()[i]
+                .as_any()
+                .downcast_ref::<ListArray<i32>>()
+                .unwrap();
+            (0..lists.len())
+                .map(|row| lists.is_valid(row).then(|| lists.value(row).len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lengths(0), vec![Some(2), None, None, Some(0)]);
+        assert_eq!(lengths(1), vec![None, None, None, Some(1)]);
+
+        let streamed = parse_response_to_arrow_streaming(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        assert_eq!(streamed.arrays(), result.arrays());
+    }
+
+    #[test]
+    fn test_parse_association_hrefs() {
+        let schema = Schema3 {
+            fields: vec![Field::new("id", DataType::UInt32)],
+            associations: vec![Association {
+                name: "categories".to_string(),
+                element_name: "category".to_string(),
+                fields: vec![Field::new("id", DataType::UInt32)],
+            }],
+        }
+        .with_association_hrefs();
+        let source = r#"
+        <prestashop xmlns:xlink="http://www.w3.org/1999/xlink">
+            <products>
+                <product>
+                    <id>1</id>
+                    <associations><categories>
+                        <category xlink:href="https://shop/api/categories/2"><id>2</id></category>
+                        <category><id>3</id></category>
+                    </categories></associations>
+                </product>
+            </products>
+        </prestashop>
+        "#;
+
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let categories = result.arrays()[1]
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap()
+            .values()[0]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .unwrap()
+            .value(0);
+        let items = categories.as_any().downcast_ref::<StructArray>().unwrap();
+        let hrefs = items.values()[1]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(hrefs, vec![Some("https://shop/api/categories/2"), None]);
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        let schema = Schema3 {
+            fields: vec![Field::new("id", DataType::UInt32)],
+            associations: vec![Association {
+                name: "images".to_string(),
+                element_name: "image".to_string(),
+                fields: vec![
+                    Field::new("id", DataType::UInt32),
+                    Field::new("@readOnly", DataType::Boolean),
+                    Field::new("@position", DataType::UInt32),
+                    Field::new("@label", DataType::Utf8),
+                ],
+            }],
+        };
+        let source = r#"
+        <prestashop>
+            <products>
+                <product>
+                    <id>1</id>
+                    <associations><images>
+                        <image readOnly="1" position="2" label="front"><id>2</id></image>
+                        <image position=""><id>3</id></image>
+                    </images></associations>
+                </product>
+            </products>
+        </prestashop>
+        "#;
+
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let images = result.arrays()[1]
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap()
+            .values()[0]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .unwrap()
+            .value(0);
+        let items = images.as_any().downcast_ref::<StructArray>().unwrap();
+        let read_only = items.values()[1]
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(read_only, vec![Some(true), None]);
+        let positions = items.values()[2]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<u32>>()
+            .unwrap()
+            .iter()
+            .map(|x| x.copied())
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![Some(2), None]);
+        let labels = items.values()[3]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec![Some("front"), None]);
+    }
+
+    #[test]
+    fn test_skip_unparseable_records() {
+        let schema = Schema3 {
+            fields: vec![Field {
+                name: "id".to_string(),
+                data_type: DataType::UInt32,
+            }],
+            associations: vec![],
+        };
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element><id>1</id></element>
+                <element><id>x</id></element>
+                <element><id>3</id></element>
+            </elements>
+        </toplevel>
+        "#;
+
+        assert!(parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default()
+        )
+        .is_err());
+        let mut skipped = Skipped::default();
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::SkipRecord,
+            &mut skipped,
+        )
+        .unwrap();
+        assert_eq!(skipped.records, 1);
+        let vec = result.arrays()[0]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<u32>>()
+            .unwrap()
+            .iter()
+            .map(|x| x.copied())
+            .collect::<Vec<_>>();
+        assert_eq!(vec, vec![Some(1), Some(3)]);
+    }
+
+    #[test]
+    fn test_skip_records_failing_after_a_field() {
+        let schema = Schema3 {
+            fields: vec![
+                Field::new("id", DataType::UInt32),
+                Field::new("name", DataType::MultilingualUtf8),
+            ],
+            associations: vec![],
+        };
+        // the id and the first language of the second record are pushed
+        // before its second language fails
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element><id>1</id><name><language id="1">a</language></name></element>
+                <element><id>2</id><name><language id="1">b</language><language id="x">c</language></name></element>
+                <element><id>3</id><name><language id="1">d</language></name></element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let mut skipped = Skipped::default();
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::SkipRecord,
+            &mut skipped,
+        )
+        .unwrap();
+        assert_eq!(skipped.records, 1);
+        assert_eq!(result.len(), 2);
+        let ids = result.arrays()[0]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<u32>>()
+            .unwrap()
+            .iter()
+            .map(|x| x.copied())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![Some(1), Some(3)]);
+        let names = result.arrays()[1]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .unwrap();
+        let languages = names.values();
+        let texts = languages
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap()
+            .values()[1]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec![Some("a"), Some("d")]);
+    }
+
+    #[test]
+    fn test_null_unparseable_values() {
+        let schema = Schema3 {
+            fields: vec![
+                Field::new("id", DataType::UInt32),
+                Field::new("quantity", DataType::Int32),
+            ],
+            associations: vec![],
+        };
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element><id>1</id><quantity>5</quantity></element>
+                <element><id>2</id><quantity>x</quantity><unknown/></element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let mut skipped = Skipped::default();
+        let result =
+            parse_response_to_arrow(&schema, source.as_bytes(), OnError::NullValue, &mut skipped)
+                .unwrap();
+        // the unparseable quantity and the unknown field
+        assert_eq!(skipped.values, 2);
+        let ids = result.arrays()[0]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<u32>>()
+            .unwrap()
+            .iter()
+            .map(|x| x.copied())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![Some(1), Some(2)]);
+        let quantities = result.arrays()[1]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<i32>>()
+            .unwrap()
+            .iter()
+            .map(|x| x.copied())
+            .collect::<Vec<_>>();
+        assert_eq!(quantities, vec![Some(5), None]);
+    }
+
+    #[test]
+    fn test_null_unparseable_nested_values() {
+        let schema = Schema3 {
+            fields: vec![
+                Field::new("id", DataType::UInt32),
+                Field::new("name", DataType::MultilingualUtf8),
+            ],
+            associations: vec![],
+        };
+        // the second language fails after the first was parsed, which must
+        // not be left behind in the name column
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element><id>1</id><name><language id="1">a</language><language id="x">b</language></name></element>
+                <element><id>2</id><name><language id="1">c</language></name></element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let mut skipped = Skipped::default();
+        let result =
+            parse_response_to_arrow(&schema, source.as_bytes(), OnError::NullValue, &mut skipped)
+                .unwrap();
+        assert_eq!(skipped.values, 1);
+        let names = result.arrays()[1]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .unwrap();
+        assert!(names.is_null(0));
+        let languages = names.value(1);
+        let texts = languages
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap()
+            .values()[1]
+            .as_any()
+            .downcast_ref::<Utf8Array<i32>>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec![Some("c")]);
+    }
+
+    #[test]
+    fn test_blank_numbers_are_null() {
+        let schema = Schema3 {
+            fields: vec![
+                Field::new("id", DataType::UInt32),
+                Field::new("weight", DataType::Float64),
+                Field::new("name", DataType::MultilingualUtf8),
+            ],
+            associations: vec![],
+        };
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element>
+                    <id>   </id>
+                    <weight>   </weight>
+                    <name><language id="   ">a</language></name>
+                </element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        assert!(result.arrays()[0].is_null(0));
+        assert!(result.arrays()[1].is_null(0));
+        let languages = result.arrays()[2]
+            .as_any()
+            .downcast_ref::<ListArray<i32>>()
+            .unwrap()
+            .value(0);
+        let ids = &languages
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap()
+            .values()[0];
+        assert!(ids.is_null(0));
+    }
+
+    #[test]
+    fn test_collapse_languages() {
+        let schema = Schema3 {
+            fields: vec![
+                Field::new("id", DataType::UInt32),
+                Field::new("name", DataType::MultilingualUtf8),
+            ],
+            associations: vec![],
+        };
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element>
+                    <id>1</id>
+                    <name><language id="1">one</language><language id="2">uno</language></name>
+                </element>
+                <element>
+                    <id>2</id>
+                    <name><language id="1">two</language></name>
+                </element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let arrow2_schema = schema.to_arrow2();
+        let name_type = &arrow2_schema.fields[1].data_type;
+        assert_eq!(
+            crate::arrow2::utils::collapse_language_type(name_type),
+            arrow2::datatypes::DataType::Utf8
+        );
+        let names = crate::arrow2::utils::collapse_languages(result.arrays()[1].as_ref(), 2);
+        let names = names.as_any().downcast_ref::<Utf8Array<i32>>().unwrap();
+        assert_eq!(names.iter().collect::<Vec<_>>(), vec![Some("uno"), None]);
+        let ids = crate::arrow2::utils::collapse_languages(result.arrays()[0].as_ref(), 2);
+        assert_eq!(ids.data_type(), &arrow2::datatypes::DataType::UInt32);
+    }
+
+    #[test]
+    fn test_parse_64_bit_integers() {
+        let schema = Schema3 {
+            fields: vec![
+                Field::new("ip_address", DataType::Int64),
+                Field::new("id_big", DataType::UInt64),
+                Field::new("id_small", DataType::UInt32),
+            ],
+            associations: vec![],
+        };
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element>
+                    <ip_address>-1062731520</ip_address>
+                    <id_big>5000000000</id_big>
+                </element>
+                <element>
+                    <ip_address>3232235776</ip_address>
+                </element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let ip = result.arrays()[0]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<i64>>()
+            .unwrap();
+        assert_eq!(ip.value(0), -1062731520);
+        assert_eq!(ip.value(1), 3232235776);
+        let id = result.arrays()[1]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<u64>>()
+            .unwrap();
+        assert_eq!(id.value(0), 5_000_000_000);
+        assert!(id.is_null(1));
+        // values beyond 32 bits are an error, not a silent overflow
+        let source = source.replace(
+            "<ip_address>3232235776",
+            "<id_small>5000000000</id_small><ip_address>1",
+        );
+        assert!(parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_date_as_epoch_seconds() {
+        let schema = Schema3 {
+            fields: vec![Field {
+                name: "date_add".to_string(),
+                data_type: DataType::Date,
+            }],
+            associations: vec![],
+        };
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element><date_add>2020-01-02 03:04:05</date_add></element>
+                <element><date_add>2020-01-02</date_add></element>
+                <element><date_add>0000-00-00 00:00:00</date_add></element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let array = result.arrays()[0]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<i64>>()
+            .unwrap();
+        assert_eq!(array.value(0), 1577934245);
+        assert_eq!(array.value(1), 1577923200);
+        assert!(array.is_null(2));
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(parse_decimal("12.5", 20, 6).unwrap(), 12_500_000);
+        assert_eq!(parse_decimal("-0.000001", 20, 6).unwrap(), -1);
+        assert_eq!(parse_decimal("19.990000000", 20, 6).unwrap(), 19_990_000);
+        assert_eq!(parse_decimal("7", 20, 6).unwrap(), 7_000_000);
+        assert_eq!(parse_decimal("0.0", 20, 6).unwrap(), 0);
+        // silently rounding would defeat the point of exact prices
+        assert!(parse_decimal("0.0000001", 20, 6).is_err());
+        assert!(parse_decimal("123456789012345.5", 20, 6).is_err());
+        assert!(parse_decimal("1e3", 20, 6).is_err());
+        assert!(parse_decimal("-", 20, 6).is_err());
+    }
+
+    #[test]
+    fn test_parse_price_as_decimal() {
+        let schema = Schema3 {
+            fields: vec![Field {
+                name: "price".to_string(),
+                data_type: DataType::Decimal(20, 6),
+            }],
+            associations: vec![],
+        };
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element><price>24.900000</price></element>
+                <element><price></price></element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let array = result.arrays()[0]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<i128>>()
+            .unwrap();
+        assert_eq!(
+            array.data_type(),
+            &arrow2::datatypes::DataType::Decimal(20, 6)
+        );
+        assert_eq!(array.value(0), 24_900_000);
+        assert!(array.is_null(1));
+    }
+
+    #[test]
+    fn test_column_order_matches_schema() {
+        let field = |name: &str, data_type| Field {
+            name: name.to_string(),
+            data_type,
+        };
+        let schema = Schema3 {
+            fields: vec![
+                field("id", DataType::UInt32),
+                field("reference", DataType::Utf8),
+                field("active", DataType::Boolean),
+                field("quantity", DataType::Int32),
+                field("weight", DataType::Float64),
+                field("date_add", DataType::Date),
+                field("price", DataType::Decimal(20, 6)),
+            ],
+            associations: vec![Association {
+                name: "categories".to_string(),
+                element_name: "category".to_string(),
+                fields: vec![field("id", DataType::UInt32)],
+            }],
+        };
+        // elements list fields in a different order than the schema
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element>
+                    <associations><categories><category><id>1</id></category></categories></associations>
+                    <price>1.5</price>
+                    <weight>2.5</weight>
+                    <active>1</active>
+                    <id>7</id>
+                </element>
+            </elements>
+        </toplevel>
+        "#;
+
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let expected = schema
+            .to_arrow2()
+            .fields
+            .into_iter()
+            .map(|f| f.data_type)
+            .collect::<Vec<_>>();
+        let actual = result
+            .arrays()
+            .iter()
+            .map(|a| a.data_type().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+        let id = result.arrays()[0]
+            .as_any()
+            .downcast_ref::<PrimitiveArray<u32>>()
+            .unwrap();
+        assert_eq!(id.value(0), 7);
+    }
+
+    #[test]
+    fn test_parse_bools() {
+        let schema = Schema3 {
+            fields: vec![Field {
+                name: "active".to_string(),
+                data_type: DataType::Boolean,
+            }],
+            associations: vec![],
+        };
+        let source = r#"
+        <toplevel>
+            <elements>
+                <element><active>true</active></element>
+                <element><active>No</active></element>
+                <element><active>YES</active></element>
+                <element><active></active></element>
+                <element><active> </active></element>
+            </elements>
+        </toplevel>
+        "#;
+        let result = parse_response_to_arrow(
+            &schema,
+            source.as_bytes(),
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let active = result.arrays()[0]
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        let values = active.iter().collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![Some(true), Some(false), Some(true), None, None]
+        );
+    }
+
+    #[test]
+    fn test_int32_overflow_error() {
+        let schema = Schema3 {
+            fields: vec![Field {
+                name: "quantity".to_string(),
+                data_type: DataType::Int32,
+            }],
+            associations: vec![],
+        };
+        let parse = |quantity: &str| {
+            let source = format!(
+                "<toplevel><elements><element><quantity>{}</quantity></element></elements></toplevel>",
+                quantity
+            );
+            parse_response_to_arrow(
+                &schema,
+                source.as_bytes(),
+                OnError::Fail,
+                &mut Skipped::default(),
+            )
+        };
+        assert!(parse("-12").is_ok());
+        let message = format!("{:#}", parse("3000000000").unwrap_err());
+        assert!(
+            message.contains(
+                "value \"3000000000\" of field \"quantity\" is out of range for int32, \
+                 try --cast quantity=int64"
+            ),
+            "{}",
+            message
+        );
+        let message = format!("{:#}", parse("12a").unwrap_err());
+        assert!(
+            message.contains("invalid int32 value \"12a\" of field \"quantity\""),
+            "{}",
+            message
+        );
+        // attributes and association fields can't be cast
+        let message = number_error(
+            &arrow2::datatypes::DataType::UInt32,
+            "@id",
+            "-1",
+            "invalid digit",
+            false,
+        )
+        .to_string();
+        assert_eq!(
+            message,
+            "value \"-1\" of field \"@id\" is out of range for uint32"
+        );
+    }
 }
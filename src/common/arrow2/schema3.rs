@@ -1,18 +1,18 @@
 use anyhow::{anyhow, Result};
 use arrow2::datatypes::TimeUnit;
-use serde_json;
 
-use crate::format::Format;
-use crate::arrow2::utils::{elements_of, parse_xml};
+use crate::arrow2::utils::{elements_of, format_schema_compact, parse_xml};
+use crate::format::{format_fallback, Format};
+use crate::utils::is_id_name;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Association {
     pub name: String,
     pub element_name: String,
     pub fields: Vec<Field>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Schema3 {
     pub fields: Vec<Field>,
     pub associations: Vec<Association>,
@@ -62,19 +62,125 @@ impl Schema3 {
         }
         arrow2::datatypes::Schema::from(fields)
     }
+
+    /// Adds an `@href` field to every association, holding the `xlink:href`
+    /// URL of each associated resource
+    pub fn with_association_hrefs(mut self) -> Self {
+        for association in &mut self.associations {
+            if !association.fields.iter().any(|f| f.name == "@href") {
+                association.fields.push(Field::new("@href", DataType::Utf8));
+            }
+        }
+        self
+    }
+
+    /// Overrides the inferred type of a top-level field
+    pub fn cast_field(&mut self, name: &str, data_type: DataType) -> Result<()> {
+        let field = self
+            .fields
+            .iter_mut()
+            .find(|f| f.name == name)
+            .ok_or_else(|| anyhow!("cannot cast unknown field {:?}", name))?;
+        if matches!(field.data_type, DataType::MultilingualUtf8) {
+            return Err(anyhow!("cannot cast multilingual field {:?}", name));
+        }
+        field.data_type = data_type;
+        Ok(())
+    }
+
+    /// Adds a top-level field that the resource's schema does not describe,
+    /// e.g. a price computed because of a `price[field][..]` parameter
+    pub fn add_field(&mut self, name: &str, data_type: DataType) -> Result<()> {
+        if self.field_names().contains(&name) {
+            return Err(anyhow!("field {:?} already exists", name));
+        }
+        self.fields.push(Field::new(name, data_type));
+        Ok(())
+    }
+
+    /// Names of the top-level fields, including `associations` if there are any
+    pub fn field_names(&self) -> Vec<&str> {
+        let mut names = self
+            .fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        if !self.associations.is_empty() {
+            names.push("associations");
+        }
+        names
+    }
+}
+
+/// Renders the compact field tree of [format_schema_compact]
+impl std::fmt::Display for Schema3 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format_schema_compact(&self.to_arrow2()))
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DataType {
     Int32,
+    Int64,
     Date,
     Boolean,
     UInt32,
+    UInt64,
     Float64,
+    /// Exact decimal with the given precision and scale
+    Decimal(usize, usize),
     Utf8,
     MultilingualUtf8,
 }
+/// Renders the name accepted by [DataType::from_cast_name], or `multilingual`
+impl std::fmt::Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataType::Int32 => f.write_str("int32"),
+            DataType::Int64 => f.write_str("int64"),
+            DataType::Date => f.write_str("date"),
+            DataType::Boolean => f.write_str("bool"),
+            DataType::UInt32 => f.write_str("uint32"),
+            DataType::UInt64 => f.write_str("uint64"),
+            DataType::Float64 => f.write_str("float64"),
+            DataType::Decimal(precision, scale) => write!(f, "decimal({},{})", precision, scale),
+            DataType::Utf8 => f.write_str("utf8"),
+            DataType::MultilingualUtf8 => f.write_str("multilingual"),
+        }
+    }
+}
+
 impl DataType {
+    /// Parses the type of a `--cast`: utf8, bool, int32, uint32, int64, uint64,
+    /// float64, date or decimal(precision,scale)
+    pub fn from_cast_name(name: &str) -> Result<DataType> {
+        Ok(match name.trim().to_ascii_lowercase().as_str() {
+            "utf8" | "string" => DataType::Utf8,
+            "bool" | "boolean" => DataType::Boolean,
+            "int32" => DataType::Int32,
+            "uint32" => DataType::UInt32,
+            "int64" => DataType::Int64,
+            "uint64" => DataType::UInt64,
+            "float64" => DataType::Float64,
+            "date" => DataType::Date,
+            other => {
+                let decimal = other
+                    .strip_prefix("decimal(")
+                    .and_then(|s| s.strip_suffix(')'))
+                    .and_then(|s| s.split_once(','))
+                    .and_then(|(p, s)| Some((p.trim().parse().ok()?, s.trim().parse().ok()?)));
+                match decimal {
+                    Some((precision, scale)) if scale <= precision && precision <= 38 => {
+                        DataType::Decimal(precision, scale)
+                    }
+                    _ => return Err(anyhow!("unknown type {:?}", name)),
+                }
+            }
+        })
+    }
+
     pub fn to_arrow2(&self) -> arrow2::datatypes::DataType {
         match self {
             DataType::Utf8 => arrow2::datatypes::DataType::Utf8,
@@ -90,17 +196,28 @@ impl DataType {
             }
             DataType::UInt32 => arrow2::datatypes::DataType::UInt32,
             DataType::Float64 => arrow2::datatypes::DataType::Float64,
+            DataType::Decimal(precision, scale) => {
+                arrow2::datatypes::DataType::Decimal(*precision, *scale)
+            }
             DataType::Int32 => arrow2::datatypes::DataType::Int32,
+            DataType::Int64 => arrow2::datatypes::DataType::Int64,
+            DataType::UInt64 => arrow2::datatypes::DataType::UInt64,
             DataType::Boolean => arrow2::datatypes::DataType::Boolean,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Field {
     pub name: String,
     pub data_type: DataType,
 }
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.data_type)
+    }
+}
+
 impl Field {
     pub fn new(name: &str, data_type: DataType) -> Self {
         Self {
@@ -114,7 +231,7 @@ impl Field {
 }
 
 fn type_from_name(name: &str) -> Option<DataType> {
-    if name.starts_with("id") || name.ends_with("id") {
+    if is_id_name(name) {
         Some(DataType::UInt32)
     } else if name.starts_with("date_") || name.ends_with("_date") {
         Some(DataType::Date)
@@ -123,50 +240,81 @@ fn type_from_name(name: &str) -> Option<DataType> {
     }
 }
 
+/// Same mapping as schema2's `Type::from_format`, except for the prices
 fn type_from_format(f: &Format) -> Result<DataType> {
     Ok(match f {
         Format::IsBool => DataType::Boolean,
         Format::IsUnsignedId => DataType::UInt32,
+        Format::IsNullOrUnsignedId => DataType::UInt32,
         Format::IsUnsignedInt => DataType::UInt32,
         Format::IsInt => DataType::Int32,
+        Format::IsIp2Long => DataType::Int64,
         Format::IsUnsignedFloat => DataType::Float64,
-        Format::IsPrice => DataType::Float64,
-        Format::IsDateFormat => DataType::Utf8,
+        // prices are stored as decimal(20,6) by PrestaShop
+        Format::IsPrice => DataType::Decimal(20, 6),
+        Format::IsNegativePrice => DataType::Decimal(20, 6),
+        Format::IsDateFormat => DataType::Date,
         Format::IsDate => DataType::Date,
+        Format::IsJson => DataType::Utf8,
+        Format::IsSerializedArray => DataType::Utf8,
+        f if f.is_string_like() => DataType::Utf8,
         _ => return Err(anyhow!("format {:?} is not supported", f)),
     })
 }
 
-fn parse_format_attribute(node: &roxmltree::Node) -> Result<Option<Format>> {
+fn parse_simple_datatype(node: &roxmltree::Node, strict: bool) -> Result<DataType> {
     if let Some(value) = node.attribute("format") {
-        Ok(Some(serde_json::from_value(serde_json::Value::String(
-            value.to_string(),
-        ))?))
-    } else {
-        Ok(None)
+        match Format::from_string(value.to_string()).and_then(|f| type_from_format(&f)) {
+            Ok(data_type) => return Ok(data_type),
+            Err(e) => {
+                format_fallback(node.tag_name().name(), e, strict)?;
+                return Ok(DataType::Utf8);
+            }
+        }
     }
+    Ok(type_from_name(node.tag_name().name()).unwrap_or(DataType::Utf8))
 }
-fn parse_simple_datatype(node: &roxmltree::Node) -> Result<DataType> {
-    Ok(parse_format_attribute(node)?
-        .and_then(|f| type_from_format(&f).ok())
-        .or_else(|| type_from_name(node.tag_name().name()))
-        .unwrap_or(DataType::Utf8))
+/// Duplicated names would collide when parsing responses, see parse_response_to_arrow
+fn check_unique_names<'a>(names: impl IntoIterator<Item = &'a str>, context: &str) -> Result<()> {
+    let mut seen = std::collections::HashSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            return Err(anyhow!(
+                "field {:?} occurs more than once in {}",
+                name,
+                context
+            ));
+        }
+    }
+    Ok(())
 }
+
 fn has_language_child(node: &roxmltree::Node) -> bool {
     node.children()
         .any(|child| child.has_tag_name("language") && child.has_attribute("id"))
 }
 
 pub fn parse_schema(bytes: &[u8]) -> Result<Schema3> {
+    parse_schema_with(bytes, false, true)
+}
+
+/// Same as [parse_schema], but formats that are unknown or have no type are an
+/// error when `strict` instead of being read as strings. Without `id_field`
+/// the `id` the synopsis leaves out isn't added, for resources whose records
+/// have no id
+pub fn parse_schema_with(bytes: &[u8], strict: bool, id_field: bool) -> Result<Schema3> {
     let doc = parse_xml(bytes)?;
     let fields_container = doc
         .root_element()
         .first_element_child()
         .ok_or(anyhow!("no elements in root"))?;
-    let mut fields = vec![Field {
-        name: "id".to_string(),
-        data_type: DataType::UInt32,
-    }];
+    let mut fields = vec![];
+    if id_field {
+        fields.push(Field {
+            name: "id".to_string(),
+            data_type: DataType::UInt32,
+        });
+    }
     let mut associations = vec![];
     for node in elements_of(&fields_container) {
         if node.has_tag_name("associations") {
@@ -178,9 +326,13 @@ pub fn parse_schema(bytes: &[u8]) -> Result<Schema3> {
                 for el in elements_of(&assoc2) {
                     fields.push(Field {
                         name: el.tag_name().name().to_string(),
-                        data_type: parse_simple_datatype(&el)?,
+                        data_type: parse_simple_datatype(&el, strict)?,
                     });
                 }
+                check_unique_names(
+                    fields.iter().map(|f| f.name.as_str()),
+                    &format!("association {:?}", assoc1.tag_name().name()),
+                )?;
                 associations.push(Association {
                     name: assoc1.tag_name().name().to_string(),
                     element_name: assoc2.tag_name().name().to_string(),
@@ -195,12 +347,180 @@ pub fn parse_schema(bytes: &[u8]) -> Result<Schema3> {
         } else {
             fields.push(Field {
                 name: node.tag_name().name().to_string(),
-                data_type: parse_simple_datatype(&node)?,
+                data_type: parse_simple_datatype(&node, strict)?,
             });
         };
     }
+    check_unique_names(
+        fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .chain(if associations.is_empty() {
+                None
+            } else {
+                Some("associations")
+            }),
+        &format!("{:?}", fields_container.tag_name().name()),
+    )?;
+    check_unique_names(associations.iter().map(|a| a.name.as_str()), "associations")?;
     Ok(Schema3 {
         fields,
-        associations
+        associations,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_equality_and_display() {
+        let xml = "<prestashop><product><reference/><price format=\"isPrice\"/>\
+            <associations><images><image><id/></image></images></associations>\
+            </product></prestashop>";
+        let schema = parse_schema(xml.as_bytes()).unwrap();
+        assert_eq!(schema, parse_schema(xml.as_bytes()).unwrap());
+        let mut changed = schema.clone();
+        changed.cast_field("price", DataType::Float64).unwrap();
+        assert_ne!(schema, changed);
+
+        let text = schema.to_string();
+        assert!(text.contains("lvl=0 price: decimal"), "{}", text);
+        assert!(text.contains("lvl=0 associations: struct"), "{}", text);
+        assert_eq!(
+            Field::new("price", DataType::Decimal(20, 6)).to_string(),
+            "price: decimal(20,6)"
+        );
+        for ty in ["utf8", "bool", "uint64", "date", "decimal(10,2)"] {
+            assert_eq!(DataType::from_cast_name(ty).unwrap().to_string(), ty);
+        }
+    }
+
+    #[test]
+    fn test_unknown_formats() {
+        let xml = "<prestashop><product><id_thing format=\"isSomethingNew\"/>\
+            <birthday format=\"isBirthDate\"/></product></prestashop>";
+        let schema = parse_schema(xml.as_bytes()).unwrap();
+        assert_eq!(
+            schema.fields[1..],
+            [
+                Field::new("id_thing", DataType::Utf8),
+                Field::new("birthday", DataType::Utf8)
+            ]
+        );
+        let err = parse_schema_with(xml.as_bytes(), true, true).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "cannot type field \"id_thing\": unknown format \"isSomethingNew\""
+        );
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let xml = "<prestashop><product><name><language id=\"1\"/></name>\
+            <price format=\"isPrice\"/>\
+            <associations><images><image><id/></image></images></associations>\
+            </product></prestashop>";
+        let schema = parse_schema(xml.as_bytes()).unwrap();
+        let json = serde_json::to_value(&schema).unwrap();
+        assert_eq!(
+            json["fields"],
+            serde_json::json!([
+                {"name": "id", "data_type": "uint32"},
+                {"name": "name", "data_type": "multilingualutf8"},
+                {"name": "price", "data_type": {"decimal": [20, 6]}}
+            ])
+        );
+        assert_eq!(serde_json::from_value::<Schema3>(json).unwrap(), schema);
+    }
+
+    #[test]
+    fn test_duplicate_fields_are_an_error() {
+        let schema = |fields: &str| {
+            parse_schema(
+                format!("<prestashop><product>{}</product></prestashop>", fields).as_bytes(),
+            )
+        };
+        assert!(schema("<reference/><price format=\"isPrice\"/>").is_ok());
+        let err = schema("<reference/><price/><reference/>").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "field \"reference\" occurs more than once in \"product\""
+        );
+        // the id field is always added
+        assert!(schema("<id/>").is_err());
+        let err = schema("<associations><images><image><id/><id/></image></images></associations>")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("association \"images\""),
+            "{}",
+            err
+        );
+        assert!(schema(
+            "<associations><images><image><id/></image></images><images><image><id/></image></images></associations>"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_cast_field() {
+        let mut schema = parse_schema(
+            br#"<prestashop><product>
+                <id_supplier/><reference/><name><language id="1"/></name>
+            </product></prestashop>"#,
+        )
+        .unwrap();
+        schema
+            .cast_field("id_supplier", DataType::from_cast_name("utf8").unwrap())
+            .unwrap();
+        schema
+            .cast_field("reference", DataType::from_cast_name("Int64").unwrap())
+            .unwrap();
+        let types = schema
+            .fields
+            .iter()
+            .map(|f| format!("{}: {:?}", f.name, f.data_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                "id: UInt32",
+                "id_supplier: Utf8",
+                "reference: Int64",
+                "name: MultilingualUtf8"
+            ]
+        );
+        assert!(matches!(
+            DataType::from_cast_name("decimal(10, 2)"),
+            Ok(DataType::Decimal(10, 2))
+        ));
+        assert!(DataType::from_cast_name("decimal(2,10)").is_err());
+        assert!(DataType::from_cast_name("text").is_err());
+        assert!(schema.cast_field("nmae", DataType::Utf8).is_err());
+        assert!(schema.cast_field("name", DataType::Utf8).is_err());
+    }
+
+    #[test]
+    fn test_schema_to_json() {
+        let schema = parse_schema(
+            br#"<prestashop xmlns:xlink="http://www.w3.org/1999/xlink"><product>
+                <price format="isPrice"/>
+                <associations><categories><category><id/></category></categories></associations>
+            </product></prestashop>"#,
+        )
+        .unwrap();
+        let json = crate::arrow2::utils::schema_to_json(&schema.to_arrow2());
+        assert_eq!(
+            json["fields"][1],
+            serde_json::json!({
+                "name": "price",
+                "type": {"decimal": {"precision": 20, "scale": 6}},
+                "nullable": true,
+            })
+        );
+        assert_eq!(
+            json["fields"][2]["type"]["struct"][0]["type"]["list"]["type"]["struct"][0]["type"],
+            "uint32"
+        );
+    }
+}
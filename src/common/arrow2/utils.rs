@@ -1,11 +1,13 @@
-use arrow2::array::{Array, StructArray};
+use arrow2::array::{Array, ListArray, PrimitiveArray, StructArray, Utf8Array};
 use arrow2::chunk::Chunk;
-use arrow2::datatypes::DataType;
+use arrow2::datatypes::{DataType, Field, TimeUnit};
 
-
-pub fn write_ndjson<W, I>(writer:W, array: I) where W: std::io::Write, I : IntoIterator<Item = Box<dyn Array>>{
-    let serializer =
-        arrow2::io::ndjson::write::Serializer::new(array.into_iter().map(Ok), vec![]);
+pub fn write_ndjson<W, I>(writer: W, array: I)
+where
+    W: std::io::Write,
+    I: IntoIterator<Item = Box<dyn Array>>,
+{
+    let serializer = arrow2::io::ndjson::write::Serializer::new(array.into_iter().map(Ok), vec![]);
 
     let mut writer = arrow2::io::ndjson::write::FileWriter::new(writer, serializer);
     writer.by_ref().for_each(|x| x.unwrap());
@@ -23,7 +25,154 @@ pub fn chunk_to_array(
     .boxed()
 }
 
-pub fn parse_xml(bytes: &[u8]) -> anyhow::Result<roxmltree::Document> {
+/// Formats an integer scaled by `10^scale` as a decimal string
+pub fn format_decimal(value: i128, scale: usize) -> String {
+    let digits = format!("{:0>width$}", value.unsigned_abs(), width = scale + 1);
+    let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+    let sign = if value < 0 { "-" } else { "" };
+    if scale == 0 {
+        format!("{}{}", sign, int_part)
+    } else {
+        format!("{}{}.{}", sign, int_part, frac_part)
+    }
+}
+
+/// Replaces decimal arrays, also nested ones, with their exact string form.
+/// The arrow2 JSON and CSV writers do not support decimals
+pub fn decimals_to_utf8(array: &dyn Array) -> Box<dyn Array> {
+    match array.data_type() {
+        DataType::Decimal(_, scale) => {
+            let array = array
+                .as_any()
+                .downcast_ref::<PrimitiveArray<i128>>()
+                .expect("decimal array");
+            let values: Vec<_> = array
+                .iter()
+                .map(|v| v.map(|v| format_decimal(*v, *scale)))
+                .collect();
+            Utf8Array::<i32>::from(values).boxed()
+        }
+        DataType::Struct(fields) => {
+            let array = array
+                .as_any()
+                .downcast_ref::<StructArray>()
+                .expect("struct array");
+            let values: Vec<_> = array
+                .values()
+                .iter()
+                .map(|v| decimals_to_utf8(v.as_ref()))
+                .collect();
+            let fields = fields
+                .iter()
+                .zip(&values)
+                .map(|(f, v)| Field::new(&f.name, v.data_type().clone(), f.is_nullable))
+                .collect();
+            StructArray::new(DataType::Struct(fields), values, array.validity().cloned()).boxed()
+        }
+        DataType::List(field) => {
+            let array = array
+                .as_any()
+                .downcast_ref::<ListArray<i32>>()
+                .expect("list array");
+            let values = decimals_to_utf8(array.values().as_ref());
+            let field = Field::new(&field.name, values.data_type().clone(), field.is_nullable);
+            ListArray::<i32>::new(
+                DataType::List(Box::new(field)),
+                array.offsets().clone(),
+                values,
+                array.validity().cloned(),
+            )
+            .boxed()
+        }
+        _ => array.to_boxed(),
+    }
+}
+
+fn is_language_list(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::List(item) => match &item.data_type {
+            DataType::Struct(fields) => {
+                let names = fields.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+                names == ["@id", "#text"]
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The type [collapse_languages] turns an array of `data_type` into
+pub fn collapse_language_type(data_type: &DataType) -> DataType {
+    match data_type {
+        t if is_language_list(t) => DataType::Utf8,
+        DataType::Struct(fields) => DataType::Struct(
+            fields
+                .iter()
+                .map(|f| {
+                    let data_type = collapse_language_type(&f.data_type);
+                    Field::new(&f.name, data_type, f.is_nullable)
+                })
+                .collect(),
+        ),
+        t => t.clone(),
+    }
+}
+
+/// Replaces multilingual arrays, lists of `{@id, #text}`, by the text of the
+/// language with `language_id`, also within structs. Rows without that
+/// language are null
+pub fn collapse_languages(array: &dyn Array, language_id: u32) -> Box<dyn Array> {
+    match array.data_type() {
+        t if is_language_list(t) => {
+            let list = array
+                .as_any()
+                .downcast_ref::<ListArray<i32>>()
+                .expect("list array");
+            let items = list
+                .values()
+                .as_any()
+                .downcast_ref::<StructArray>()
+                .expect("struct array");
+            let ids = items.values()[0]
+                .as_any()
+                .downcast_ref::<PrimitiveArray<u32>>()
+                .expect("u32 array");
+            let texts = items.values()[1]
+                .as_any()
+                .downcast_ref::<Utf8Array<i32>>()
+                .expect("utf8 array");
+            let offsets = list.offsets().buffer();
+            let values: Vec<_> = (0..list.len())
+                .map(|row| {
+                    if !list.is_valid(row) {
+                        return None;
+                    }
+                    let (start, end) = (offsets[row] as usize, offsets[row + 1] as usize);
+                    (start..end)
+                        .find(|&i| ids.get(i) == Some(language_id))
+                        .and_then(|i| texts.get(i))
+                })
+                .collect();
+            Utf8Array::<i32>::from(values).boxed()
+        }
+        DataType::Struct(_) => {
+            let array = array
+                .as_any()
+                .downcast_ref::<StructArray>()
+                .expect("struct array");
+            let values: Vec<_> = array
+                .values()
+                .iter()
+                .map(|v| collapse_languages(v.as_ref(), language_id))
+                .collect();
+            let data_type = collapse_language_type(array.data_type());
+            StructArray::new(data_type, values, array.validity().cloned()).boxed()
+        }
+        _ => array.to_boxed(),
+    }
+}
+
+pub fn parse_xml(bytes: &[u8]) -> anyhow::Result<roxmltree::Document<'_>> {
     let doc = roxmltree::Document::parse(simdutf8::basic::from_utf8(bytes)?)?;
     Ok(doc)
 }
@@ -51,11 +200,52 @@ pub fn format_schema_compact(schema: &arrow2::datatypes::Schema) -> String {
             DataType::Utf8 => "string",
             DataType::UInt32 => "uint32",
             DataType::Int32 => "int32",
+            DataType::UInt64 => "uint64",
+            DataType::Int64 => "int64",
             DataType::Float64 => "float64",
+            DataType::Decimal(_, _) => "decimal",
             DataType::Date64 => "date64",
+            DataType::Boolean => "bool",
+            DataType::Timestamp(_, _) => "timestamp",
             _ => "unknown",
         };
         lines.push(format!("lvl={} {}: {}", d, x.name, ty));
     }
     lines.join("\n")
 }
+
+/// Describes the schema as JSON, e.g. `{"fields": [{"name": "id", "type": "uint32", "nullable": true}]}`.
+/// Nested types are objects like `{"list": field}`, `{"struct": [field, ..]}` and
+/// `{"decimal": {"precision": 20, "scale": 6}}`
+pub fn schema_to_json(schema: &arrow2::datatypes::Schema) -> serde_json::Value {
+    serde_json::json!({ "fields": schema.fields.iter().map(field_to_json).collect::<Vec<_>>() })
+}
+
+fn field_to_json(field: &Field) -> serde_json::Value {
+    serde_json::json!({
+        "name": field.name,
+        "type": data_type_to_json(&field.data_type),
+        "nullable": field.is_nullable,
+    })
+}
+
+fn data_type_to_json(data_type: &DataType) -> serde_json::Value {
+    match data_type {
+        DataType::Boolean => "bool".into(),
+        DataType::Int32 => "int32".into(),
+        DataType::UInt32 => "uint32".into(),
+        DataType::Int64 => "int64".into(),
+        DataType::UInt64 => "uint64".into(),
+        DataType::Float64 => "float64".into(),
+        DataType::Utf8 => "string".into(),
+        DataType::Timestamp(TimeUnit::Second, None) => "timestamp[s]".into(),
+        DataType::Decimal(precision, scale) => {
+            serde_json::json!({ "decimal": { "precision": precision, "scale": scale } })
+        }
+        DataType::List(field) => serde_json::json!({ "list": field_to_json(field) }),
+        DataType::Struct(fields) => {
+            serde_json::json!({ "struct": fields.iter().map(field_to_json).collect::<Vec<_>>() })
+        }
+        other => format!("{:?}", other).into(),
+    }
+}
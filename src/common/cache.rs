@@ -0,0 +1,46 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::info;
+
+/// Plain file cache, one file per key, expired by modification time
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let file_name = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        self.dir.join(file_name + ".json")
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        let path = self.path(key);
+        let modified = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.modified()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if modified.elapsed().unwrap_or_default() > self.ttl {
+            info!("cache entry {} expired", path.display());
+            return Ok(None);
+        }
+        info!("using cached {}", path.display());
+        Ok(Some(std::fs::read_to_string(path)?))
+    }
+
+    pub fn put(&self, key: &str, value: &str) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(key), value)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! Fetching resources as arrow record batches, independent of any output
+
+use anyhow::{anyhow, Result};
+use arrow::array::{RecordBatch, StructArray};
+
+use crate::http::{ws_get_resource2_arrow, ws_get_resource_schema2, Http, QueryParam, Resource};
+use crate::on_error::OnError;
+use crate::schema2;
+
+/// How [fetch_resource_batches] fetches and shapes the records
+#[derive(Debug, Clone, Copy)]
+pub struct FetchOptions {
+    pub on_error: OnError,
+    /// Fetch in pages of this many records instead of a single request
+    pub page_size: Option<usize>,
+    /// Parse responses one record at a time, see [crate::xml_stream]
+    pub streaming: bool,
+    /// Replace the single struct column named after the resource element by
+    /// its fields, so that every field of the resource is a column
+    pub flatten1: bool,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            on_error: OnError::default(),
+            page_size: None,
+            streaming: false,
+            flatten1: true,
+        }
+    }
+}
+
+/// Fetches the schema of `resource`, then the records matching `params`
+pub async fn fetch_resource_batches(
+    http: &Http,
+    resource: &Resource,
+    params: &[QueryParam],
+    options: FetchOptions,
+) -> Result<Vec<RecordBatch>> {
+    let schema = ws_get_resource_schema2(http, resource).await?;
+    let batch = fetch_resource_batch(http, resource, &schema, params, options).await?;
+    Ok(vec![batch])
+}
+
+/// Fetches the records matching `params` with an already fetched schema
+pub async fn fetch_resource_batch(
+    http: &Http,
+    resource: &Resource,
+    schema: &schema2::Schema,
+    params: &[QueryParam],
+    options: FetchOptions,
+) -> Result<RecordBatch> {
+    let batch = ws_get_resource2_arrow(
+        http,
+        resource,
+        schema,
+        params,
+        options.on_error,
+        options.page_size,
+        options.streaming,
+    )
+    .await?;
+    if options.flatten1 {
+        flatten_single_toplevel_struct(&batch)
+    } else {
+        Ok(batch)
+    }
+}
+
+pub fn flatten_single_toplevel_struct(batch: &RecordBatch) -> Result<RecordBatch> {
+    if batch.num_columns() != 1 {
+        return Err(anyhow!(
+            "cannot flatten1 when top level has more than one field"
+        ));
+    }
+    let sa = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<StructArray>()
+        .ok_or_else(|| anyhow!("failed casting ot StructArray"))?;
+    let new_batch = RecordBatch::from(sa);
+    Ok(new_batch)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow::array::{Array, Int32Array};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_flatten_single_toplevel_struct() {
+        let ids = Arc::new(Int32Array::from(vec![1, 2])) as _;
+        let product = StructArray::try_from(vec![("id", ids)]).unwrap();
+        let batch =
+            RecordBatch::try_from_iter(vec![("product", Arc::new(product) as Arc<dyn Array>)])
+                .unwrap();
+        let flat = flatten_single_toplevel_struct(&batch).unwrap();
+        assert_eq!(flat.schema().field(0).name(), "id");
+        assert_eq!(flat.num_rows(), 2);
+        let two = RecordBatch::try_from_iter(vec![
+            ("a", flat.column(0).clone()),
+            ("b", flat.column(0).clone()),
+        ])
+        .unwrap();
+        assert!(flatten_single_toplevel_struct(&two).is_err());
+    }
+}
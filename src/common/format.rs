@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use arrow::datatypes::DataType;
+use tracing::warn;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -74,41 +74,72 @@ pub enum Format {
 }
 
 impl Format {
-    pub fn to_arrow(&self) -> Result<DataType> {
-        Ok(match self {
-            Format::IsBool => DataType::Boolean,
-            Format::IsUnsignedId => DataType::UInt32,
-            Format::IsUnsignedInt => DataType::UInt32,
-            Format::IsInt => DataType::Int32,
-            Format::IsUnsignedFloat => DataType::Float64,
-            Format::IsPrice => DataType::Float64,
-
-            // these are integers
-            Format::IsEan13 => DataType::Utf8,
-            Format::IsUpc => DataType::Utf8,
-            Format::IsIsbn => DataType::Utf8,
-            //
-            Format::IsDateFormat => DataType::Utf8,
-            Format::IsDate => DataType::Utf8,
-            //
-            //^both|catalog|search|none$/i/
-            Format::IsProductVisibility => DataType::Utf8,
-            //
-            Format::IsString => DataType::Utf8,
-            Format::IsGenericName => DataType::Utf8,
-            Format::IsCatalogName => DataType::Utf8,
-            Format::IsCleanHtml => DataType::Utf8,
-            Format::IsLinkRewrite => DataType::Utf8,
-            Format::IsGenericName1 => DataType::Utf8,
-            Format::IsMpn => DataType::Utf8,
-            Format::IsReference => DataType::Utf8,
-            //_ => DataType::Utf8,
-            _unsupported => return Err(anyhow!("format {:?} is not supported", self)),
-        })
+    /// Formats of free text or of codes that are kept as strings, e.g. colors,
+    /// e-mails, URLs, names and enum-like values such as reduction types
+    pub fn is_string_like(&self) -> bool {
+        matches!(
+            self,
+            Format::IsString
+                | Format::IsAnything
+                | Format::IsApe
+                | Format::IsCleanHtml
+                | Format::IsColor
+                | Format::IsEmail
+                | Format::IsLanguageCode
+                | Format::IsLanguageIsoCode
+                | Format::IsLinkRewrite
+                | Format::IsLocale
+                | Format::IsMd5
+                | Format::IsNumericIsoCode
+                | Format::IsPasswd
+                | Format::IsPasswdAdmin
+                | Format::IsPhpDateFormat
+                | Format::IsPriceDisplayMethod
+                | Format::IsReductionType
+                | Format::IsStockManagement
+                | Format::IsReference
+                | Format::IsSha1
+                | Format::IsThemeName
+                | Format::IsTrackingNumber
+                | Format::IsUrl
+                | Format::IsCatalogName
+                | Format::IsCarrierName
+                | Format::IsConfigName
+                | Format::IsCustomerName
+                | Format::IsGenericName
+                | Format::IsGenericName1
+                | Format::IsImageTypeName
+                | Format::IsModuleName
+                | Format::IsName
+                | Format::IsTplName
+                | Format::IsAbsoluteUrl
+                | Format::IsMpn
+                | Format::IsProductVisibility
+                | Format::IsAddress
+                | Format::IsDniLite
+                | Format::IsCityName
+                | Format::IsCoordinate
+                | Format::IsMessage
+                | Format::IsPhoneNumber
+                | Format::IsPostCode
+                | Format::IsStateIsoCode
+                | Format::IsZipCodeFormat
+        )
     }
 
     pub fn from_string(s: String) -> Result<Format> {
-        let format: Format = serde_json::from_value(serde_json::Value::String(s))?;
+        let format: Format = serde_json::from_value(serde_json::Value::String(s.clone()))
+            .map_err(|_| anyhow!("unknown format {:?}", s))?;
         Ok(format)
     }
 }
+
+/// Handles a format of `field` that is unknown or has no type: an error when
+/// `strict`, otherwise logged so that the caller reads the field as a string
+pub fn format_fallback(field: &str, e: anyhow::Error, strict: bool) -> Result<()> {
+    if strict {
+        return Err(e.context(format!("cannot type field {:?}", field)));
+    }
+    warn!("{:#} of field {:?}, reading it as a string", e, field);
+    Ok(())
+}
@@ -1,64 +1,386 @@
 use crate::arrow2::{parse_response, schema3};
+use crate::cache::Cache;
 use crate::http_config::{AuthorizationKind, HttpConfig};
+use crate::languages::{parse_languages, Languages};
+use crate::on_error::{OnError, Skipped};
 use crate::parser::Parser;
-use crate::schema2;
-use anyhow::Result;
+use crate::rate_limit::RateLimiter;
+use crate::{payload, schema2};
+use anyhow::{Context, Result};
 use arrow::array::RecordBatch;
+use arrow2::chunk::Chunk;
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
-use chrono::NaiveDate;
-use reqwest::{Client, Method};
-use tracing::{error, info};
+use chrono::NaiveDateTime;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use std::io::Write;
+use tracing::{error, info, warn};
 
 pub struct Http {
     config: HttpConfig,
     client: Client,
+    http_log: Option<HttpLog>,
+    rate_limiter: Option<RateLimiter>,
+}
+
+/// What the http log needs to know of a sent request
+struct SentRequest {
+    method: Method,
+    url: reqwest::Url,
+    started: std::time::Instant,
+}
+
+/// Appends a JSON line per request to a file, see [Http::with_http_log]
+struct HttpLog {
+    file: std::sync::Mutex<std::fs::File>,
+}
+
+impl HttpLog {
+    fn open(path: &std::path::Path) -> Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("cannot open http log {}", path.display()))?;
+        Ok(Self {
+            file: std::sync::Mutex::new(file),
+        })
+    }
+
+    fn record(&self, sent: &SentRequest, status: StatusCode, bytes: Option<usize>) -> Result<()> {
+        let entry = serde_json::json!({
+            "time": chrono::Utc::now().to_rfc3339(),
+            "method": sent.method.as_str(),
+            "url": redact_url(&sent.url),
+            "status": status.as_u16(),
+            "elapsed_ms": sent.started.elapsed().as_millis() as u64,
+            "bytes": bytes,
+        });
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| anyhow::anyhow!("http log lock is poisoned"))?;
+        writeln!(file, "{}", entry).context("cannot write http log")?;
+        Ok(())
+    }
+}
+
+/// The key must not end up in logs when it's passed as a query parameter
+fn redact_url(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    let pairs = url
+        .query_pairs()
+        .map(|(k, v)| match k.as_ref() {
+            "ws_key" => (k.into_owned(), "***".to_string()),
+            _ => (k.into_owned(), v.into_owned()),
+        })
+        .collect::<Vec<_>>();
+    if !pairs.is_empty() {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
 }
 
 impl Http {
     fn new(config: HttpConfig) -> Result<Self> {
+        let mut builder = Client::builder();
+        if let Some(user_agent) = &config.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        builder = configure_tls(builder, &config)?;
+        let client = builder
+            .default_headers(default_headers(&config)?)
+            .connect_timeout(config.connect_timeout())
+            .timeout(config.request_timeout())
+            // sets Accept-Encoding and decompresses the body, setting the header
+            // by hand would turn off the decompression
+            .gzip(config.accept_compression())
+            .deflate(config.accept_compression())
+            .build()?;
+        let rate_limiter = config.max_rps.map(RateLimiter::new).transpose()?;
         Ok(Self {
             config,
-            client: Client::builder().build()?,
+            client,
+            http_log: None,
+            rate_limiter,
         })
     }
-    async fn get(&self, path: &str, query: &[QueryParam]) -> Result<String> {
-        let url = reqwest::Url::parse(format!("{}/api", self.config.host.as_str()).as_str())?
+
+    /// Appends the method, URL, status, timing and body size of every request
+    /// to `path` as JSON lines. A `ws_key` query parameter is redacted
+    pub fn with_http_log(mut self, path: &std::path::Path) -> Result<Self> {
+        self.http_log = Some(HttpLog::open(path)?);
+        Ok(self)
+    }
+    fn request(&self, method: Method, path: &str, query: &[QueryParam]) -> Result<RequestBuilder> {
+        let mut url = reqwest::Url::parse(format!("{}/api", self.config.host.as_str()).as_str())?
             .join(path)?;
         let mut query = query.to_vec();
         match self.config.authorization_kind {
             AuthorizationKind::Header => (),
             AuthorizationKind::QueryParam => query.push(QueryParam::WsKey(self.config.key.clone())),
         };
-        let query = render_query_params(&query);
-        let builder = self.client.request(Method::GET, url);
+        let query = render_query_params(&query)?;
+        if !query.is_empty() {
+            url.set_query(Some(&encode_query(&query)));
+        }
+        let builder = self.client.request(method, url);
         let builder = match self.config.authorization_kind {
-            AuthorizationKind::Header => {
-                let authorization_key = BASE64_STANDARD.encode(self.config.key.trim());
-                let authorization_header =
-                    "Basic".to_string() + " " + authorization_key.as_str() + ":";
-                builder.header(reqwest::header::AUTHORIZATION, authorization_header)
-            }
+            AuthorizationKind::Header => builder.header(
+                reqwest::header::AUTHORIZATION,
+                basic_authorization(&self.config.key),
+            ),
             AuthorizationKind::QueryParam => builder,
         };
-        let builder = builder.query(&query);
+        Ok(builder)
+    }
+
+    async fn send(&self, builder: RequestBuilder) -> Result<(SentRequest, Response)> {
         let request = builder.build()?;
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
         info!("url={}", request.url());
         info!("request={:?}", request);
-        //.header(reqwest::header::AUTHORIZATION, authorization_header)
-        // .query(&query)
-        //.build()?;
-        let resp = self.client.execute(request).await?;
-        if !resp.status().is_success() {
-            let msg = format!("HTTP status={} for url={}", resp.status(), resp.url());
-            error!(msg);
-            let body = resp.text().await?;
-            error!("{}: <<EOF\n{}\nEOF\n", msg, body);
-            return Err(anyhow::anyhow!(msg));
+        let sent = SentRequest {
+            method: request.method().clone(),
+            url: request.url().clone(),
+            started: std::time::Instant::now(),
+        };
+        let resp = self.client.execute(request).await.map_err(|e| {
+            if e.is_timeout() {
+                anyhow::anyhow!("request timed out for url={}: {}", sent.url, e)
+            } else {
+                e.into()
+            }
+        })?;
+        Ok((sent, resp))
+    }
+
+    fn log(&self, sent: &SentRequest, status: StatusCode, bytes: Option<usize>) -> Result<()> {
+        match &self.http_log {
+            Some(http_log) => http_log.record(sent, status, bytes),
+            None => Ok(()),
+        }
+    }
+
+    async fn read(&self, sent: SentRequest, resp: Response) -> Result<String> {
+        let status = resp.status();
+        let body = read_response(resp).await;
+        self.log(&sent, status, body.as_ref().ok().map(|body| body.len()))?;
+        body
+    }
+
+    async fn execute(&self, builder: RequestBuilder) -> Result<String> {
+        let (sent, resp) = self.send(builder).await?;
+        self.read(sent, resp).await
+    }
+
+    pub fn host(&self) -> &str {
+        self.config.host.as_str()
+    }
+
+    /// GETs are retried on rate limiting and gateway errors, see [is_transient]
+    async fn get(&self, path: &str, query: &[QueryParam]) -> Result<String> {
+        let max_retries = self.config.max_retries();
+        let mut attempt = 0;
+        loop {
+            let (sent, resp) = self.send(self.request(Method::GET, path, query)?).await?;
+            if attempt >= max_retries || !is_transient(resp.status()) {
+                return self.read(sent, resp).await;
+            }
+            self.log(&sent, resp.status(), None)?;
+            let delay = retry_after(resp.headers()).unwrap_or(self.config.retry_delay(attempt));
+            attempt += 1;
+            warn!(
+                "HTTP status={} for url={}, retry {} of {} in {:?}",
+                resp.status(),
+                resp.url(),
+                attempt,
+                max_retries,
+                delay
+            );
+            tokio::time::sleep(delay).await;
         }
-        let s = resp.text().await?;
-        Ok(s)
     }
+
+    async fn delete(&self, path: &str) -> Result<String> {
+        self.execute(self.request(Method::DELETE, path, &[])?).await
+    }
+
+    async fn post(&self, path: &str, body: String) -> Result<String> {
+        let builder = self
+            .request(Method::POST, path, &[])?
+            .header(reqwest::header::CONTENT_TYPE, "application/xml")
+            .body(body);
+        self.execute(builder).await
+    }
+
+    async fn put(&self, path: &str, body: String) -> Result<String> {
+        let builder = self
+            .request(Method::PUT, path, &[])?
+            .header(reqwest::header::CONTENT_TYPE, "application/xml")
+            .body(body);
+        self.execute(builder).await
+    }
+}
+
+fn configure_tls(
+    mut builder: reqwest::ClientBuilder,
+    config: &HttpConfig,
+) -> Result<reqwest::ClientBuilder> {
+    let read = |path: &std::path::Path| {
+        std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))
+    };
+    if let Some(path) = &config.ca_cert_path {
+        let certificates = reqwest::Certificate::from_pem_bundle(&read(path)?)
+            .with_context(|| format!("invalid CA certificates in {}", path.display()))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    match (&config.client_cert_path, &config.client_key_path) {
+        (Some(cert), Some(key)) => {
+            let identity = reqwest::Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+                .with_context(|| format!("invalid client certificate {}", cert.display()))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => (),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "client_cert_path and client_key_path must be set together"
+            ))
+        }
+    }
+    if config.danger_accept_invalid_certs() {
+        warn!("danger_accept_invalid_certs is set, server certificates are not verified");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder)
+}
+
+fn default_headers(config: &HttpConfig) -> Result<HeaderMap> {
+    let mut out = HeaderMap::new();
+    for (name, value) in config.headers.iter().flatten() {
+        let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())?;
+        if name == reqwest::header::AUTHORIZATION {
+            return Err(anyhow::anyhow!(
+                "the Authorization header can't be set in headers, it is controlled by authorization_kind"
+            ));
+        }
+        out.insert(name, value.parse()?);
+    }
+    Ok(out)
+}
+
+/// The key is the user name with an empty password, i.e. `Basic base64(key:)`
+fn basic_authorization(key: &str) -> String {
+    format!(
+        "Basic {}",
+        BASE64_STANDARD.encode(format!("{}:", key.trim()))
+    )
+}
+
+async fn read_response(resp: Response) -> Result<String> {
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let url = resp.url().to_string();
+        let body = resp.text().await?;
+        error!(
+            "HTTP status={} for url={}: <<EOF\n{}\nEOF\n",
+            status, url, body
+        );
+        let errors = parse_error_body(&body).unwrap_or_default();
+        return Err(ApiError {
+            status,
+            url,
+            errors,
+        }
+        .into());
+    }
+    let s = resp.text().await?;
+    Ok(s)
+}
+
+/// Statuses worth retrying: rate limiting and errors of overloaded shops or their gateways
+fn is_transient(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Delay requested by a `Retry-After: <seconds>` header
+fn retry_after(headers: &HeaderMap) -> Option<std::time::Duration> {
+    let secs = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(std::time::Duration::from_secs(secs))
+}
+
+/// An `<error>` of a PrestaShop error body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WsError {
+    pub code: String,
+    pub message: String,
+}
+
+/// A non-success response, with the errors of its body when it had any.
+/// Can be recovered with `anyhow::Error::downcast_ref::<ApiError>()`
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub url: String,
+    pub errors: Vec<WsError>,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP status={} for url={}", self.status, self.url)?;
+        for (i, e) in self.errors.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { "; " };
+            write!(f, "{}{}: {}", sep, e.code, e.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Extracts the `<errors>` of a PrestaShop error body
+fn parse_error_body(body: &str) -> Result<Vec<WsError>> {
+    let doc = roxmltree::Document::parse(body)?;
+    let errors = Parser::new(doc.root_element())
+        .named("prestashop")?
+        .single_child()?
+        .named("errors")?
+        .only_same_named_children()?;
+    let mut out = vec![];
+    for error in errors {
+        let fields = error.uniquely_named_children_map()?;
+        let text = |name: &str| {
+            fields
+                .get(name)
+                .and_then(|p| p.node().text())
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default()
+        };
+        out.push(WsError {
+            code: text("code"),
+            message: text("message"),
+        });
+    }
+    Ok(out)
 }
 
 pub async fn ws_get_available_resources(http: &Http) -> Result<Vec<Resource>> {
@@ -80,6 +402,26 @@ pub async fn ws_get_available_resources(http: &Http) -> Result<Vec<Resource>> {
     Ok(r)
 }
 
+pub async fn ws_get_languages(http: &Http, cache: Option<&Cache>) -> Result<Languages> {
+    let key = format!("{}/languages", http.host());
+    if let Some(cached) = cache.map(|c| c.get(&key)).transpose()?.flatten() {
+        return Ok(serde_json::from_str(&cached)?);
+    }
+    let fields = vec!["id".to_string(), "iso_code".to_string()];
+    let response = http
+        .get(
+            "/api/languages",
+            &[QueryParam::Display(query_param::Display::Fields(fields))],
+        )
+        .await?;
+    let doc = roxmltree::Document::parse(&response)?;
+    let languages = parse_languages(Parser::new(doc.root_element()))?;
+    if let Some(cache) = cache {
+        cache.put(&key, &serde_json::to_string(&languages)?)?;
+    }
+    Ok(languages)
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Resource {
     identifier: String,
@@ -119,19 +461,217 @@ impl DateField {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDir {
+    Asc,
+    Desc,
+}
+impl SortDir {
+    pub fn identifier(&self) -> &str {
+        match self {
+            SortDir::Asc => "ASC",
+            SortDir::Desc => "DESC",
+        }
+    }
+}
+impl std::str::FromStr for SortDir {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "asc" => Ok(SortDir::Asc),
+            "desc" => Ok(SortDir::Desc),
+            _ => Err(anyhow::anyhow!(
+                "invalid sort direction '{}', expected 'asc' or 'desc'",
+                s
+            )),
+        }
+    }
+}
+
+/// A setting of a price that the webservice computes into an extra field of
+/// each record, rendered as `price[<field>][<setting>]=<value>`. Booleans are
+/// rendered as `0` or `1`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PriceSetting {
+    /// `use_tax`, whether the price includes taxes
+    UseTax(bool),
+    /// `use_reduction`, whether specific prices and discounts are applied
+    UseReduction(bool),
+    /// `currency`, id of the currency to convert the price to
+    Currency(usize),
+    /// `country`, id of the country whose taxes are applied
+    Country(usize),
+    /// `group`, id of the customer group whose reductions are applied
+    Group(usize),
+    /// `product_attribute`, id of the combination to price
+    ProductAttribute(usize),
+    /// `quantity`, number of items the price is for
+    Quantity(usize),
+    /// `decimals`, number of decimals the price is rounded to
+    Decimals(usize),
+}
+
+impl PriceSetting {
+    /// Parses the `setting` and `value` of `price[field][setting]=value`
+    pub fn new(setting: &str, value: &str) -> Result<Self> {
+        let flag = || {
+            crate::utils::parse_bool(value)?
+                .ok_or_else(|| anyhow::anyhow!("expected 0 or 1 for {}", setting))
+        };
+        let id = || {
+            value
+                .trim()
+                .parse::<usize>()
+                .with_context(|| format!("invalid {} {:?}", setting, value))
+        };
+        Ok(match setting {
+            "use_tax" => PriceSetting::UseTax(flag()?),
+            "use_reduction" => PriceSetting::UseReduction(flag()?),
+            "currency" => PriceSetting::Currency(id()?),
+            "country" => PriceSetting::Country(id()?),
+            "group" => PriceSetting::Group(id()?),
+            "product_attribute" => PriceSetting::ProductAttribute(id()?),
+            "quantity" => PriceSetting::Quantity(id()?),
+            "decimals" => PriceSetting::Decimals(id()?),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "unknown price setting {:?}, expected one of use_tax, use_reduction, \
+                     currency, country, group, product_attribute, quantity, decimals",
+                    setting
+                ))
+            }
+        })
+    }
+
+    pub fn identifier(&self) -> &str {
+        match self {
+            PriceSetting::UseTax(_) => "use_tax",
+            PriceSetting::UseReduction(_) => "use_reduction",
+            PriceSetting::Currency(_) => "currency",
+            PriceSetting::Country(_) => "country",
+            PriceSetting::Group(_) => "group",
+            PriceSetting::ProductAttribute(_) => "product_attribute",
+            PriceSetting::Quantity(_) => "quantity",
+            PriceSetting::Decimals(_) => "decimals",
+        }
+    }
+
+    fn value(&self) -> String {
+        match self {
+            PriceSetting::UseTax(b) | PriceSetting::UseReduction(b) => (*b as u8).to_string(),
+            PriceSetting::Currency(n)
+            | PriceSetting::Country(n)
+            | PriceSetting::Group(n)
+            | PriceSetting::ProductAttribute(n)
+            | PriceSetting::Quantity(n)
+            | PriceSetting::Decimals(n) => n.to_string(),
+        }
+    }
+}
+
+/// How `filter[field]` compares the field to the given values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    /// `[value]`
+    Equals,
+    /// `[a|b|c]`
+    In,
+    /// `[from,to]`, inclusive
+    Range,
+    /// `[value]%`
+    Begins,
+    /// `%[value]%`
+    Contains,
+    /// `%[value]`
+    Ends,
+    /// `[]`, compares to the empty string. The webservice has no IS NULL, so
+    /// NULL columns are not matched
+    IsEmpty,
+    /// `![]`, anything but the empty string. NULL columns are not matched either
+    IsNotEmpty,
+}
+
+impl FilterOp {
+    fn render(&self, values: &[String]) -> Result<String> {
+        Ok(match self {
+            FilterOp::Equals | FilterOp::In => format!("[{}]", join_filter_values(values, '|')?),
+            FilterOp::Range => format!("[{}]", join_filter_values(values, ',')?),
+            FilterOp::Begins => format!("[{}]%", join_filter_values(values, '|')?),
+            FilterOp::Contains => format!("%[{}]%", join_filter_values(values, '|')?),
+            FilterOp::Ends => format!("%[{}]", join_filter_values(values, '|')?),
+            FilterOp::IsEmpty => "[]".to_string(),
+            FilterOp::IsNotEmpty => "![]".to_string(),
+        })
+    }
+}
+
+/// Joins the values of a filter with `separator`. PrestaShop splits filters
+/// without any escaping, so a value containing the separator can't be sent
+fn join_filter_values(values: &[String], separator: char) -> Result<String> {
+    if let Some(value) = values.iter().find(|v| v.contains(separator)) {
+        return Err(anyhow::anyhow!(
+            "filter value {:?} contains {:?}, which the shop always reads as a separator",
+            value,
+            separator
+        ));
+    }
+    Ok(values.join(&separator.to_string()))
+}
+
 #[derive(Clone)]
 pub enum QueryParam {
     Schema(query_param::Schema),
     Language(usize),
+    /// Scopes a multistore shop to one shop, `id_shop`
+    Shop(usize),
+    /// Scopes a multistore shop to a group of shops, `id_group_shop`
+    ShopGroup(usize),
     Display(query_param::Display),
     Limit(usize),
     LimitFromIndex(usize, usize),
     WsKey(String),
-    DateRange(DateField, NaiveDate, NaiveDate),
+    DateRange(DateField, NaiveDateTime, NaiveDateTime),
     FieldValueIn(String, Vec<String>),
+    Sort(Vec<(String, SortDir)>),
+    Filter {
+        field: String,
+        op: FilterOp,
+        values: Vec<String>,
+    },
+    /// `price[field][setting]=value`, see [PriceSetting]
+    PriceParam {
+        field: String,
+        setting: PriceSetting,
+    },
+}
+
+/// Characters that are escaped in query keys and values. The brackets, `|` and
+/// `,` of filters like `filter[id]=[1|5]` are kept as is, as PrestaShop expects
+const QUERY_ESCAPED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'[')
+    .remove(b']')
+    .remove(b'|')
+    .remove(b',');
+
+fn encode_query(query: &[(String, String)]) -> String {
+    query
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(k, QUERY_ESCAPED),
+                utf8_percent_encode(v, QUERY_ESCAPED)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
 }
 
-fn render_query_params(params: &[QueryParam]) -> Vec<(String, String)> {
+fn render_query_params(params: &[QueryParam]) -> Result<Vec<(String, String)>> {
     let mut out = vec![];
     for p in params {
         match p {
@@ -141,10 +681,26 @@ fn render_query_params(params: &[QueryParam]) -> Vec<(String, String)> {
             }
             QueryParam::FieldValueIn(field_name, values) => {
                 let name = format!("filter[{}]", field_name);
-                let value = format!("[{}]", values.join("|"));
+                let value = format!("[{}]", join_filter_values(values, '|')?);
                 out.push((name, value))
             }
+            QueryParam::Filter { field, op, values } => {
+                out.push((format!("filter[{}]", field), op.render(values)?))
+            }
+            QueryParam::Sort(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, dir)| format!("{}_{}", name, dir.identifier()))
+                    .collect::<Vec<_>>();
+                out.push(("sort".to_string(), format!("[{}]", fields.join(","))));
+            }
+            QueryParam::PriceParam { field, setting } => out.push((
+                format!("price[{}][{}]", field, setting.identifier()),
+                setting.value(),
+            )),
             QueryParam::Language(id) => out.push(("language".to_string(), id.to_string())),
+            QueryParam::Shop(id) => out.push(("id_shop".to_string(), id.to_string())),
+            QueryParam::ShopGroup(id) => out.push(("id_group_shop".to_string(), id.to_string())),
             QueryParam::Schema(a) => out.push((
                 "schema".to_string(),
                 match a {
@@ -162,13 +718,20 @@ fn render_query_params(params: &[QueryParam]) -> Vec<(String, String)> {
             }
             QueryParam::WsKey(key) => out.push(("ws_key".to_string(), key.to_string())),
             QueryParam::DateRange(date_field, from, to) => {
-                let value = format!("[{},{}]", from.format("%Y-%m-%d"), to.format("%Y-%m-%d"));
+                let format = "%Y-%m-%d %H:%M:%S";
+                let value = format!("[{},{}]", from.format(format), to.format(format));
                 out.push((format!("filter[{}]", date_field.identifier()), value));
-                out.push(("date".to_string(), "1".to_string()));
+                // date=1 enables date filters as a whole, it is pushed once below
             }
         }
     }
-    out
+    if params
+        .iter()
+        .any(|p| matches!(p, QueryParam::DateRange(_, _, _)))
+    {
+        out.push(("date".to_string(), "1".to_string()));
+    }
+    Ok(out)
 }
 
 pub async fn ws_get_resource_schema_string<'a>(
@@ -185,6 +748,38 @@ pub async fn ws_get_resource_schema_string<'a>(
     Ok(response)
 }
 
+/// The empty template of `resource` with `schema=blank`, in which every
+/// writable field is present without a value
+pub async fn ws_get_resource_blank_string(http: &Http, resource: &Resource) -> Result<String> {
+    let path = format!("/api/{}", resource.identifier());
+    http.get(&path, &[QueryParam::Schema(query_param::Schema::Blank)])
+        .await
+}
+
+/// [ws_get_resource_blank_string] as JSON in the shape `create` takes, e.g.
+/// `{"product": {"price": null, ..}}`
+pub async fn ws_get_resource_blank(
+    http: &Http,
+    resource: &Resource,
+    schema: &schema2::Schema,
+) -> Result<serde_json::Value> {
+    let response = ws_get_resource_blank_string(http, resource).await?;
+    let doc = roxmltree::Document::parse(&response)?;
+    schema2::parse_entity_to_json(Parser::new(doc.root_element()), schema)
+}
+
+/// The URL `ws_get_resource_string` would request, including `ws_key` when the
+/// key is passed as a query parameter
+pub fn ws_resource_url(
+    http: &Http,
+    resource: &Resource,
+    params: &[QueryParam],
+) -> Result<reqwest::Url> {
+    let path = format!("/api/{}", resource.identifier());
+    let request = http.request(Method::GET, &path, params)?.build()?;
+    Ok(request.url().clone())
+}
+
 pub async fn ws_get_resource_string(
     http: &Http,
     resource: &Resource,
@@ -192,20 +787,117 @@ pub async fn ws_get_resource_string(
 ) -> Result<String> {
     let path = format!("/api/{}", resource.identifier());
     //query.push(some(queryparam::language(1))); let response = http.get(&path, &query).await?;
-    let response = http.get(&path, &params).await?;
+    let response = http.get(&path, params).await?;
+    Ok(response)
+}
+
+/// Number of records matching `params`, fetched cheaply with `display=[id]`
+pub async fn ws_count_resource(
+    http: &Http,
+    resource: &Resource,
+    params: &[QueryParam],
+) -> Result<usize> {
+    let mut params = params
+        .iter()
+        .filter(|p| !matches!(p, QueryParam::Display(_)))
+        .cloned()
+        .collect::<Vec<_>>();
+    params.push(QueryParam::Display(query_param::Display::Fields(vec![
+        "id".to_string(),
+    ])));
+    let response = ws_get_resource_string(http, resource, &params).await?;
+    count_records(&response)
+}
+
+/// Whether the key can read a resource, see [ws_probe_resource]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ResourceAccess {
+    pub resource: String,
+    pub readable: bool,
+    pub status: u16,
+}
+
+/// Requests a single record of `resource` to find out whether the key can
+/// read it. Error statuses are reported rather than returned as errors
+pub async fn ws_probe_resource(http: &Http, resource: &Resource) -> Result<ResourceAccess> {
+    let result = ws_get_resource_string(http, resource, &[QueryParam::Limit(1)]).await;
+    resource_access(resource, result)
+}
+
+fn resource_access(resource: &Resource, result: Result<String>) -> Result<ResourceAccess> {
+    let status = match result {
+        Ok(_) => StatusCode::OK,
+        Err(e) => match e.downcast_ref::<ApiError>() {
+            Some(api_error) => api_error.status,
+            None => return Err(e),
+        },
+    };
+    Ok(ResourceAccess {
+        resource: resource.identifier().to_string(),
+        readable: status.is_success(),
+        status: status.as_u16(),
+    })
+}
+
+fn count_records(response: &str) -> Result<usize> {
+    let doc = roxmltree::Document::parse(response)?;
+    let records = Parser::new(doc.root_element())
+        .named("prestashop")?
+        .single_child()?
+        .only_same_named_children()?;
+    Ok(records.len())
+}
+
+/// Same as [ws_get_resource_schema_string], reusing the synopsis cached for the
+/// host and resource when there is one. It is cached as a JSON string
+pub async fn ws_get_resource_schema_string_cached(
+    http: &Http,
+    resource: &Resource,
+    cache: Option<&Cache>,
+) -> Result<String> {
+    let key = format!("{}/schema/{}", http.host(), resource.identifier());
+    if let Some(cached) = cache.map(|c| c.get(&key)).transpose()?.flatten() {
+        return Ok(serde_json::from_str(&cached)?);
+    }
+    let response = ws_get_resource_schema_string(http, resource).await?;
+    if let Some(cache) = cache {
+        cache.put(&key, &serde_json::to_string(&response)?)?;
+    }
     Ok(response)
 }
 
 pub async fn ws_get_resource_schema2(http: &Http, resource: &Resource) -> Result<schema2::Schema> {
-    let response = &ws_get_resource_schema_string(http, resource).await?;
+    ws_get_resource_schema2_cached(http, resource, None, false, true).await
+}
+
+/// See [schema2::parse_schema_with] for `strict` and `id_field`
+pub async fn ws_get_resource_schema2_cached(
+    http: &Http,
+    resource: &Resource,
+    cache: Option<&Cache>,
+    strict: bool,
+    id_field: bool,
+) -> Result<schema2::Schema> {
+    let response = &ws_get_resource_schema_string_cached(http, resource, cache).await?;
     let xml = roxmltree::Document::parse(response.as_str())?;
-    let s = schema2::parse_schema(Parser::new(xml.root_element()))?;
+    let s = schema2::parse_schema_with(Parser::new(xml.root_element()), strict, id_field)?;
     Ok(s)
 }
 
 pub async fn ws_get_resource_schema3(http: &Http, resource: &Resource) -> Result<schema3::Schema3> {
-    let response = &ws_get_resource_schema_string(http, resource).await?;
-    let schema = schema3::parse_schema(response.as_bytes())?;
+    ws_get_resource_schema3_cached(http, resource, None, false, true).await
+}
+
+/// See [schema3::parse_schema_with] for `strict` and `id_field`
+pub async fn ws_get_resource_schema3_cached(
+    http: &Http,
+    resource: &Resource,
+    cache: Option<&Cache>,
+    strict: bool,
+    id_field: bool,
+) -> Result<schema3::Schema3> {
+    let response = &ws_get_resource_schema_string_cached(http, resource, cache).await?;
+    let schema = schema3::parse_schema_with(response.as_bytes(), strict, id_field)?;
     Ok(schema)
 }
 
@@ -221,31 +913,660 @@ pub async fn ws_get_resource2(
     Ok(json)
 }
 
+/// Walks a resource in `limit=offset,page_size` pages until an empty page is returned
+#[derive(Debug, Clone)]
+pub struct Pages {
+    page_size: usize,
+    offset: usize,
+    done: bool,
+}
+
+impl Pages {
+    pub fn new(page_size: usize) -> Result<Self> {
+        if page_size == 0 {
+            return Err(anyhow::anyhow!("page size must be greater than 0"));
+        }
+        Ok(Self {
+            page_size,
+            offset: 0,
+            done: false,
+        })
+    }
+
+    /// Query parameters of the next page, any limit in `params` is replaced.
+    /// Returns `None` once an empty page was reported to [Pages::page_fetched]
+    pub fn next_params(&self, params: &[QueryParam]) -> Option<Vec<QueryParam>> {
+        if self.done {
+            return None;
+        }
+        let mut params = params
+            .iter()
+            .filter(|p| !matches!(p, QueryParam::Limit(_) | QueryParam::LimitFromIndex(_, _)))
+            .cloned()
+            .collect::<Vec<_>>();
+        params.push(QueryParam::LimitFromIndex(self.offset, self.page_size));
+        Some(params)
+    }
+
+    pub fn page_fetched(&mut self, rows: usize) {
+        if rows == 0 {
+            self.done = true;
+        } else {
+            self.offset += self.page_size;
+        }
+    }
+
+    pub fn is_first(&self) -> bool {
+        self.offset == 0
+    }
+}
+
+/// Fetches a single response, or all pages of `page_size` records concatenated
 pub async fn ws_get_resource2_arrow(
     http: &Http,
     resource: &Resource,
     schema: &schema2::Schema,
     params: &[QueryParam],
+    on_error: OnError,
+    page_size: Option<usize>,
+    streaming: bool,
+) -> Result<RecordBatch> {
+    let mut skipped = Skipped::default();
+    let Some(page_size) = page_size else {
+        let batch = ws_get_resource2_arrow_page(
+            http,
+            resource,
+            schema,
+            params,
+            on_error,
+            streaming,
+            &mut skipped,
+        )
+        .await?;
+        skipped.report(batch.num_rows());
+        return Ok(batch);
+    };
+    let mut pages = Pages::new(page_size)?;
+    let mut batches = vec![];
+    while let Some(params) = pages.next_params(params) {
+        let batch = ws_get_resource2_arrow_page(
+            http,
+            resource,
+            schema,
+            &params,
+            on_error,
+            streaming,
+            &mut skipped,
+        )
+        .await?;
+        pages.page_fetched(batch.num_rows());
+        batches.push(batch);
+    }
+    // the last page is always empty, but still carries the schema
+    let schema = batches[batches.len() - 1].schema();
+    let batch = arrow::compute::concat_batches(&schema, &batches)?;
+    skipped.report(batch.num_rows());
+    Ok(batch)
+}
+
+async fn ws_get_resource2_arrow_page(
+    http: &Http,
+    resource: &Resource,
+    schema: &schema2::Schema,
+    params: &[QueryParam],
+    on_error: OnError,
+    streaming: bool,
+    skipped: &mut Skipped,
 ) -> Result<RecordBatch> {
     let response = &ws_get_resource_string(http, resource, params).await?;
+    if streaming {
+        return schema2::parse_data_to_arrow_streaming(response, schema, on_error, skipped);
+    }
     let doc = roxmltree::Document::parse(response)?;
-    let batch = schema2::parse_data_to_arrow(Parser::new(doc.root_element()), schema)?;
+    let batch =
+        schema2::parse_data_to_arrow(Parser::new(doc.root_element()), schema, on_error, skipped)?;
     Ok(batch)
 }
 
+/// Fetches a single response, or all pages of `page_size` records concatenated
 pub async fn ws_get_resource2_arrow2(
     http: &Http,
     resource: &Resource,
     schema: &schema3::Schema3,
     params: &[QueryParam],
-) -> Result<arrow2::chunk::Chunk<Box<dyn arrow2::array::Array>>> {
+    on_error: OnError,
+    page_size: Option<usize>,
+    streaming: bool,
+) -> Result<Chunk<Box<dyn arrow2::array::Array>>> {
+    let mut skipped = Skipped::default();
+    let Some(page_size) = page_size else {
+        let chunk = ws_get_resource2_arrow2_page(
+            http,
+            resource,
+            schema,
+            params,
+            on_error,
+            streaming,
+            &mut skipped,
+        )
+        .await?;
+        skipped.report(chunk.len());
+        return Ok(chunk);
+    };
+    let mut pages = Pages::new(page_size)?;
+    let mut chunks = vec![];
+    while let Some(params) = pages.next_params(params) {
+        let chunk = ws_get_resource2_arrow2_page(
+            http,
+            resource,
+            schema,
+            &params,
+            on_error,
+            streaming,
+            &mut skipped,
+        )
+        .await?;
+        pages.page_fetched(chunk.len());
+        chunks.push(chunk);
+    }
+    let chunk = concat_chunks(&chunks)?;
+    skipped.report(chunk.len());
+    Ok(chunk)
+}
+
+async fn ws_get_resource2_arrow2_page(
+    http: &Http,
+    resource: &Resource,
+    schema: &schema3::Schema3,
+    params: &[QueryParam],
+    on_error: OnError,
+    streaming: bool,
+    skipped: &mut Skipped,
+) -> Result<Chunk<Box<dyn arrow2::array::Array>>> {
     let response = &ws_get_resource_string(http, resource, params).await?;
-    let chunk = parse_response::parse_response_to_arrow(schema, response.as_bytes())?;
+    let parse = if streaming {
+        parse_response::parse_response_to_arrow_streaming
+    } else {
+        parse_response::parse_response_to_arrow
+    };
+    let chunk = parse(schema, response.as_bytes(), on_error, skipped)?;
     Ok(chunk)
 }
 
-pub fn configure_http(conf_path: &str) -> Result<Http> {
-    let conf: HttpConfig = toml::from_str(std::fs::read_to_string(conf_path)?.as_str())?;
+fn concat_chunks(
+    chunks: &[Chunk<Box<dyn arrow2::array::Array>>],
+) -> Result<Chunk<Box<dyn arrow2::array::Array>>> {
+    let num_columns = chunks.first().map(|c| c.arrays().len()).unwrap_or(0);
+    let columns = (0..num_columns)
+        .map(|i| {
+            let arrays = chunks
+                .iter()
+                .map(|c| c.arrays()[i].as_ref())
+                .collect::<Vec<_>>();
+            arrow2::compute::concatenate::concatenate(&arrays)
+        })
+        .collect::<arrow2::error::Result<Vec<_>>>()?;
+    Ok(Chunk::try_new(columns)?)
+}
+
+pub async fn ws_create_resource(
+    http: &Http,
+    resource: &Resource,
+    schema: &schema2::Schema,
+    payload: &str,
+) -> Result<serde_json::Value> {
+    let path = format!("/api/{}", resource.identifier());
+    let body = payload::to_prestashop_xml(payload)?;
+    let response = http.post(&path, body).await?;
+    let doc = roxmltree::Document::parse(&response)?;
+    let json = schema2::parse_entity_to_json(Parser::new(doc.root_element()), schema)?;
+    Ok(json)
+}
+
+/// Replaces a 404 of `/api/{resource}/{id}` by an error naming the entity
+fn not_found_error(e: anyhow::Error, resource: &Resource, id: u32) -> anyhow::Error {
+    match e.downcast_ref::<ApiError>() {
+        Some(api_error) if api_error.status == StatusCode::NOT_FOUND => {
+            anyhow::anyhow!("{} with id {} does not exist", resource.identifier(), id)
+        }
+        _ => e,
+    }
+}
+
+/// Fetches the entity with `id` from `/api/{resource}/{id}`, e.g. `{"product": {..}}`
+pub async fn ws_get_resource_by_id(
+    http: &Http,
+    resource: &Resource,
+    schema: &schema2::Schema,
+    id: u32,
+    params: &[QueryParam],
+) -> Result<serde_json::Value> {
+    let path = format!("/api/{}/{}", resource.identifier(), id);
+    let response = http
+        .get(&path, params)
+        .await
+        .map_err(|e| not_found_error(e, resource, id))?;
+    let doc = roxmltree::Document::parse(&response)?;
+    schema2::parse_entity_to_json(Parser::new(doc.root_element()), schema)
+}
+
+/// Updates the fields given in `patch`, a payload like for [ws_create_resource].
+/// The WebService only accepts full entities, so the current entity is fetched
+/// and the patch merged into it before it is PUT back
+pub async fn ws_update_resource(
+    http: &Http,
+    resource: &Resource,
+    schema: &schema2::Schema,
+    id: u32,
+    patch: &str,
+) -> Result<serde_json::Value> {
+    let path = format!("/api/{}/{}", resource.identifier(), id);
+    let current = http
+        .get(&path, &[])
+        .await
+        .map_err(|e| not_found_error(e, resource, id))?;
+    let patch = payload::to_prestashop_xml(patch)?;
+    let body = payload::merge_entity_xml(&current, &patch, schema.read_only_fields())?;
+    let response = http.put(&path, body).await?;
+    let doc = roxmltree::Document::parse(&response)?;
+    let json = schema2::parse_entity_to_json(Parser::new(doc.root_element()), schema)?;
+    Ok(json)
+}
+
+/// Errors carry the messages of the XML error body, e.g. for ids that don't exist
+pub async fn ws_delete_resource(http: &Http, resource: &Resource, id: u32) -> Result<()> {
+    let path = format!("/api/{}/{}", resource.identifier(), id);
+    http.delete(&path).await?;
+    Ok(())
+}
+
+/// Loads the config at `conf_path`, selecting `profile` when the file has
+/// `[profiles.<name>]`, and lets `overrides` change it before the client is built
+pub fn configure_http<P: AsRef<std::path::Path>>(
+    conf_path: P,
+    profile: Option<&str>,
+    overrides: impl FnOnce(&mut HttpConfig),
+) -> Result<Http> {
+    let conf_path = conf_path.as_ref();
+    let conf = std::fs::read_to_string(conf_path)
+        .with_context(|| format!("cannot read config {}", conf_path.display()))?;
+    let mut conf = HttpConfig::from_toml(conf.as_str(), profile)?;
+    conf.resolve_key()?;
+    overrides(&mut conf);
     let http = Http::new(conf)?;
     Ok(http)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_http_log() {
+        let path = std::env::temp_dir().join("ps17_test_http_log.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let http_log = HttpLog::open(&path).unwrap();
+        let sent = SentRequest {
+            method: Method::GET,
+            url: reqwest::Url::parse("https://shop/api/products?display=full&ws_key=secret")
+                .unwrap(),
+            started: std::time::Instant::now(),
+        };
+        http_log.record(&sent, StatusCode::OK, Some(42)).unwrap();
+        http_log
+            .record(&sent, StatusCode::SERVICE_UNAVAILABLE, None)
+            .unwrap();
+        let lines = std::fs::read_to_string(&path).unwrap();
+        let entries = lines
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["method"], "GET");
+        assert_eq!(
+            entries[0]["url"],
+            "https://shop/api/products?display=full&ws_key=***"
+        );
+        assert_eq!(entries[0]["status"], 200);
+        assert_eq!(entries[0]["bytes"], 42);
+        assert_eq!(entries[1]["bytes"], serde_json::Value::Null);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_count_records() {
+        let response = r#"<?xml version="1.0" encoding="UTF-8"?>
+<prestashop xmlns:xlink="http://www.w3.org/1999/xlink">
+<products>
+    <product><id><![CDATA[1]]></id></product>
+    <product><id><![CDATA[2]]></id></product>
+</products>
+</prestashop>"#;
+        assert_eq!(count_records(response).unwrap(), 2);
+        let empty = "<prestashop><products/></prestashop>";
+        assert_eq!(count_records(empty).unwrap(), 0);
+        assert!(count_records("<html/>").is_err());
+    }
+
+    fn limit_of(params: &[QueryParam]) -> Vec<String> {
+        render_query_params(params)
+            .unwrap()
+            .into_iter()
+            .filter(|(k, _)| k == "limit")
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    #[test]
+    fn test_pages_until_empty() {
+        let params = vec![
+            QueryParam::Limit(5),
+            QueryParam::Display(query_param::Display::Full),
+        ];
+        let mut pages = Pages::new(100).unwrap();
+        let first = pages.next_params(&params).unwrap();
+        assert_eq!(limit_of(&first), vec!["0,100"]);
+        assert_eq!(first.len(), 2);
+        pages.page_fetched(100);
+        assert_eq!(
+            limit_of(&pages.next_params(&params).unwrap()),
+            vec!["100,100"]
+        );
+        pages.page_fetched(0);
+        assert!(pages.next_params(&params).is_none());
+        assert!(Pages::new(0).is_err());
+    }
+
+    #[test]
+    fn test_render_date_flag_once() {
+        let date = |s: &str| {
+            chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        let params = vec![
+            QueryParam::DateRange(DateField::DateAdd, date("2020-01-01"), date("2020-12-31")),
+            QueryParam::DateRange(DateField::DateUpd, date("2021-01-01"), date("2021-12-31")),
+        ];
+        let rendered = render_query_params(&params).unwrap();
+        assert_eq!(rendered.iter().filter(|(k, _)| k == "date").count(), 1);
+        assert_eq!(
+            rendered
+                .iter()
+                .filter(|(k, _)| k.starts_with("filter["))
+                .count(),
+            2
+        );
+        assert_eq!(rendered[0].1, "[2020-01-01 00:00:00,2020-12-31 00:00:00]");
+        assert!(render_query_params(&[QueryParam::Limit(1)])
+            .unwrap()
+            .iter()
+            .all(|(k, _)| k != "date"));
+    }
+
+    #[test]
+    fn test_render_sort() {
+        let sort = QueryParam::Sort(vec![
+            ("name".to_string(), SortDir::Asc),
+            ("price".to_string(), SortDir::Desc),
+        ]);
+        assert_eq!(
+            render_query_params(&[sort]).unwrap(),
+            vec![("sort".to_string(), "[name_ASC,price_DESC]".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_render_filter_values_with_separator() {
+        let values = |values: &[&str]| values.iter().map(|s| s.to_string()).collect();
+        let field_value_in = |v| QueryParam::FieldValueIn("reference".to_string(), v);
+        assert_eq!(
+            render_query_params(&[field_value_in(values(&["a", "b,c"]))]).unwrap()[0].1,
+            "[a|b,c]"
+        );
+        let err = render_query_params(&[field_value_in(values(&["a|b"]))]).unwrap_err();
+        assert!(err.to_string().contains("\"a|b\""), "{}", err);
+        let range = QueryParam::Filter {
+            field: "price".to_string(),
+            op: FilterOp::Range,
+            values: values(&["1,5", "10"]),
+        };
+        assert!(render_query_params(&[range]).is_err());
+    }
+
+    #[test]
+    fn test_render_price_params() {
+        let price = |setting: &str, value: &str| QueryParam::PriceParam {
+            field: "my_price".to_string(),
+            setting: PriceSetting::new(setting, value).unwrap(),
+        };
+        assert_eq!(
+            render_query_params(&[
+                price("use_tax", "0"),
+                price("currency", "2"),
+                price("decimals", "4")
+            ])
+            .unwrap(),
+            vec![
+                ("price[my_price][use_tax]".to_string(), "0".to_string()),
+                ("price[my_price][currency]".to_string(), "2".to_string()),
+                ("price[my_price][decimals]".to_string(), "4".to_string()),
+            ]
+        );
+        assert!(PriceSetting::new("use_tax", "maybe").is_err());
+        assert!(PriceSetting::new("tax", "1").is_err());
+    }
+
+    #[test]
+    fn test_render_shop() {
+        assert_eq!(
+            render_query_params(&[QueryParam::Shop(2), QueryParam::ShopGroup(1)]).unwrap(),
+            vec![
+                ("id_shop".to_string(), "2".to_string()),
+                ("id_group_shop".to_string(), "1".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_filters() {
+        let filter = |op, values: &[&str]| QueryParam::Filter {
+            field: "f".to_string(),
+            op,
+            values: values.iter().map(|s| s.to_string()).collect(),
+        };
+        let rendered = render_query_params(&[
+            filter(FilterOp::Range, &["1", "10"]),
+            filter(FilterOp::In, &["a", "b"]),
+            filter(FilterOp::Begins, &["x"]),
+            filter(FilterOp::Contains, &["x"]),
+            filter(FilterOp::Ends, &["x"]),
+            filter(FilterOp::Equals, &["x"]),
+            filter(FilterOp::IsEmpty, &[]),
+            filter(FilterOp::IsNotEmpty, &[]),
+        ])
+        .unwrap()
+        .into_iter()
+        .map(|(_, v)| v)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            rendered,
+            vec!["[1,10]", "[a|b]", "[x]%", "%[x]%", "%[x]", "[x]", "[]", "![]"]
+        );
+    }
+
+    #[test]
+    fn test_default_headers() {
+        let config = |headers: &str| -> HttpConfig {
+            toml::from_str(&format!(
+                "key = \"k\"\nhost = \"h\"\nauthorization_kind = \"Header\"\n[headers]\n{}",
+                headers
+            ))
+            .unwrap()
+        };
+        let headers = default_headers(&config("X-Forwarded-For = \"10.0.0.1\"")).unwrap();
+        assert_eq!(headers.get("x-forwarded-for").unwrap(), "10.0.0.1");
+        assert!(default_headers(&config("authorization = \"Basic x\"")).is_err());
+    }
+
+    #[test]
+    fn test_resource_url_keeps_filter_syntax() {
+        let config: HttpConfig = toml::from_str(
+            "key = \"k\"\nhost = \"https://shop.example\"\nauthorization_kind = \"Header\"",
+        )
+        .unwrap();
+        let http = Http::new(config).unwrap();
+        let products = Resource::new("products".to_string());
+        let params = [
+            QueryParam::Display(query_param::Display::Fields(vec![
+                "id".to_string(),
+                "name".to_string(),
+            ])),
+            QueryParam::FieldValueIn("id".to_string(), vec!["1".to_string(), "5".to_string()]),
+            QueryParam::Filter {
+                field: "name".to_string(),
+                op: FilterOp::Contains,
+                values: vec!["a&b c%".to_string()],
+            },
+            QueryParam::LimitFromIndex(10, 20),
+        ];
+        let url = ws_resource_url(&http, &products, &params).unwrap();
+        assert_eq!(
+            url.query(),
+            Some(
+                "display=[id,name]&filter[id]=[1|5]&filter[name]=%25[a%26b%20c%25]%25&limit=10,20"
+            )
+        );
+    }
+
+    #[test]
+    fn test_tls_config_errors() {
+        let config = |tls: &str| -> HttpConfig {
+            toml::from_str(&format!(
+                "key = \"k\"\nhost = \"h\"\nauthorization_kind = \"Header\"\n{}",
+                tls
+            ))
+            .unwrap()
+        };
+        assert!(Http::new(config("danger_accept_invalid_certs = true")).is_ok());
+        let err = Http::new(config("ca_cert_path = \"/nonexistent/ca.pem\""))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "cannot read /nonexistent/ca.pem");
+        let err = Http::new(config("client_cert_path = \"/nonexistent/cert.pem\""))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "client_cert_path and client_key_path must be set together"
+        );
+    }
+
+    #[test]
+    fn test_basic_authorization() {
+        assert_eq!(
+            basic_authorization(" ZQ88PRJX5VWQHCWE4EE7SQ7HPNX00RAJ\n"),
+            "Basic WlE4OFBSSlg1VldRSENXRTRFRTdTUTdIUE5YMDBSQUo6"
+        );
+    }
+
+    #[test]
+    fn test_parse_error_body() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<prestashop xmlns:xlink="http://www.w3.org/1999/xlink">
+<errors>
+<error>
+<code><![CDATA[90]]></code>
+<message><![CDATA[Id(s) not exists: 999]]></message>
+</error>
+<error>
+<code><![CDATA[41]]></code>
+<message><![CDATA[Validation error: "price" is invalid]]></message>
+</error>
+</errors>
+</prestashop>"#;
+        let errors = parse_error_body(body).unwrap();
+        assert_eq!(
+            errors[0],
+            WsError {
+                code: "90".to_string(),
+                message: "Id(s) not exists: 999".to_string()
+            }
+        );
+        let err: anyhow::Error = ApiError {
+            status: StatusCode::BAD_REQUEST,
+            url: "https://example.com/api/products".to_string(),
+            errors,
+        }
+        .into();
+        assert_eq!(
+            err.to_string(),
+            "HTTP status=400 Bad Request for url=https://example.com/api/products: \
+             90: Id(s) not exists: 999; 41: Validation error: \"price\" is invalid"
+        );
+        assert_eq!(err.downcast_ref::<ApiError>().unwrap().errors[1].code, "41");
+        assert!(parse_error_body("<html>Bad Gateway</html>").is_err());
+    }
+
+    #[test]
+    fn test_resource_access() {
+        let orders = Resource::new("orders".to_string());
+        let forbidden = ApiError {
+            status: StatusCode::UNAUTHORIZED,
+            url: "https://example.com/api/orders".to_string(),
+            errors: vec![],
+        };
+        assert_eq!(
+            resource_access(&orders, Err(forbidden.into())).unwrap(),
+            ResourceAccess {
+                resource: "orders".to_string(),
+                readable: false,
+                status: 401,
+            }
+        );
+        let readable = resource_access(&orders, Ok("<prestashop/>".to_string())).unwrap();
+        assert!(readable.readable);
+        assert_eq!(readable.status, 200);
+        assert!(resource_access(&orders, Err(anyhow::anyhow!("connection reset"))).is_err());
+    }
+
+    #[test]
+    fn test_not_found_error() {
+        let products = Resource::new("products".to_string());
+        let api_error = |status| -> anyhow::Error {
+            ApiError {
+                status,
+                url: "https://example.com/api/products/7".to_string(),
+                errors: vec![],
+            }
+            .into()
+        };
+        assert_eq!(
+            not_found_error(api_error(StatusCode::NOT_FOUND), &products, 7).to_string(),
+            "products with id 7 does not exist"
+        );
+        assert!(
+            not_found_error(api_error(StatusCode::UNAUTHORIZED), &products, 7)
+                .downcast_ref::<ApiError>()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(reqwest::header::RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(
+            retry_after(&headers),
+            Some(std::time::Duration::from_secs(7))
+        );
+        // HTTP dates are not supported, the backoff delay is used instead
+        let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+        headers.insert(reqwest::header::RETRY_AFTER, date.parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+        assert!(is_transient(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_transient(StatusCode::NOT_FOUND));
+    }
+}
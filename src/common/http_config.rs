@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
 #[derive(Debug, serde::Deserialize)]
 pub enum AuthorizationKind {
     QueryParam,
@@ -5,7 +8,165 @@ pub enum AuthorizationKind {
 }
 #[derive(Debug, serde::Deserialize)]
 pub struct HttpConfig {
+    /// WebService key, `${VAR}` reads it from the environment variable VAR
+    #[serde(default)]
     pub key: String,
+    /// Environment variable holding the key, takes precedence over `key`
+    pub key_env: Option<String>,
     pub host: String,
     pub authorization_kind: AuthorizationKind,
+    /// Seconds to wait for a connection to be established, defaults to 30
+    pub connect_timeout_secs: Option<u64>,
+    /// Seconds to wait for a whole request including the response body, defaults to 30
+    pub request_timeout_secs: Option<u64>,
+    /// How many times a GET is retried on 429 and 5xx gateway errors, defaults to 3
+    pub max_retries: Option<u32>,
+    /// Delay before the first retry in milliseconds, doubled on every further retry.
+    /// A Retry-After header sent by the shop takes precedence. Defaults to 500
+    pub retry_base_delay_ms: Option<u64>,
+    /// Requests per second at most, e.g. 0.5 for one every two seconds. Retries
+    /// count too. Unlimited by default
+    pub max_rps: Option<f64>,
+    /// Ask for gzip/deflate compressed responses, defaults to true
+    pub accept_compression: Option<bool>,
+    /// Sent instead of reqwest's default User-Agent
+    pub user_agent: Option<String>,
+    /// Extra headers sent with every request. Authorization can't be set here,
+    /// it is controlled by `authorization_kind`
+    pub headers: Option<HashMap<String, String>>,
+    /// PEM file with extra CA certificates to trust, e.g. of an internal CA
+    pub ca_cert_path: Option<PathBuf>,
+    /// PEM file with the client certificate for mutual TLS, needs `client_key_path`
+    pub client_cert_path: Option<PathBuf>,
+    /// PEM file with the PKCS#8 private key of `client_cert_path`
+    pub client_key_path: Option<PathBuf>,
+    /// Accept any server certificate, e.g. self-signed ones of staging shops.
+    /// Defaults to false
+    pub danger_accept_invalid_certs: Option<bool>,
+}
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 500;
+
+impl HttpConfig {
+    /// Reads either a flat config or one of its `[profiles.<name>]` tables.
+    /// Without a profile name a flat config is used, or the only profile if there is one
+    pub fn from_toml(s: &str, profile: Option<&str>) -> anyhow::Result<Self> {
+        let mut table: toml::Table = toml::from_str(s)?;
+        let profiles = match table.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => return Err(anyhow::anyhow!("profiles must be a table")),
+            None => toml::Table::new(),
+        };
+        let is_flat = table.contains_key("key") || table.contains_key("host");
+        let names = || profiles.keys().cloned().collect::<Vec<_>>().join(", ");
+        let config = match profile {
+            Some(name) => profiles.get(name).cloned().ok_or_else(|| {
+                anyhow::anyhow!("no profile '{}', available profiles: {}", name, names())
+            })?,
+            None if is_flat => toml::Value::Table(table),
+            None if profiles.len() == 1 => profiles.values().next().unwrap().clone(),
+            None => {
+                return Err(anyhow::anyhow!(
+                    "select a profile with --profile, available profiles: {}",
+                    names()
+                ))
+            }
+        };
+        Ok(config.try_into()?)
+    }
+
+    /// Replaces `key` with the value of the environment variable named by
+    /// `key_env` or by a `${VAR}` key
+    pub fn resolve_key(&mut self) -> anyhow::Result<()> {
+        let var = match &self.key_env {
+            Some(var) => Some(var.as_str()),
+            None => self
+                .key
+                .trim()
+                .strip_prefix("${")
+                .and_then(|s| s.strip_suffix('}')),
+        };
+        if let Some(var) = var {
+            self.key = std::env::var(var)
+                .map_err(|e| anyhow::anyhow!("cannot read key from ${}: {}", var, e))?;
+        }
+        if self.key.trim().is_empty() {
+            return Err(anyhow::anyhow!("key is not set, use key or key_env"));
+        }
+        Ok(())
+    }
+    pub fn connect_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.connect_timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS))
+    }
+    pub fn request_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.request_timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS))
+    }
+    pub fn accept_compression(&self) -> bool {
+        self.accept_compression.unwrap_or(true)
+    }
+    pub fn danger_accept_invalid_certs(&self) -> bool {
+        self.danger_accept_invalid_certs.unwrap_or(false)
+    }
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries.unwrap_or(DEFAULT_MAX_RETRIES)
+    }
+    /// Exponential backoff delay before retry number `attempt`, counting from 0
+    pub fn retry_delay(&self, attempt: u32) -> std::time::Duration {
+        let base = self
+            .retry_base_delay_ms
+            .unwrap_or(DEFAULT_RETRY_BASE_DELAY_MS);
+        std::time::Duration::from_millis(base.saturating_mul(1 << attempt.min(16)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        let s = r#"
+            [profiles.staging]
+            key = "S"
+            host = "https://staging.example.com"
+            authorization_kind = "Header"
+
+            [profiles.prod]
+            key = "P"
+            host = "https://example.com"
+            authorization_kind = "QueryParam"
+        "#;
+        assert_eq!(HttpConfig::from_toml(s, Some("prod")).unwrap().key, "P");
+        assert!(HttpConfig::from_toml(s, Some("dev")).is_err());
+        assert!(HttpConfig::from_toml(s, None).is_err());
+        let flat = "key = \"K\"\nhost = \"h\"\nauthorization_kind = \"Header\"";
+        assert_eq!(HttpConfig::from_toml(flat, None).unwrap().key, "K");
+        let single = "[profiles.only]\n".to_string() + flat;
+        assert_eq!(HttpConfig::from_toml(&single, None).unwrap().key, "K");
+    }
+
+    fn config(toml: &str) -> HttpConfig {
+        let base = "host = \"https://example.com\"\nauthorization_kind = \"Header\"\n";
+        toml::from_str(&(base.to_string() + toml)).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_key_from_env() {
+        std::env::set_var("PS17_TEST_KEY", "SECRET");
+        let mut c = config("key = \"${PS17_TEST_KEY}\"");
+        c.resolve_key().unwrap();
+        assert_eq!(c.key, "SECRET");
+        let mut c = config("key_env = \"PS17_TEST_KEY\"");
+        c.resolve_key().unwrap();
+        assert_eq!(c.key, "SECRET");
+        let mut c = config("key = \"PLAIN\"");
+        c.resolve_key().unwrap();
+        assert_eq!(c.key, "PLAIN");
+        assert!(config("key_env = \"PS17_TEST_KEY_UNSET\"")
+            .resolve_key()
+            .is_err());
+        assert!(config("").resolve_key().is_err());
+    }
 }
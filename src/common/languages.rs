@@ -0,0 +1,79 @@
+use crate::parser::Parser;
+use anyhow::Result;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Language {
+    pub id: u32,
+    pub iso_code: String,
+}
+
+/// The shop's language id <-> iso code mapping
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Languages {
+    languages: Vec<Language>,
+}
+
+impl Languages {
+    pub fn iter(&self) -> impl Iterator<Item = &Language> {
+        self.languages.iter()
+    }
+    /// Case insensitive lookup, e.g. "EN" and "en" both match
+    pub fn id_of(&self, iso_code: &str) -> Option<u32> {
+        self.languages
+            .iter()
+            .find(|l| l.iso_code.eq_ignore_ascii_case(iso_code))
+            .map(|l| l.id)
+    }
+    pub fn iso_code_of(&self, id: u32) -> Option<&str> {
+        self.languages
+            .iter()
+            .find(|l| l.id == id)
+            .map(|l| l.iso_code.as_str())
+    }
+}
+
+/// Parses a `languages?display=[id,iso_code]` response
+pub fn parse_languages(p: Parser) -> Result<Languages> {
+    let mut languages = vec![];
+    // a single language shop still returns a (one element) list here
+    for el in p
+        .named("prestashop")?
+        .single_child()?
+        .named("languages")?
+        .only_same_named_children()?
+    {
+        let fields = el.uniquely_named_children_map()?;
+        let text = |name: &str| -> Result<&str> {
+            let p = fields
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("language without '{}'", name))?;
+            Ok(p.node().text().unwrap_or("").trim())
+        };
+        languages.push(Language {
+            id: text("id")?.parse()?,
+            iso_code: text("iso_code")?.to_string(),
+        });
+    }
+    Ok(Languages { languages })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_language() {
+        let source = r#"
+        <prestashop>
+            <languages>
+                <language><id>1</id><iso_code><![CDATA[en]]></iso_code></language>
+            </languages>
+        </prestashop>
+        "#;
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let languages = parse_languages(Parser::new(doc.root_element())).unwrap();
+        assert_eq!(languages.id_of("EN"), Some(1));
+        assert_eq!(languages.iso_code_of(1), Some("en"));
+        assert_eq!(languages.id_of("fr"), None);
+    }
+}
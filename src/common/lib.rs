@@ -1,7 +1,14 @@
 pub mod arrow2;
+pub mod cache;
+pub mod fetch;
 pub mod format;
 pub mod http;
 pub mod http_config;
+pub mod languages;
+pub mod on_error;
 pub mod parser;
+pub mod payload;
+pub mod rate_limit;
 pub mod schema2;
 pub mod utils;
+pub mod xml_stream;
@@ -0,0 +1,48 @@
+use tracing::warn;
+
+/// What to do when a single record in a response fails to parse
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnError {
+    /// Abort the whole parse on the first error
+    #[default]
+    Fail,
+    /// Log the offending record and continue with the next one
+    SkipRecord,
+    /// Log the offending field of the record and read it as null, keeping the
+    /// rest of the record
+    NullValue,
+}
+
+/// Counts what was left out of responses because of [OnError], summed over
+/// all pages of a listing so it is reported once
+#[derive(Debug, Default)]
+pub struct Skipped {
+    pub records: usize,
+    pub values: usize,
+}
+
+impl Skipped {
+    /// Logs the totals, `len` being the number of records that were kept
+    pub fn report(&self, len: usize) {
+        if self.records > 0 {
+            warn!("skipped {} of {} records", self.records, self.records + len);
+        }
+        if self.values > 0 {
+            warn!(
+                "read {} invalid values of {} records as null",
+                self.values, len
+            );
+        }
+    }
+}
+
+/// Describes a record element for log messages, e.g. `record #3 (id=12)`
+pub(crate) fn describe_record(position: usize, node: &roxmltree::Node) -> String {
+    let id = node
+        .children()
+        .find(|c| c.is_element() && c.has_tag_name("id"))
+        .and_then(|c| c.text())
+        .map(|s| s.trim())
+        .unwrap_or("?");
+    format!("record #{} (id={})", position, id)
+}
@@ -1,8 +1,12 @@
 use anyhow::{anyhow, Context, Result};
 use core::fmt::Formatter;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::{Debug, Display};
 
+/// Walks a response by element names. Names are matched by their local part:
+/// namespaces are ignored, so `<ps:product>` and `<product xmlns="..">` are
+/// both `product`, and children with the same local name are duplicates even
+/// when their namespaces differ
 #[derive(Debug, Clone)]
 pub struct Parser<'a> {
     path: Vec<&'a str>,
@@ -45,8 +49,7 @@ impl<'a> Parser<'a> {
         if other == name {
             Ok(self)
         } else {
-            return Err(anyhow!("expected element name '{name}', found '{other}'"))
-                .context(self.context());
+            Err(anyhow!("expected element name '{name}', found '{other}'")).context(self.context())
         }
     }
 
@@ -94,6 +97,56 @@ impl<'a> Parser<'a> {
         Ok(out)
     }
 
+    fn element_children(&self) -> Vec<Parser<'a>> {
+        self.node
+            .children()
+            .filter(|c| c.is_element())
+            .map(|el| {
+                let mut path = self.path.clone();
+                path.push(el.tag_name().name());
+                Parser { path, node: el }
+            })
+            .collect()
+    }
+
+    /// The element holding the records of a list response, normally the single
+    /// child of the root, e.g. `<products>` in `<prestashop><products>`. When
+    /// that does not hold, the first element, breadth first, with repeated
+    /// same-named children is used instead
+    pub fn records_container(self) -> Result<Self> {
+        let expected = self.clone().single_child().and_then(|container| {
+            container.clone().only_same_named_children()?;
+            Ok(container)
+        });
+        match expected {
+            Ok(container) => Ok(container),
+            Err(e) => match self.find_repeated_children() {
+                Some(container) => {
+                    tracing::info!("reading records of {}", container.context());
+                    Ok(container)
+                }
+                None => Err(e).context("cannot find the element holding the records"),
+            },
+        }
+    }
+
+    fn find_repeated_children(self) -> Option<Self> {
+        let mut queue = VecDeque::from([self]);
+        while let Some(p) = queue.pop_front() {
+            let children = p.element_children();
+            let name = children.first().map(|c| c.node.tag_name().name());
+            if children.len() > 1
+                && children
+                    .iter()
+                    .all(|c| Some(c.node.tag_name().name()) == name)
+            {
+                return Some(p);
+            }
+            queue.extend(children);
+        }
+        None
+    }
+
     pub fn uniquely_named_children_map(self) -> Result<HashMap<String, Parser<'a>>> {
         let mut out = HashMap::new();
         for el in self.node.children() {
@@ -200,3 +253,75 @@ impl<'a> Parser<'a> {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_namespaced_elements() {
+        let source = r#"<prestashop xmlns="urn:ps" xmlns:ps="urn:ps" xmlns:xlink="http://www.w3.org/1999/xlink">
+            <ps:products>
+                <ps:product xlink:href="https://shop/api/products/1"><id>1</id><ps:name>a</ps:name></ps:product>
+            </ps:products>
+        </prestashop>"#;
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let products = Parser::new(doc.root_element())
+            .named("prestashop")
+            .unwrap()
+            .single_child()
+            .unwrap()
+            .named("products")
+            .unwrap()
+            .only_same_named_children()
+            .unwrap();
+        assert_eq!(products.len(), 1);
+        let fields = products[0].clone().uniquely_named_children_map().unwrap();
+        assert_eq!(fields["id"].node().text(), Some("1"));
+        assert_eq!(fields["name"].node().text(), Some("a"));
+
+        let clash =
+            r#"<product xmlns:a="urn:a" xmlns:b="urn:b"><a:id>1</a:id><b:id>2</b:id></product>"#;
+        let doc = roxmltree::Document::parse(clash).unwrap();
+        assert!(Parser::new(doc.root_element())
+            .uniquely_named_children_map()
+            .is_err());
+    }
+
+    #[test]
+    fn test_records_container() {
+        let source = "<prestashop><products><product/></products></prestashop>";
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let container = Parser::new(doc.root_element()).records_container().unwrap();
+        assert_eq!(container.context(), r#"["products"]"#);
+
+        let source = r#"<prestashop>
+            <meta><total>2</total></meta>
+            <list><products><product/><product/></products></list>
+        </prestashop>"#;
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let container = Parser::new(doc.root_element()).records_container().unwrap();
+        assert_eq!(container.context(), r#"["list", "products"]"#);
+        // --streaming doesn't search, but names the element instead
+        let e = crate::xml_stream::records(source)
+            .collect::<Result<Vec<_>>>()
+            .unwrap_err();
+        let e = format!("{:#}", e);
+        assert!(
+            e.starts_with("cannot find the element holding the records: []"),
+            "{}",
+            e
+        );
+
+        let source = "<prestashop><products><product/><total/></products></prestashop>";
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let e = Parser::new(doc.root_element())
+            .records_container()
+            .unwrap_err();
+        assert!(format!("{:#}", e).contains(r#"["products"]"#), "{:#}", e);
+        let e = crate::xml_stream::records(source)
+            .collect::<Result<Vec<_>>>()
+            .unwrap_err();
+        assert!(format!("{:#}", e).contains(r#"["products"]"#), "{:#}", e);
+    }
+}
@@ -0,0 +1,333 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+const PRESTASHOP_OPEN: &str = r#"<prestashop xmlns:xlink="http://www.w3.org/1999/xlink">"#;
+const PRESTASHOP_CLOSE: &str = "</prestashop>";
+
+/// Converts a JSON or XML payload into the `<prestashop>` XML envelope the
+/// WebService expects for POST/PUT.
+///
+/// JSON must have the same shape `get` emits for a single record, i.e. a
+/// single key naming the resource element: `{"product": {"price": 1.5}}`.
+/// XML is passed through, wrapped in `<prestashop>` when it isn't already.
+pub fn to_prestashop_xml(input: &str) -> Result<String> {
+    if input.trim_start().starts_with('<') {
+        xml_to_prestashop_xml(input)
+    } else {
+        json_to_prestashop_xml(&serde_json::from_str(input)?)
+    }
+}
+
+fn xml_to_prestashop_xml(input: &str) -> Result<String> {
+    let doc = roxmltree::Document::parse(input)?;
+    let root = doc.root_element();
+    let root_xml = &input[root.range()];
+    if root.has_tag_name("prestashop") {
+        Ok(root_xml.to_string())
+    } else {
+        Ok(format!(
+            "{}{}{}",
+            PRESTASHOP_OPEN, root_xml, PRESTASHOP_CLOSE
+        ))
+    }
+}
+
+pub fn json_to_prestashop_xml(value: &Value) -> Result<String> {
+    let (name, entity) = match value {
+        Value::Object(m) if m.len() == 1 => m.iter().next().unwrap(),
+        _ => {
+            return Err(anyhow!(
+                "expected a single top level key naming the resource element, e.g. {{\"product\": {{..}}}}"
+            ))
+        }
+    };
+    let mut out = PRESTASHOP_OPEN.to_string();
+    write_element(&mut out, name, entity)?;
+    out.push_str(PRESTASHOP_CLOSE);
+    Ok(out)
+}
+
+/// Merges the fields of `patch` into `current`, both `<prestashop>` documents of
+/// a single entity, for a PUT that needs the full entity.
+///
+/// Fields of the patch replace those of the current entity, except for
+/// multilingual fields, where only the given languages are replaced. The
+/// `read_only` fields of the current entity, which have no setter and would be
+/// rejected with `parameter "…" not writable`, are left out.
+pub fn merge_entity_xml(current: &str, patch: &str, read_only: &[String]) -> Result<String> {
+    let current_doc = roxmltree::Document::parse(current)?;
+    let patch_doc = roxmltree::Document::parse(patch)?;
+    let entity = single_entity(&current_doc)?;
+    let patch_entity = single_entity(&patch_doc)?;
+    let name = entity.tag_name().name();
+    if patch_entity.tag_name().name() != name {
+        return Err(anyhow!(
+            "cannot update a {} with a {}",
+            name,
+            patch_entity.tag_name().name()
+        ));
+    }
+    let patch_fields = patch_entity
+        .children()
+        .filter(|c| c.is_element())
+        .collect::<Vec<_>>();
+    for field in &patch_fields {
+        let field_name = field.tag_name().name();
+        if !entity.children().any(|c| c.has_tag_name(field_name)) {
+            return Err(anyhow!("{} has no field {}", name, field_name));
+        }
+        if read_only.iter().any(|f| f == field_name) {
+            return Err(anyhow!("field {} of {} is read-only", field_name, name));
+        }
+    }
+    let mut out = PRESTASHOP_OPEN.to_string();
+    out.push_str(&format!("<{}>", name));
+    for field in entity
+        .children()
+        .filter(|c| c.is_element() && !read_only.iter().any(|f| f == c.tag_name().name()))
+    {
+        let patched = patch_fields
+            .iter()
+            .find(|p| p.has_tag_name(field.tag_name().name()));
+        match patched {
+            None => out.push_str(&current[field.range()]),
+            Some(patched) if is_multilingual(&field) && is_multilingual(patched) => {
+                merge_languages(&mut out, current, &field, patch, patched)?
+            }
+            Some(patched) => out.push_str(&patch[patched.range()]),
+        }
+    }
+    out.push_str(&format!("</{}>", name));
+    out.push_str(PRESTASHOP_CLOSE);
+    Ok(out)
+}
+
+fn single_entity<'a>(doc: &'a roxmltree::Document<'a>) -> Result<roxmltree::Node<'a, 'a>> {
+    let mut entities = doc.root_element().children().filter(|c| c.is_element());
+    match (entities.next(), entities.next()) {
+        (Some(entity), None) => Ok(entity),
+        _ => Err(anyhow!("expected a single entity in <prestashop>")),
+    }
+}
+
+fn is_multilingual(field: &roxmltree::Node) -> bool {
+    let mut children = field.children().filter(|c| c.is_element()).peekable();
+    children.peek().is_some() && children.all(|c| c.has_tag_name("language"))
+}
+
+fn merge_languages(
+    out: &mut String,
+    current: &str,
+    field: &roxmltree::Node,
+    patch: &str,
+    patched: &roxmltree::Node,
+) -> Result<()> {
+    let name = field.tag_name().name();
+    out.push_str(&format!("<{}>", name));
+    for language in field.children().filter(|c| c.is_element()) {
+        let id = language.attribute("id");
+        let replacement = patched
+            .children()
+            .filter(|c| c.is_element())
+            .find(|c| c.attribute("id") == id);
+        match replacement {
+            Some(replacement) => out.push_str(&patch[replacement.range()]),
+            None => out.push_str(&current[language.range()]),
+        }
+    }
+    for language in patched.children().filter(|c| c.is_element()) {
+        let id = language.attribute("id");
+        if !field
+            .children()
+            .any(|c| c.is_element() && c.attribute("id") == id)
+        {
+            return Err(anyhow!(
+                "field {} has no language with id {}",
+                name,
+                id.unwrap_or("?")
+            ));
+        }
+    }
+    out.push_str(&format!("</{}>", name));
+    Ok(())
+}
+
+fn write_element(out: &mut String, name: &str, value: &Value) -> Result<()> {
+    out.push('<');
+    out.push_str(name);
+    // xmltodict style attributes, as produced by the arrow2 path
+    if let Value::Object(m) = value {
+        for (k, v) in m.iter().filter(|(k, _)| k.starts_with('@')) {
+            out.push_str(&format!(
+                " {}=\"{}\"",
+                &k[1..],
+                escape(&scalar_to_string(v)?)
+            ));
+        }
+    }
+    out.push('>');
+    write_value(out, value)?;
+    out.push_str("</");
+    out.push_str(name);
+    out.push('>');
+    Ok(())
+}
+
+fn write_value(out: &mut String, value: &Value) -> Result<()> {
+    match value {
+        Value::Object(m) => {
+            for (k, v) in m {
+                if k == "#text" {
+                    out.push_str(&escape(&scalar_to_string(v)?));
+                } else if !k.starts_with('@') {
+                    write_element(out, k, v)?;
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                write_list_item(out, item)?;
+            }
+        }
+        other => out.push_str(&escape(&scalar_to_string(other)?)),
+    }
+    Ok(())
+}
+
+fn write_list_item(out: &mut String, item: &Value) -> Result<()> {
+    let m = match item {
+        Value::Object(m) => m,
+        other => return Err(anyhow!("expected list items to be objects, got {}", other)),
+    };
+    // multilingual fields: {"id": 1, "language": ".."} or {"@id": 1, "#text": ".."}
+    let language = match (m.get("id"), m.get("language"), m.get("@id"), m.get("#text")) {
+        (Some(id), Some(text), None, None) if m.len() == 2 => Some((id, text)),
+        (None, None, Some(id), Some(text)) if m.len() == 2 => Some((id, text)),
+        _ => None,
+    };
+    if let Some((id, text)) = language {
+        out.push_str(&format!(
+            "<language id=\"{}\">{}</language>",
+            escape(&scalar_to_string(id)?),
+            escape(&scalar_to_string(text)?)
+        ));
+        Ok(())
+    } else if m.len() == 1 {
+        let (name, value) = m.iter().next().unwrap();
+        write_element(out, name, value)
+    } else {
+        Err(anyhow!(
+            "cannot infer the element name of list item {}",
+            item
+        ))
+    }
+}
+
+fn scalar_to_string(value: &Value) -> Result<String> {
+    Ok(match value {
+        Value::Null => String::new(),
+        Value::Bool(b) => if *b { "1" } else { "0" }.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.to_string(),
+        other => return Err(anyhow!("expected a scalar value, got {}", other)),
+    })
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_json_to_prestashop_xml() {
+        let json = r##"{"product": {
+            "active": true,
+            "associations": {"categories": [{"category": {"id": 3}}]},
+            "name": [{"id": 1, "language": "a & b"}, {"@id": 2, "#text": "c"}],
+            "price": 1.5
+        }}"##;
+        assert_eq!(
+            to_prestashop_xml(json).unwrap(),
+            PRESTASHOP_OPEN.to_string()
+                + "<product><active>1</active>"
+                + "<associations><categories><category><id>3</id></category></categories></associations>"
+                + r#"<name><language id="1">a &amp; b</language><language id="2">c</language></name>"#
+                + "<price>1.5</price></product></prestashop>"
+        );
+    }
+
+    #[test]
+    fn test_merge_entity_xml() {
+        let current = r#"<?xml version="1.0" encoding="UTF-8"?>
+<prestashop xmlns:xlink="http://www.w3.org/1999/xlink"><product>
+    <id><![CDATA[7]]></id>
+    <id_manufacturer xlink:href="https://shop/api/manufacturers/2"><![CDATA[2]]></id_manufacturer>
+    <price><![CDATA[10.000000]]></price>
+    <name><language id="1"><![CDATA[old]]></language><language id="2"><![CDATA[alt]]></language></name>
+</product></prestashop>"#;
+        let patch = r#"{"product": {"price": 12.5, "name": [{"id": 1, "language": "new"}]}}"#;
+        let merged = merge_entity_xml(current, &to_prestashop_xml(patch).unwrap(), &[]).unwrap();
+        assert_eq!(
+            merged,
+            PRESTASHOP_OPEN.to_string()
+                + "<product><id><![CDATA[7]]></id>"
+                + r#"<id_manufacturer xlink:href="https://shop/api/manufacturers/2"><![CDATA[2]]></id_manufacturer>"#
+                + "<price>12.5</price>"
+                + r#"<name><language id="1">new</language><language id="2"><![CDATA[alt]]></language></name>"#
+                + "</product></prestashop>"
+        );
+        let typo = r#"{"product": {"prcie": 1}}"#;
+        let err = merge_entity_xml(current, &to_prestashop_xml(typo).unwrap(), &[]).unwrap_err();
+        assert_eq!(err.to_string(), "product has no field prcie");
+        let other = r#"{"category": {"price": 1}}"#;
+        assert!(merge_entity_xml(current, &to_prestashop_xml(other).unwrap(), &[]).is_err());
+    }
+
+    #[test]
+    fn test_merge_drops_read_only_fields() {
+        let current = r#"<prestashop xmlns:xlink="http://www.w3.org/1999/xlink"><product>
+    <id><![CDATA[7]]></id>
+    <manufacturer_name not_filterable="true"><![CDATA[Acme]]></manufacturer_name>
+    <quantity not_filterable="true"><![CDATA[3]]></quantity>
+    <price><![CDATA[10.000000]]></price>
+</product></prestashop>"#;
+        let read_only = ["manufacturer_name".to_string(), "quantity".to_string()];
+        let patch = r#"{"product": {"price": 12.5}}"#;
+        let merged =
+            merge_entity_xml(current, &to_prestashop_xml(patch).unwrap(), &read_only).unwrap();
+        assert_eq!(
+            merged,
+            PRESTASHOP_OPEN.to_string()
+                + "<product><id><![CDATA[7]]></id><price>12.5</price></product></prestashop>"
+        );
+        let patch = r#"{"product": {"quantity": 5}}"#;
+        let err =
+            merge_entity_xml(current, &to_prestashop_xml(patch).unwrap(), &read_only).unwrap_err();
+        assert_eq!(err.to_string(), "field quantity of product is read-only");
+    }
+
+    #[test]
+    fn test_xml_is_wrapped_once() {
+        let xml = r#"<?xml version="1.0"?><product><price>1</price></product>"#;
+        let wrapped = to_prestashop_xml(xml).unwrap();
+        assert_eq!(
+            wrapped,
+            PRESTASHOP_OPEN.to_string() + "<product><price>1</price></product></prestashop>"
+        );
+        assert_eq!(to_prestashop_xml(&wrapped).unwrap(), wrapped);
+    }
+}
@@ -0,0 +1,66 @@
+//! Spaces requests out evenly so that a shop's request rate limit isn't hit
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Lets a request through every `1 / max_rps` seconds, i.e. a token bucket
+/// holding a single token. Requests that arrive while the limiter was idle go
+/// through at once, but never in a burst, which strict shop limits would count
+/// against
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_rps: f64) -> Result<Self> {
+        if !(max_rps.is_finite() && max_rps > 0.0) {
+            return Err(anyhow!("max_rps must be positive, got {}", max_rps));
+        }
+        let interval = Duration::try_from_secs_f64(1.0 / max_rps)
+            .map_err(|_| anyhow!("max_rps {} is too small", max_rps))?;
+        Ok(Self {
+            interval,
+            next: Mutex::new(None),
+        })
+    }
+
+    /// Waits until the next request may be sent
+    pub async fn acquire(&self) {
+        let at = {
+            let mut next = self.next.lock().await;
+            let now = Instant::now();
+            let at = next.map_or(now, |next| next.max(now));
+            *next = Some(at + self.interval);
+            at
+        };
+        tokio::time::sleep_until(at).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_acquire_spaces_requests() {
+        let limiter = RateLimiter::new(100.0).unwrap();
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::from_millis(40));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let idle = Instant::now();
+        limiter.acquire().await;
+        assert_eq!(idle.elapsed(), Duration::ZERO);
+        assert!(RateLimiter::new(0.0).is_err());
+        assert!(RateLimiter::new(1e-20).is_err());
+        assert!(RateLimiter::new(f64::MIN_POSITIVE / 2.0).is_err());
+    }
+}
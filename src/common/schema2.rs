@@ -5,9 +5,13 @@ use anyhow::{anyhow, Result};
 
 use serde_json::{Number, Value};
 
-use arrow::datatypes::{DataType, Fields};
+use arrow::datatypes::{DataType, Fields, TimeUnit};
+use chrono::{NaiveDate, NaiveDateTime};
 
-use crate::format::Format;
+use crate::format::{format_fallback, Format};
+use crate::on_error::{describe_record, OnError, Skipped};
+use crate::utils::{is_id_name, node_text, parse_bool};
+use crate::xml_stream;
 
 use std::sync::Arc;
 use tracing::warn;
@@ -20,6 +24,10 @@ pub struct Record {
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Schema {
     record: Record,
+    /// Fields of the resource element that the synopsis marks `read_only`,
+    /// they have no setter and are rejected in a PUT
+    #[serde(default)]
+    read_only: Vec<String>,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -28,19 +36,17 @@ pub struct Field {
     ty: Type,
 }
 
-impl Field {
-    fn new(name: String, ty: Type) -> Self {
-        Self { name, ty }
-    }
-}
-
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum Type {
     Int32,
     UInt32,
+    Int64,
+    UInt64,
     Float64,
     Utf8,
     Bool,
+    /// Seconds since the epoch, as arrow2's schema3 stores dates
+    Date,
     Record(Record),
     List(Box<Field>),
     Language(u32),
@@ -51,9 +57,12 @@ impl Type {
         match self {
             Type::Int32 => DataType::Int32,
             Type::UInt32 => DataType::UInt32,
+            Type::Int64 => DataType::Int64,
+            Type::UInt64 => DataType::UInt64,
             Type::Float64 => DataType::Float64,
             Type::Utf8 => DataType::Utf8,
             Type::Bool => DataType::Boolean,
+            Type::Date => DataType::Timestamp(TimeUnit::Second, None),
             Type::Record(record) => DataType::Struct(
                 record
                     .fields
@@ -100,6 +109,129 @@ impl Schema {
                 .collect::<Vec<_>>(),
         )
     }
+
+    pub fn read_only_fields(&self) -> &[String] {
+        &self.read_only
+    }
+
+    /// Overrides the inferred type of a field of the resource element
+    pub fn cast_field(&mut self, name: &str, ty: Type) -> Result<()> {
+        let fields = match self.record.fields.first_mut().map(|f| &mut f.ty) {
+            Some(Type::Record(record)) => &mut record.fields,
+            _ => return Err(anyhow!("schema has no resource element")),
+        };
+        let field = fields
+            .iter_mut()
+            .find(|f| f.name == name)
+            .ok_or_else(|| anyhow!("cannot cast unknown field {:?}", name))?;
+        if matches!(
+            field.ty,
+            Type::Record(_) | Type::List(_) | Type::Language(_)
+        ) {
+            return Err(anyhow!("cannot cast nested field {:?}", name));
+        }
+        field.ty = ty;
+        Ok(())
+    }
+
+    /// Adds a top-level field that the resource's schema does not describe,
+    /// e.g. a price computed because of a `price[field][..]` parameter. It is
+    /// placed before `associations`, which stay last
+    pub fn add_field(&mut self, name: &str, ty: Type) -> Result<()> {
+        let fields = match self.record.fields.first_mut().map(|f| &mut f.ty) {
+            Some(Type::Record(record)) => &mut record.fields,
+            _ => return Err(anyhow!("schema has no resource element")),
+        };
+        if fields.iter().any(|f| f.name == name) {
+            return Err(anyhow!("field {:?} already exists", name));
+        }
+        let index = fields
+            .iter()
+            .position(|f| f.name == "associations")
+            .unwrap_or(fields.len());
+        let name = name.to_string();
+        fields.insert(index, Field { name, ty });
+        Ok(())
+    }
+
+    /// Keeps only the given subfields of nested fields of the resource element,
+    /// e.g. `associations.images` drops every association but `images`. Paths
+    /// without a dot leave their field as is
+    pub fn select_subfields(&mut self, paths: &[String]) -> Result<()> {
+        let fields = match self.record.fields.first_mut().map(|f| &mut f.ty) {
+            Some(Type::Record(record)) => &mut record.fields,
+            _ => return Err(anyhow!("schema has no resource element")),
+        };
+        let paths = paths.iter().map(String::as_str).collect::<Vec<_>>();
+        for (name, rests) in group_paths(&paths) {
+            let field = fields
+                .iter_mut()
+                .find(|f| f.name == name)
+                .ok_or_else(|| anyhow!("unknown field {:?}", name))?;
+            if let Some(rests) = rests {
+                select_type_subfields(&mut field.ty, &rests, name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of the fields of the resource element, e.g. `id` and `name` of `<product>`
+    pub fn field_names(&self) -> Vec<&str> {
+        match self.record.fields.first().map(|f| &f.ty) {
+            Some(Type::Record(record)) => record.fields.iter().map(|f| f.name.as_str()).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Groups paths like `a.b` by their first segment, in order of appearance. The
+/// rests are `None` when the whole field is selected
+fn group_paths<'a>(paths: &[&'a str]) -> Vec<(&'a str, Option<Vec<&'a str>>)> {
+    let mut groups: Vec<(&str, Option<Vec<&str>>)> = vec![];
+    for path in paths {
+        let (name, rest) = match path.split_once('.') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (*path, None),
+        };
+        let index = match groups.iter().position(|(n, _)| *n == name) {
+            Some(index) => index,
+            None => {
+                groups.push((name, Some(vec![])));
+                groups.len() - 1
+            }
+        };
+        match (&mut groups[index].1, rest) {
+            (Some(rests), Some(rest)) => rests.push(rest),
+            (rests, None) => *rests = None,
+            (None, Some(_)) => {}
+        }
+    }
+    groups
+}
+
+fn select_type_subfields(ty: &mut Type, paths: &[&str], at: &str) -> Result<()> {
+    match ty {
+        Type::List(field) => select_type_subfields(&mut field.ty, paths, at),
+        Type::Record(record) => {
+            let groups = group_paths(paths);
+            for (name, _) in &groups {
+                if !record.fields.iter().any(|f| f.name == *name) {
+                    return Err(anyhow!("unknown field \"{}.{}\"", at, name));
+                }
+            }
+            record
+                .fields
+                .retain(|f| groups.iter().any(|(name, _)| f.name == *name));
+            for (name, rests) in groups {
+                if let Some(rests) = rests {
+                    let field = record.fields.iter_mut().find(|f| f.name == name).unwrap();
+                    select_type_subfields(&mut field.ty, &rests, &format!("{}.{}", at, name))?;
+                }
+            }
+            Ok(())
+        }
+        _ => Err(anyhow!("field {:?} has no subfields", at)),
+    }
 }
 
 mod pp {
@@ -123,10 +255,8 @@ mod pp {
 
     pub fn pretty_print_record(record: &Record, depth: usize, max_depth: usize) -> String {
         let mut lines = vec![];
-        let prefix = std::iter::repeat(' ').take(depth * 4).collect::<String>();
-        let prefix2 = std::iter::repeat(' ')
-            .take(depth * 4 + 4)
-            .collect::<String>();
+        let prefix = " ".repeat(depth * 4);
+        let prefix2 = " ".repeat(depth * 4 + 4);
         lines.push("{".to_string());
         record.fields.iter().for_each(|field| {
             lines.push(format!(
@@ -141,6 +271,41 @@ mod pp {
     }
 }
 
+/// Describes an arrow schema as JSON, in the same shape as
+/// [`crate::arrow2::utils::schema_to_json`] does for arrow2
+pub fn arrow_schema_to_json(schema: &arrow::datatypes::Schema) -> Value {
+    serde_json::json!({ "fields": schema.fields().iter().map(|f| arrow_field_to_json(f)).collect::<Vec<_>>() })
+}
+
+fn arrow_field_to_json(field: &arrow::datatypes::Field) -> Value {
+    serde_json::json!({
+        "name": field.name(),
+        "type": arrow_data_type_to_json(field.data_type()),
+        "nullable": field.is_nullable(),
+    })
+}
+
+fn arrow_data_type_to_json(data_type: &DataType) -> Value {
+    match data_type {
+        DataType::Boolean => "bool".into(),
+        DataType::Int32 => "int32".into(),
+        DataType::UInt32 => "uint32".into(),
+        DataType::Int64 => "int64".into(),
+        DataType::UInt64 => "uint64".into(),
+        DataType::Float64 => "float64".into(),
+        DataType::Utf8 => "string".into(),
+        DataType::Timestamp(TimeUnit::Second, None) => "timestamp[s]".into(),
+        DataType::Decimal128(precision, scale) => {
+            serde_json::json!({ "decimal": { "precision": precision, "scale": scale } })
+        }
+        DataType::List(field) => serde_json::json!({ "list": arrow_field_to_json(field) }),
+        DataType::Struct(fields) => serde_json::json!({
+            "struct": fields.iter().map(|f| arrow_field_to_json(f)).collect::<Vec<_>>()
+        }),
+        other => format!("{:?}", other).into(),
+    }
+}
+
 pub fn pretty_print(schema: &Schema) -> String {
     pp::pretty_print_record(&schema.record, 0, usize::MAX)
 }
@@ -149,13 +314,51 @@ pub fn pretty_print_max_depth(schema: &Schema, max_depth: usize) -> String {
     pp::pretty_print_record(&schema.record, 0, max_depth)
 }
 
+/// Replaces objects and lists nested deeper than `max_depth` records by `"..."`,
+/// counting depth like [pretty_print_max_depth] does: lists don't add a level
+pub fn truncate_json_max_depth(value: Value, max_depth: usize) -> Value {
+    truncate_json(value, 0, max_depth)
+}
+
+fn truncate_json(value: Value, depth: usize, max_depth: usize) -> Value {
+    match value {
+        Value::Object(_) | Value::Array(_) if depth >= max_depth => {
+            Value::String("...".to_string())
+        }
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, truncate_json(v, depth + 1, max_depth)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|v| truncate_json(v, depth, max_depth))
+                .collect(),
+        ),
+        scalar => scalar,
+    }
+}
+
 impl Type {
+    /// Parses the type of a `--cast`: utf8, bool, int32, uint32, int64, uint64,
+    /// float64 or date
+    pub fn from_cast_name(name: &str) -> Result<Type> {
+        Ok(match name.trim().to_ascii_lowercase().as_str() {
+            "utf8" | "string" => Type::Utf8,
+            "bool" | "boolean" => Type::Bool,
+            "int32" => Type::Int32,
+            "uint32" => Type::UInt32,
+            "int64" => Type::Int64,
+            "uint64" => Type::UInt64,
+            "float64" => Type::Float64,
+            "date" => Type::Date,
+            _ => return Err(anyhow!("unknown type {:?}", name)),
+        })
+    }
+
     fn from_name(name: &str) -> Option<Type> {
-        if name.starts_with("id") || name.ends_with("id") {
-            warn!(
-                "assuming field '{}' is UInt32 because it has 'id' in the name",
-                name
-            );
+        if is_id_name(name) {
             Some(Type::UInt32)
         } else {
             None
@@ -165,8 +368,12 @@ impl Type {
         Ok(match f {
             Format::IsBool => Type::Bool,
             Format::IsUnsignedId => Type::UInt32,
+            // all fields are nullable, so "null or" needs nothing more
+            Format::IsNullOrUnsignedId => Type::UInt32,
             Format::IsUnsignedInt => Type::UInt32,
             Format::IsInt => Type::Int32,
+            // ip2long values don't fit Int32 and are negative on 32 bit PHP
+            Format::IsIp2Long => Type::Int64,
             Format::IsUnsignedFloat => Type::Float64,
             Format::IsPrice => Type::Float64,
 
@@ -175,8 +382,8 @@ impl Type {
             Format::IsUpc => Type::Utf8,
             Format::IsIsbn => Type::Utf8,
             //
-            Format::IsDateFormat => Type::Utf8,
-            Format::IsDate => Type::Utf8,
+            Format::IsDateFormat => Type::Date,
+            Format::IsDate => Type::Date,
             //
             //^both|catalog|search|none$/i/
             Format::IsProductVisibility => Type::Utf8,
@@ -189,34 +396,32 @@ impl Type {
             Format::IsGenericName1 => Type::Utf8,
             Format::IsMpn => Type::Utf8,
             Format::IsReference => Type::Utf8,
+            // kept as the raw serialized string
+            Format::IsJson => Type::Utf8,
+            Format::IsSerializedArray => Type::Utf8,
+            f if f.is_string_like() => Type::Utf8,
             //_ => DataType::Utf8,
             _unsupported => return Err(anyhow!("format {:?} is not supported", f)),
         })
     }
 }
 
-fn try_type_from_format(p: &Parser) -> Result<Option<Type>> {
-    if let Ok(format_string) = p.attribute("format") {
-        let format = Format::from_string(format_string.to_string())?;
-        if let Ok(ty) = Type::from_format(&format) {
-            return Ok(Some(ty));
+fn try_type_from_format(p: &Parser, strict: bool) -> Result<Option<Type>> {
+    let Ok(format_string) = p.attribute("format") else {
+        return Ok(None);
+    };
+    match Format::from_string(format_string.to_string()).and_then(|f| Type::from_format(&f)) {
+        Ok(ty) => Ok(Some(ty)),
+        Err(e) => {
+            format_fallback(p.node().tag_name().name(), e, strict)?;
+            Ok(Some(Type::Utf8))
         }
     }
-    Ok(None)
 }
 
-fn warp_in_record_field(field_name: String, struct_field_name: String, ty: Type) -> Field {
-    Field::new(
-        field_name,
-        Type::Record(Record {
-            fields: vec![Field::new(struct_field_name, ty)],
-        }),
-    )
-}
-
-pub fn parse_schema_field_type(name: Option<&str>, p: Parser) -> Result<Type> {
+pub fn parse_schema_field_type(name: Option<&str>, p: Parser, strict: bool) -> Result<Type> {
     let mut fields = vec![];
-    let maybe_ty = try_type_from_format(&p)?.or_else(|| name.and_then(Type::from_name));
+    let maybe_ty = try_type_from_format(&p, strict)?.or_else(|| name.and_then(Type::from_name));
 
     if let Ok(v) = p.clone().only_same_named_children1() {
         let tmp = v[0].clone();
@@ -233,7 +438,7 @@ pub fn parse_schema_field_type(name: Option<&str>, p: Parser) -> Result<Type> {
     if let Ok(_a) = p.clone().attribute("nodeType") {
         let p = p.single_child()?;
         let name = p.node().tag_name().name();
-        let ty = parse_schema_field_type(Some(name), p)?;
+        let ty = parse_schema_field_type(Some(name), p, strict)?;
         return Ok(Type::List(Box::new(Field {
             name: name.to_string(),
             ty,
@@ -242,13 +447,13 @@ pub fn parse_schema_field_type(name: Option<&str>, p: Parser) -> Result<Type> {
 
     for child in p.uniquely_named_children()? {
         let name = child.node().tag_name().name();
-        let ty = parse_schema_field_type(Some(name), child)?;
+        let ty = parse_schema_field_type(Some(name), child, strict)?;
         fields.push(Field {
             name: name.to_string(),
             ty,
         });
     }
-    let ty = if fields.len() > 0 {
+    let ty = if !fields.is_empty() {
         Type::Record(Record { fields })
     } else if let Some(ty) = maybe_ty {
         ty
@@ -270,18 +475,27 @@ fn insert_id_field(mut schema: Schema) -> Result<Schema> {
     Ok(schema)
 }
 
-fn insert_id_field2(mut record: Record) -> Result<Record> {
-    let id_field = Field {
-        name: "id".to_string(),
-        ty: Type::UInt32,
-    };
-    record.fields.insert(0, id_field);
-    Ok(record)
-}
 pub fn parse_schema(p: Parser) -> Result<Schema> {
-    let ty = parse_schema_field_type(None, p)?;
+    parse_schema_with(p, false, true)
+}
+
+/// Same as [parse_schema], but formats that are unknown or have no type are an
+/// error when `strict` instead of being read as strings. Without `id_field`
+/// the `id` the synopsis leaves out isn't added, for resources whose records
+/// have no id
+pub fn parse_schema_with(p: Parser, strict: bool, id_field: bool) -> Result<Schema> {
+    let read_only = p
+        .node()
+        .children()
+        .filter(|c| c.is_element())
+        .flat_map(|entity| entity.children())
+        .filter(|c| c.is_element() && c.attribute("read_only") == Some("true"))
+        .map(|c| c.tag_name().name().to_string())
+        .collect();
+    let ty = parse_schema_field_type(None, p, strict)?;
     match ty {
-        Type::Record(record) => Ok(insert_id_field(Schema { record })?),
+        Type::Record(record) if id_field => Ok(insert_id_field(Schema { record, read_only })?),
+        Type::Record(record) => Ok(Schema { record, read_only }),
         _ => Err(anyhow!(
             "schema must parse to struct, got this value:\n{:?}",
             ty
@@ -297,7 +511,6 @@ fn parse_xml_list_field(p: Parser, field: &Field) -> Result<Value> {
     )])))
 }
 
-
 fn parse_from_str<A: FromStr>(o: Parser) -> Result<Option<A>>
 where
     <A as FromStr>::Err: std::fmt::Debug,
@@ -325,6 +538,21 @@ where
     }
 }
 
+/// Parses `2020-01-02 03:04:05` and `2020-01-02`. Empty values and
+/// PrestaShop's zero date `0000-00-00 00:00:00` are null
+pub(crate) fn parse_date(text: Option<&str>) -> Result<Option<NaiveDateTime>> {
+    let text = text.unwrap_or("").trim();
+    if text.is_empty() || text.starts_with("0000-00-00") {
+        return Ok(None);
+    }
+    let date = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").or_else(|_| {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").map(|d| d.and_hms_opt(0, 0, 0).unwrap())
+    });
+    Ok(Some(
+        date.map_err(|e| anyhow!("invalid date '{}': {}", text, e))?,
+    ))
+}
+
 fn from_option(opt: Option<Value>) -> serde_json::Value {
     opt.unwrap_or(Value::Null)
 }
@@ -335,7 +563,7 @@ fn parse_xml_node_to_json(p: Parser, ty: &Type) -> Result<serde_json::Value> {
             let v: Vec<_> = Result::from_iter(
                 p.only_same_named_children()?
                     .into_iter()
-                    .map(|c| parse_xml_list_field(c.clone(), &field)),
+                    .map(|c| parse_xml_list_field(c.clone(), field)),
             )?;
             Value::Array(v)
         }
@@ -351,9 +579,11 @@ fn parse_xml_node_to_json(p: Parser, ty: &Type) -> Result<serde_json::Value> {
             }
             Value::Array(v)
         }
-        Type::Record(record) => parse_xml_record_to_json(p, &record)?,
+        Type::Record(record) => parse_xml_record_to_json(p, record)?,
         Type::Int32 => text_to_json_number::<i32>(p)?,
         Type::UInt32 => text_to_json_number::<u32>(p)?,
+        Type::Int64 => text_to_json_number::<i64>(p)?,
+        Type::UInt64 => text_to_json_number::<u64>(p)?,
         Type::Float64 => {
             let opt: Option<f64> = parse_from_str(p)?;
             match opt {
@@ -361,11 +591,13 @@ fn parse_xml_node_to_json(p: Parser, ty: &Type) -> Result<serde_json::Value> {
                 None => Value::Null,
             }
         }
-        Type::Utf8 => from_option(p.node().text().map(|s| Value::String(s.to_string()))),
-        Type::Bool => match p.node().text() {
-            Some("1") => Value::Bool(true),
-            Some("0") => Value::Bool(false),
-            Some(z) => return Err(anyhow!("invalid boolean: '{}'", z)),
+        Type::Utf8 => from_option(node_text(&p.node()).map(|s| Value::String(s.into_owned()))),
+        Type::Date => match parse_date(p.node().text())? {
+            Some(date) => Value::Number(Number::from(date.and_utc().timestamp())),
+            None => Value::Null,
+        },
+        Type::Bool => match p.node().text().map(parse_bool).transpose()?.flatten() {
+            Some(b) => Value::Bool(b),
             None => Value::Null,
         },
     };
@@ -389,34 +621,476 @@ fn wrap_in_object(key: String, value: Value) -> Value {
     Value::Object(serde_json::Map::from_iter([(key, value)]))
 }
 
-#[tracing::instrument(skip(p, schema))]
-pub fn parse_data_to_jsonl(p: Parser, schema: &Schema) -> Result<Vec<serde_json::Value>> {
+/// Parses the records of a list response, adding what `on_error` left out
+/// to `skipped` for the caller to report
+#[tracing::instrument(skip(p, schema, skipped))]
+pub fn parse_data_to_jsonl(
+    p: Parser,
+    schema: &Schema,
+    on_error: OnError,
+    skipped: &mut Skipped,
+) -> Result<Vec<serde_json::Value>> {
     let ty = &schema.record.fields[0].ty;
     let mut out = vec![];
-    for el in p.single_child()?.only_same_named_children()? {
-        let name = el.node().tag_name().name().to_string();
-        let json = parse_xml_node_to_json(el, &ty)?;
-        let json = wrap_in_object(name, json);
-        out.push(json);
+    for (position, el) in p
+        .records_container()?
+        .only_same_named_children()?
+        .into_iter()
+        .enumerate()
+    {
+        if let Some(json) = parse_record_to_json(position, el, ty, on_error, skipped)? {
+            out.push(json);
+        }
     }
     Ok(out)
 }
 
+/// Parses one record of a list response, returning `None` for a record
+/// skipped because of `on_error`
+fn parse_record_to_json(
+    position: usize,
+    el: Parser,
+    ty: &Type,
+    on_error: OnError,
+    skipped: &mut Skipped,
+) -> Result<Option<serde_json::Value>> {
+    let name = el.node().tag_name().name().to_string();
+    let node = el.node();
+    let result = match ty {
+        Type::Record(record) if on_error == OnError::NullValue => {
+            parse_xml_record_to_json_lenient(position, el, record, skipped)
+        }
+        _ => parse_xml_node_to_json(el, ty),
+    };
+    match result {
+        Ok(json) => Ok(Some(wrap_in_object(name, json))),
+        Err(e) if on_error == OnError::SkipRecord => {
+            warn!("skipping {}: {:#}", describe_record(position, &node), e);
+            skipped.records += 1;
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Same as [parse_xml_record_to_json], but a field that fails to parse is
+/// logged and left out, so that it is read as null
+fn parse_xml_record_to_json_lenient(
+    position: usize,
+    p: Parser,
+    record: &Record,
+    skipped: &mut Skipped,
+) -> Result<serde_json::Value> {
+    let node = p.node();
+    let elements = p.uniquely_named_children_map()?;
+    let mut entries = serde_json::Map::new();
+    for field in &record.fields {
+        if let Some(el) = elements.get(field.name.as_str()) {
+            match parse_xml_node_to_json(el.clone(), &field.ty) {
+                Ok(json) => {
+                    entries.insert(field.name.to_string(), json);
+                }
+                Err(e) => {
+                    warn!(
+                        "reading field {:?} of {} as null: {:#}",
+                        field.name,
+                        describe_record(position, &node),
+                        e
+                    );
+                    skipped.values += 1;
+                }
+            }
+        }
+    }
+    Ok(Value::Object(entries))
+}
+
+/// Parses a single entity response, e.g. `<prestashop><product>..</product></prestashop>`
+#[tracing::instrument(skip(p, schema))]
+pub fn parse_entity_to_json(p: Parser, schema: &Schema) -> Result<serde_json::Value> {
+    let field = &schema.record.fields[0];
+    let el = p.single_child()?.named(field.name.as_str())?;
+    let json = parse_xml_node_to_json(el, &field.ty)?;
+    Ok(wrap_in_object(field.name.to_string(), json))
+}
+
 #[tracing::instrument(skip(p, schema))]
 pub fn parse_data_to_json(p: Parser, schema: &Schema) -> Result<serde_json::Value> {
     let ty = Type::List(Box::new(schema.record.fields[0].clone()));
     parse_xml_node_to_json(p.single_child()?, &ty)
 }
 
-#[tracing::instrument(skip(p, schema))]
-pub fn parse_data_to_arrow(p: Parser, schema: &Schema) -> Result<arrow::record_batch::RecordBatch> {
+#[tracing::instrument(skip(p, schema, skipped))]
+pub fn parse_data_to_arrow(
+    p: Parser,
+    schema: &Schema,
+    on_error: OnError,
+    skipped: &mut Skipped,
+) -> Result<arrow::record_batch::RecordBatch> {
     let arrow_schema = Arc::new(schema.to_arrow());
     let mut decoder =
         arrow::json::reader::ReaderBuilder::new(arrow_schema.clone()).build_decoder()?;
-    let json = parse_data_to_jsonl(p, schema)?;
+    let json = parse_data_to_jsonl(p, schema, on_error, skipped)?;
     decoder.serialize(&json)?;
     let batch = decoder
         .flush()?
         .unwrap_or_else(|| arrow::record_batch::RecordBatch::new_empty(arrow_schema.clone()));
     Ok(batch)
 }
+
+/// Same as [`parse_data_to_arrow`], but reads the response one record at a
+/// time instead of parsing it into a single DOM
+#[tracing::instrument(skip(source, schema, skipped))]
+pub fn parse_data_to_arrow_streaming(
+    source: &str,
+    schema: &Schema,
+    on_error: OnError,
+    skipped: &mut Skipped,
+) -> Result<arrow::record_batch::RecordBatch> {
+    let ty = &schema.record.fields[0].ty;
+    let arrow_schema = Arc::new(schema.to_arrow());
+    let mut decoder =
+        arrow::json::reader::ReaderBuilder::new(arrow_schema.clone()).build_decoder()?;
+    for (position, record) in xml_stream::records(source).enumerate() {
+        let record = record?;
+        let doc = xml_stream::parse_record(&record)?;
+        let el = Parser::new(xml_stream::record_of(&doc)?);
+        if let Some(json) = parse_record_to_json(position, el, ty, on_error, skipped)? {
+            decoder.serialize(&[json])?;
+        }
+    }
+    let batch = decoder
+        .flush()?
+        .unwrap_or_else(|| arrow::record_batch::RecordBatch::new_empty(arrow_schema.clone()));
+    Ok(batch)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow::array::TimestampSecondArray;
+
+    #[test]
+    fn test_parse_dates_as_epoch_seconds() {
+        let schema = Schema {
+            record: Record {
+                fields: vec![Field {
+                    name: "order".to_string(),
+                    ty: Type::Record(Record {
+                        fields: vec![Field {
+                            name: "date_add".to_string(),
+                            ty: Type::Date,
+                        }],
+                    }),
+                }],
+            },
+            read_only: vec![],
+        };
+        let source = r#"<prestashop><orders>
+            <order><date_add>2020-01-02 03:04:05</date_add></order>
+            <order><date_add>2020-01-02</date_add></order>
+            <order><date_add>0000-00-00 00:00:00</date_add></order>
+            <order><date_add/></order>
+        </orders></prestashop>"#;
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let batch = parse_data_to_arrow(
+            Parser::new(doc.root_element()),
+            &schema,
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let order = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<arrow::array::StructArray>()
+            .unwrap();
+        let dates = order
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampSecondArray>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(dates, vec![Some(1577934245), Some(1577923200), None, None]);
+        assert!(parse_date(Some("02/01/2020")).is_err());
+
+        let streamed =
+            parse_data_to_arrow_streaming(source, &schema, OnError::Fail, &mut Skipped::default())
+                .unwrap();
+        assert_eq!(streamed, batch);
+    }
+
+    #[test]
+    fn test_string_like_formats() {
+        for format in [
+            "isColor",
+            "isEmail",
+            "isUrl",
+            "isName",
+            "isModuleName",
+            "isCleanHtml",
+            "isReductionType",
+            "isPriceDisplayMethod",
+            "isStockManagement",
+        ] {
+            let format = Format::from_string(format.to_string()).unwrap();
+            assert!(matches!(Type::from_format(&format), Ok(Type::Utf8)));
+        }
+        let format = Format::from_string("isUnsignedInt".to_string()).unwrap();
+        assert!(matches!(Type::from_format(&format), Ok(Type::UInt32)));
+    }
+
+    #[test]
+    fn test_serialized_fields_are_strings() {
+        let source = r#"<prestashop><cart_rule>
+            <conditions format="isJson"></conditions>
+            <restrictions format="isSerializedArray"></restrictions>
+        </cart_rule></prestashop>"#;
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let schema = parse_schema(Parser::new(doc.root_element())).unwrap();
+        let Type::Record(record) = &schema.record.fields[0].ty else {
+            panic!("expected a record");
+        };
+        let types = record
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), format!("{:?}", f.ty)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                ("id", "UInt32".to_string()),
+                ("conditions", "Utf8".to_string()),
+                ("restrictions", "Utf8".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cast_field() {
+        let schema = r#"<prestashop><product>
+            <reference format="isReference"></reference>
+        </product></prestashop>"#;
+        let doc = roxmltree::Document::parse(schema).unwrap();
+        let mut schema = parse_schema(Parser::new(doc.root_element())).unwrap();
+        schema
+            .cast_field("reference", Type::from_cast_name("uint64").unwrap())
+            .unwrap();
+        schema.cast_field("id", Type::Utf8).unwrap();
+        assert!(schema.cast_field("nmae", Type::Utf8).is_err());
+        let source = r#"<prestashop><products>
+            <product><id>1</id><reference>0042</reference></product>
+        </products></prestashop>"#;
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let json = parse_data_to_jsonl(
+            Parser::new(doc.root_element()),
+            &schema,
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            json,
+            vec![serde_json::json!({"product": {"id": "1", "reference": 42}})]
+        );
+    }
+
+    #[test]
+    fn test_parse_read_only_fields() {
+        let schema = r#"<prestashop><product>
+            <manufacturer_name format="isCatalogName" read_only="true"></manufacturer_name>
+            <quantity format="isInt" read_only="true"></quantity>
+            <price format="isPrice"></price>
+        </product></prestashop>"#;
+        let doc = roxmltree::Document::parse(schema).unwrap();
+        let schema = parse_schema(Parser::new(doc.root_element())).unwrap();
+        assert_eq!(schema.read_only_fields(), ["manufacturer_name", "quantity"]);
+    }
+
+    #[test]
+    fn test_null_unparseable_values() {
+        let schema = r#"<prestashop><product>
+            <quantity format="isInt"></quantity>
+        </product></prestashop>"#;
+        let doc = roxmltree::Document::parse(schema).unwrap();
+        let schema = parse_schema(Parser::new(doc.root_element())).unwrap();
+        let source = r#"<prestashop><products>
+            <product><id>1</id><quantity>x</quantity></product>
+        </products></prestashop>"#;
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let p = Parser::new(doc.root_element());
+        assert!(
+            parse_data_to_jsonl(p.clone(), &schema, OnError::Fail, &mut Skipped::default())
+                .is_err()
+        );
+        let json =
+            parse_data_to_jsonl(p, &schema, OnError::NullValue, &mut Skipped::default()).unwrap();
+        assert_eq!(json, vec![serde_json::json!({"product": {"id": 1}})]);
+    }
+
+    #[test]
+    fn test_select_subfields() {
+        let schema = r#"<prestashop><product>
+            <reference format="isReference"></reference>
+            <associations>
+                <categories nodeType="category" api="categories">
+                    <category><id></id></category>
+                </categories>
+                <images nodeType="image" api="images">
+                    <image><id></id><position></position></image>
+                </images>
+            </associations>
+        </product></prestashop>"#;
+        let doc = roxmltree::Document::parse(schema).unwrap();
+        let schema = parse_schema(Parser::new(doc.root_element())).unwrap();
+        let select = |paths: &[&str]| {
+            let mut schema = schema.clone();
+            let paths = paths.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            schema.select_subfields(&paths).map(|_| schema)
+        };
+        let selected = select(&["reference", "associations.images.id"]).unwrap();
+        let source = r#"<prestashop><products><product>
+            <id>1</id><reference>a</reference>
+            <associations>
+                <categories><category><id>2</id></category></categories>
+                <images><image><id>3</id><position>1</position></image></images>
+            </associations>
+        </product></products></prestashop>"#;
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let json = parse_data_to_jsonl(
+            Parser::new(doc.root_element()),
+            &selected,
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            json,
+            vec![serde_json::json!({"product": {
+                "id": 1,
+                "reference": "a",
+                "associations": {"images": [{"image": {"id": 3}}]}
+            }})]
+        );
+        // selecting a whole field keeps all of its subfields
+        assert!(select(&["associations.images", "associations"]).is_ok());
+        let err = select(&["associations.imgs"]).unwrap_err();
+        assert_eq!(err.to_string(), "unknown field \"associations.imgs\"");
+        assert!(select(&["reference.x"]).is_err());
+    }
+
+    #[test]
+    fn test_truncate_json_max_depth() {
+        let json = serde_json::json!({"product": {
+            "id": 1,
+            "name": [{"id": 1, "language": "a"}],
+            "associations": {"images": [{"image": {"id": 3}}]}
+        }});
+        assert_eq!(
+            truncate_json_max_depth(json.clone(), 1),
+            serde_json::json!({"product": "..."})
+        );
+        assert_eq!(
+            truncate_json_max_depth(json.clone(), 2),
+            serde_json::json!({"product": {"id": 1, "name": "...", "associations": "..."}})
+        );
+        assert_eq!(
+            truncate_json_max_depth(json.clone(), 3),
+            serde_json::json!({"product": {
+                "id": 1,
+                "name": [{"id": 1, "language": "a"}],
+                "associations": {"images": "..."}
+            }})
+        );
+        assert_eq!(truncate_json_max_depth(json.clone(), 10), json);
+    }
+
+    #[test]
+    fn test_unknown_formats() {
+        let schema = r#"<prestashop><customer>
+            <birthday format="isBirthDate"></birthday>
+            <id_thing format="isSomethingNew"></id_thing>
+        </customer></prestashop>"#;
+        let doc = roxmltree::Document::parse(schema).unwrap();
+        let parsed = parse_schema(Parser::new(doc.root_element())).unwrap();
+        let arrow = parsed.to_arrow();
+        let customer = match arrow.field(0).data_type() {
+            DataType::Struct(fields) => fields.clone(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(customer[1].data_type(), &DataType::Utf8);
+        assert_eq!(customer[2].data_type(), &DataType::Utf8);
+        let err = parse_schema_with(Parser::new(doc.root_element()), true, true).unwrap_err();
+        assert!(format!("{:#}", err).contains("birthday"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_without_id_field() {
+        let schema = r#"<prestashop><configuration>
+            <value></value>
+        </configuration></prestashop>"#;
+        let doc = roxmltree::Document::parse(schema).unwrap();
+        let schema = parse_schema(Parser::new(doc.root_element())).unwrap();
+        assert_eq!(schema.field_names(), vec!["id", "value"]);
+        let schema = parse_schema_with(Parser::new(doc.root_element()), false, false).unwrap();
+        assert_eq!(schema.field_names(), vec!["value"]);
+        // an id described by the synopsis itself is kept
+        let schema = r#"<prestashop><configuration>
+            <id></id>
+            <value></value>
+        </configuration></prestashop>"#;
+        let doc = roxmltree::Document::parse(schema).unwrap();
+        let schema = parse_schema_with(Parser::new(doc.root_element()), false, false).unwrap();
+        assert_eq!(schema.field_names(), vec!["id", "value"]);
+    }
+
+    #[test]
+    fn test_null_or_unsigned_id() {
+        let schema = r#"<prestashop><category>
+            <id_parent format="isNullOrUnsignedId"></id_parent>
+        </category></prestashop>"#;
+        let doc = roxmltree::Document::parse(schema).unwrap();
+        let schema = parse_schema(Parser::new(doc.root_element())).unwrap();
+        let source = r#"<prestashop><categories>
+            <category><id>1</id><id_parent></id_parent></category>
+            <category><id>2</id><id_parent>1</id_parent></category>
+        </categories></prestashop>"#;
+        let doc = roxmltree::Document::parse(source).unwrap();
+        let batch = parse_data_to_arrow(
+            Parser::new(doc.root_element()),
+            &schema,
+            OnError::Fail,
+            &mut Skipped::default(),
+        )
+        .unwrap();
+        let category = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<arrow::array::StructArray>()
+            .unwrap();
+        let parents = category
+            .column_by_name("id_parent")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<arrow::array::UInt32Array>()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(parents, vec![None, Some(1)]);
+    }
+
+    #[test]
+    fn test_type_from_name() {
+        for name in ["valid", "guid", "paid", "width"] {
+            assert!(Type::from_name(name).is_none(), "{}", name);
+        }
+        for name in ["id", "id_customer", "manufacturer_id", "id_default_image"] {
+            assert!(
+                matches!(Type::from_name(name), Some(Type::UInt32)),
+                "{}",
+                name
+            );
+        }
+    }
+}
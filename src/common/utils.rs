@@ -1,8 +1,14 @@
+use anyhow::{anyhow, Result};
+use std::borrow::Cow;
 
+/// Logs to stderr at `level`, unless RUST_LOG is set, which takes precedence
 pub fn setup_tracing(level: tracing_subscriber::filter::LevelFilter) {
     let t = tracing_subscriber::fmt::time::Uptime::default();
+    let filter = tracing_subscriber::EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env_lossy();
     let fmt = tracing_subscriber::fmt()
-        .with_max_level(level)
+        .with_env_filter(filter)
         .with_writer(std::io::stderr)
         .with_ansi(false)
         .with_timer(t)
@@ -11,3 +17,131 @@ pub fn setup_tracing(level: tracing_subscriber::filter::LevelFilter) {
         .finish();
     tracing::subscriber::set_global_default(fmt).unwrap();
 }
+
+/// Text of `node`, joining all of its text and CDATA segments, unlike
+/// `Node::text` which stops at the first child element or comment. `None`
+/// when the element has no text at all
+pub fn node_text<'a>(node: &roxmltree::Node<'a, '_>) -> Option<Cow<'a, str>> {
+    let mut texts = node
+        .children()
+        .filter(|c| c.is_text())
+        .filter_map(|c| c.text());
+    let first = texts.next()?;
+    match texts.next() {
+        None => Some(Cow::Borrowed(first)),
+        Some(second) => {
+            let mut text = format!("{}{}", first, second);
+            texts.for_each(|t| text.push_str(t));
+            Some(Cow::Owned(text))
+        }
+    }
+}
+
+/// Parses a boolean as PrestaShop or its modules write them: `1`/`0`,
+/// `true`/`false` or `yes`/`no` in any case. Empty text is `None`
+pub fn parse_bool(s: &str) -> Result<Option<bool>> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    match s.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Ok(Some(true)),
+        "0" | "false" | "no" => Ok(Some(false)),
+        _ => Err(anyhow!("invalid bool value {:?}", s)),
+    }
+}
+
+/// Whether a field is named like a foreign or primary key: `id` itself, or
+/// `id_` / `_id` as a separate word, e.g. `id_customer` but not `valid` or `guid`
+pub fn is_id_name(name: &str) -> bool {
+    name.split('_').any(|word| word == "id")
+}
+
+/// Checks that every requested field is one of `valid`, listing the valid
+/// names otherwise
+pub fn check_field_names(requested: &[String], valid: &[&str]) -> Result<()> {
+    let unknown = requested
+        .iter()
+        .filter(|name| !valid.contains(&name.as_str()))
+        .map(|name| format!("{:?}", name))
+        .collect::<Vec<_>>();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "unknown field(s) {}, valid fields are: {}",
+        unknown.join(", "),
+        valid.join(", ")
+    ))
+}
+
+/// The names of `valid` that are not `excluded`, erroring on unknown excluded names
+pub fn exclude_field_names(excluded: &[String], valid: &[&str]) -> Result<Vec<String>> {
+    check_field_names(excluded, valid)?;
+    Ok(valid
+        .iter()
+        .filter(|name| !excluded.iter().any(|e| e == *name))
+        .map(|name| name.to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_node_text() {
+        let text = |xml: &str| {
+            let doc = roxmltree::Document::parse(xml).unwrap();
+            node_text(&doc.root_element()).map(|t| t.into_owned())
+        };
+        assert_eq!(
+            text("<d><![CDATA[<p>hi &amp; bye</p>]]></d>").as_deref(),
+            Some("<p>hi &amp; bye</p>")
+        );
+        assert_eq!(
+            text("<d>a &amp; <![CDATA[ b ]]>\n c</d>").as_deref(),
+            Some("a &  b \n c")
+        );
+        assert_eq!(
+            text("<d>before<!-- comment -->after<br/> end</d>").as_deref(),
+            Some("beforeafter end")
+        );
+        assert_eq!(text("<d></d>"), None);
+    }
+
+    #[test]
+    fn test_parse_bool() {
+        for s in ["1", "true", "TRUE", "True", "yes", "Yes", " 1 "] {
+            assert_eq!(parse_bool(s).unwrap(), Some(true), "{:?}", s);
+        }
+        for s in ["0", "false", "FALSE", "no", "NO"] {
+            assert_eq!(parse_bool(s).unwrap(), Some(false), "{:?}", s);
+        }
+        assert_eq!(parse_bool("").unwrap(), None);
+        assert_eq!(parse_bool("  ").unwrap(), None);
+        assert!(parse_bool("2").is_err());
+        assert!(parse_bool("on").is_err());
+    }
+
+    #[test]
+    fn test_exclude_field_names() {
+        let valid = ["id", "name", "description", "price"];
+        assert_eq!(
+            exclude_field_names(&["description".to_string()], &valid).unwrap(),
+            vec!["id", "name", "price"]
+        );
+        assert!(exclude_field_names(&["descr".to_string()], &valid).is_err());
+    }
+
+    #[test]
+    fn test_check_field_names() {
+        let valid = ["id", "name", "price"];
+        assert!(check_field_names(&["id".to_string(), "price".to_string()], &valid).is_ok());
+        let e = check_field_names(&["id".to_string(), "nmae".to_string()], &valid).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "unknown field(s) \"nmae\", valid fields are: id, name, price"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Splits a list response like `<prestashop><products><product>..</product>..</products></prestashop>`
+//! into its records without building a DOM of the whole response.
+//!
+//! Each record is yielded as a small standalone document, so the existing
+//! roxmltree based parsers can be reused one record at a time.
+//!
+//! Unlike [crate::parser::Parser::records_container], the records are not
+//! searched for: a response whose root has more than one child, or whose
+//! container has differently named children, is an error naming the element.
+
+use anyhow::{anyhow, Context, Result};
+use xmlparser::{ElementEnd, Token, Tokenizer};
+
+/// Depth of the record elements: inside the root and the container
+const RECORD_DEPTH: usize = 2;
+
+/// Iterator over the records of a list response, see [`records`]
+pub struct Records<'a> {
+    source: &'a str,
+    tokenizer: Tokenizer<'a>,
+    depth: usize,
+    record_start: Option<usize>,
+    namespaces: String,
+    container: Option<&'a str>,
+    record_name: Option<&'a str>,
+    done: bool,
+}
+
+/// Iterates over the record elements of `source`, each wrapped in an element
+/// that repeats the namespace declarations of the root, e.g. `xmlns:xlink`,
+/// so that prefixed attributes keep resolving
+pub fn records(source: &str) -> Records<'_> {
+    Records {
+        source,
+        tokenizer: Tokenizer::from(source),
+        depth: 0,
+        record_start: None,
+        namespaces: String::new(),
+        container: None,
+        record_name: None,
+        done: false,
+    }
+}
+
+impl<'a> Records<'a> {
+    fn wrap(&self, start: usize, end: usize) -> String {
+        format!(
+            "<record{}>{}</record>",
+            self.namespaces,
+            &self.source[start..end]
+        )
+    }
+
+    /// Checks the layout that [records] expects, with errors shaped like those
+    /// of [crate::parser::Parser], e.g. `["products"]: there are elements ..`
+    fn check_element(&mut self, local: &'a str) -> Result<()> {
+        let error = |e: anyhow::Error, path: Vec<&str>| {
+            Err(e)
+                .context(format!("{:?}", path))
+                .context("cannot find the element holding the records")
+        };
+        if self.depth == RECORD_DEPTH - 1 {
+            if let Some(container) = self.container {
+                let names = vec![container, local];
+                return error(anyhow!("expected single child, found {:?}", names), vec![]);
+            }
+            self.container = Some(local);
+        } else if self.depth == RECORD_DEPTH {
+            match self.record_name {
+                Some(name) if name != local => {
+                    let e = anyhow!(
+                        "there are elements named '{local}' and '{name}' where single name is expected"
+                    );
+                    return error(e, self.container.into_iter().collect());
+                }
+                _ => self.record_name = Some(local),
+            }
+        }
+        Ok(())
+    }
+
+    fn next_record(&mut self) -> Result<Option<String>> {
+        while let Some(token) = self.tokenizer.next() {
+            match token? {
+                Token::ElementStart { local, span, .. } => {
+                    self.check_element(local.as_str())?;
+                    if self.depth == RECORD_DEPTH {
+                        self.record_start = Some(span.start());
+                    }
+                }
+                Token::Attribute {
+                    prefix,
+                    local,
+                    span,
+                    ..
+                } if self.depth < RECORD_DEPTH
+                    && (prefix.as_str() == "xmlns"
+                        || prefix.is_empty() && local.as_str() == "xmlns") =>
+                {
+                    self.namespaces.push(' ');
+                    self.namespaces.push_str(span.as_str());
+                }
+                Token::ElementEnd { end, span } => match end {
+                    ElementEnd::Open => self.depth += 1,
+                    ElementEnd::Close(_, _) => {
+                        self.depth = self
+                            .depth
+                            .checked_sub(1)
+                            .ok_or_else(|| anyhow!("unbalanced closing tag"))?;
+                        if self.depth == RECORD_DEPTH {
+                            if let Some(start) = self.record_start.take() {
+                                return Ok(Some(self.wrap(start, span.end())));
+                            }
+                        }
+                    }
+                    ElementEnd::Empty => {
+                        if self.depth == RECORD_DEPTH {
+                            if let Some(start) = self.record_start.take() {
+                                return Ok(Some(self.wrap(start, span.end())));
+                            }
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+        if self.container.is_none() {
+            return Err(anyhow!("no elements in root"));
+        }
+        if self.depth != 0 {
+            return Err(anyhow!("unexpected end of response"));
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_record().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// Parses a record yielded by [`records`], returning the document whose root
+/// element wraps the record, see [`record_of`]
+pub fn parse_record(record: &str) -> Result<roxmltree::Document<'_>> {
+    Ok(roxmltree::Document::parse(record)?)
+}
+
+/// The record element of a document returned by [`parse_record`]
+pub fn record_of<'a>(doc: &'a roxmltree::Document<'a>) -> Result<roxmltree::Node<'a, 'a>> {
+    doc.root_element()
+        .first_element_child()
+        .ok_or_else(|| anyhow!("empty record"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_records() {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <prestashop xmlns:xlink="http://www.w3.org/1999/xlink">
+            <products>
+                <product id="1" xlink:href="https://shop/api/products/1"/>
+                <product><id><![CDATA[2]]></id><name><language id="1">a &amp; b</language></name></product>
+            </products>
+        </prestashop>"#;
+        let records = records(source).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(records.len(), 2);
+
+        let doc = parse_record(&records[0]).unwrap();
+        let product = record_of(&doc).unwrap();
+        assert_eq!(product.attribute("id"), Some("1"));
+        assert_eq!(
+            product.attribute(("http://www.w3.org/1999/xlink", "href")),
+            Some("https://shop/api/products/1")
+        );
+
+        let doc = parse_record(&records[1]).unwrap();
+        let product = record_of(&doc).unwrap();
+        let texts = product
+            .descendants()
+            .filter(|n| n.is_element())
+            .filter_map(|n| n.text())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec!["2", "a & b"]);
+    }
+
+    #[test]
+    fn test_empty_and_invalid_responses() {
+        let empty = "<prestashop><products/></prestashop>";
+        assert_eq!(records(empty).count(), 0);
+        assert!(records("<prestashop/>").next().unwrap().is_err());
+        let truncated = "<prestashop><products><product><id>1</id></product><product><id>";
+        let results = records(truncated).collect::<Vec<_>>();
+        assert!(results[0].is_ok());
+        assert!(results.last().unwrap().is_err());
+    }
+}